@@ -1,6 +1,7 @@
 use criterion::{black_box, criterion_group, criterion_main, Criterion};
 
-use finfoot::ode::dopri5;
+use finfoot::ode::{batch, dopri5};
+use nalgebra::{dvector, DVector};
 use test_util::all_problems;
 
 macro_rules! generate_ode_benchmarks {
@@ -38,4 +39,62 @@ generate_ode_benchmarks! {
     coupled_oscillators,
 }
 
-criterion_main!(ode_benches);
+// Parameter sweep over van der Pol mu, one system at a time and eight lanes at a time.
+const SWEEP_SIZE: usize = 64;
+const SWEEP_CONFIG: dopri5::Config = dopri5::Config {
+    rel_tol: 1e-4,
+    abs_tol: 1e-6,
+};
+
+#[allow(clippy::cast_precision_loss)]
+fn sweep_mu() -> Vec<f64> {
+    (0..SWEEP_SIZE)
+        .map(|i| 1.0 + 9.0 * i as f64 / SWEEP_SIZE as f64)
+        .collect()
+}
+
+fn van_der_pol_sweep(c: &mut Criterion) {
+    let mu = sweep_mu();
+    let y0 = dvector![1.0, 0.0];
+
+    c.bench_function("van_der_pol_sweep", |b| {
+        b.iter(|| {
+            for &mu in &mu {
+                let f = move |_, y: &DVector<f64>| {
+                    dvector![y[1], mu * (1.0 - y[0].powi(2)) * y[1] - y[0]]
+                };
+                let input = dopri5::Input {
+                    t_span: [0.0, 15.0],
+                    y0: &y0,
+                    h0: 0.15,
+                    f: &f,
+                };
+                black_box(dopri5::integrate(black_box(&input), &SWEEP_CONFIG).unwrap());
+            }
+        });
+    });
+
+    let y0 = [[1.0; 8], [0.0; 8]];
+    c.bench_function("van_der_pol_sweep_batched", |b| {
+        b.iter(|| {
+            for mu in mu.chunks_exact(8) {
+                let f = |_: &[f64; 8], y: &[[f64; 8]], dydt: &mut [[f64; 8]]| {
+                    for l in 0..8 {
+                        dydt[0][l] = y[1][l];
+                        dydt[1][l] = mu[l] * (1.0 - y[0][l] * y[0][l]) * y[1][l] - y[0][l];
+                    }
+                };
+                let input = batch::Input {
+                    t_span: [0.0, 15.0],
+                    y0: &y0,
+                    h0: 0.15,
+                    f: &f,
+                };
+                black_box(batch::integrate(black_box(&input), &SWEEP_CONFIG).unwrap());
+            }
+        });
+    });
+}
+
+criterion_group!(sweep_benches, van_der_pol_sweep);
+criterion_main!(ode_benches, sweep_benches);
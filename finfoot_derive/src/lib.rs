@@ -0,0 +1,418 @@
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::{format_ident, quote};
+use syn::{parse_macro_input, Data, DeriveInput, Expr, Fields, Type};
+
+enum Kind {
+    Scalar,
+    Array(Expr),
+    Vector,
+    Nested(Type),
+}
+
+#[derive(Default)]
+struct Metadata {
+    unit: Option<syn::Path>,
+    description: Option<syn::LitStr>,
+    names: Vec<syn::LitStr>,
+}
+
+struct Field {
+    ident: syn::Ident,
+    vis: syn::Visibility,
+    kind: Kind,
+    metadata: Metadata,
+}
+
+/// Field attributes:
+///
+/// ```text
+/// #[state(unit = uom::si::velocity::meter_per_second)]
+/// #[state(description = "Velocity in the inertial frame")]
+/// #[state(names = ["x", "y", "z"])]
+/// ```
+#[proc_macro_derive(StateVector, attributes(state))]
+pub fn derive_state_vector(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand(&input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+fn is_f64(ty: &Type) -> bool {
+    matches!(ty, Type::Path(path) if path.qself.is_none() && path.path.is_ident("f64"))
+}
+
+// Primitive types that look like a named child state but cannot be stored as f64.
+const UNSUPPORTED_LEAVES: [&str; 15] = [
+    "f32", "i8", "i16", "i32", "i64", "i128", "isize", "u8", "u16", "u32", "u64", "u128", "usize",
+    "bool", "char",
+];
+
+fn unsupported(ty: &Type) -> syn::Error {
+    syn::Error::new_spanned(
+        ty,
+        "StateVector fields must be f64, [f64; N], Vec<f64> or a StateVector",
+    )
+}
+
+fn field_kind(ty: &Type) -> syn::Result<Kind> {
+    if is_f64(ty) {
+        return Ok(Kind::Scalar);
+    }
+
+    if let Type::Array(array) = ty {
+        if is_f64(&array.elem) {
+            return Ok(Kind::Array(array.len.clone()));
+        }
+    }
+
+    if let Type::Path(path) = ty {
+        let Some(segment) = path.path.segments.last() else {
+            return Err(unsupported(ty));
+        };
+        if segment.ident == "Vec" {
+            if let syn::PathArguments::AngleBracketed(args) = &segment.arguments {
+                if let [syn::GenericArgument::Type(elem)] = args.args.iter().collect::<Vec<_>>()[..]
+                {
+                    if is_f64(elem) {
+                        return Ok(Kind::Vector);
+                    }
+                }
+            }
+            return Err(unsupported(ty));
+        }
+        if path.qself.is_none()
+            && path.path.segments.len() == 1
+            && UNSUPPORTED_LEAVES.iter().any(|leaf| segment.ident == leaf)
+        {
+            return Err(unsupported(ty));
+        }
+
+        // Any other named type is a child state laid out in place.
+        return Ok(Kind::Nested(ty.clone()));
+    }
+
+    Err(unsupported(ty))
+}
+
+fn parse_metadata(attrs: &[syn::Attribute]) -> syn::Result<Metadata> {
+    let mut metadata = Metadata::default();
+    for attr in attrs.iter().filter(|attr| attr.path().is_ident("state")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("unit") {
+                metadata.unit = Some(meta.value()?.parse()?);
+            } else if meta.path.is_ident("description") {
+                metadata.description = Some(meta.value()?.parse()?);
+            } else if meta.path.is_ident("names") {
+                let names: syn::ExprArray = meta.value()?.parse()?;
+                for name in names.elems {
+                    match name {
+                        Expr::Lit(syn::ExprLit {
+                            lit: syn::Lit::Str(name),
+                            ..
+                        }) => metadata.names.push(name),
+                        name => return Err(syn::Error::new_spanned(name, "expected a string")),
+                    }
+                }
+            } else {
+                return Err(meta.error("expected `unit`, `description` or `names`"));
+            }
+            Ok(())
+        })?;
+    }
+    Ok(metadata)
+}
+
+fn parse_fields(input: &DeriveInput) -> syn::Result<Vec<Field>> {
+    let Data::Struct(data) = &input.data else {
+        return Err(syn::Error::new_spanned(
+            &input.ident,
+            "StateVector can only be derived for structs",
+        ));
+    };
+    let Fields::Named(fields) = &data.fields else {
+        return Err(syn::Error::new_spanned(
+            &input.ident,
+            "StateVector requires named fields",
+        ));
+    };
+    if !input.generics.params.is_empty() {
+        return Err(syn::Error::new_spanned(
+            &input.generics,
+            "StateVector cannot be derived for generic structs",
+        ));
+    }
+
+    fields
+        .named
+        .iter()
+        .map(|field| {
+            let kind = field_kind(&field.ty)?;
+            let metadata = parse_metadata(&field.attrs)?;
+            check_names(&kind, &metadata.names)?;
+            Ok(Field {
+                ident: field.ident.clone().expect("named field"),
+                vis: field.vis.clone(),
+                kind,
+                metadata,
+            })
+        })
+        .collect()
+}
+
+// Arrays of literal length need one name per element. Lengths given by constants are checked by
+// an assertion in the generated code instead.
+fn check_names(kind: &Kind, names: &[syn::LitStr]) -> syn::Result<()> {
+    let (
+        Kind::Array(Expr::Lit(syn::ExprLit {
+            lit: syn::Lit::Int(len),
+            ..
+        })),
+        Some(first),
+    ) = (kind, names.first())
+    else {
+        return Ok(());
+    };
+    let len: usize = len.base10_parse()?;
+    if names.len() == len {
+        return Ok(());
+    }
+    Err(syn::Error::new_spanned(
+        first,
+        format!(
+            "expected {len} names, one per array element, found {}",
+            names.len()
+        ),
+    ))
+}
+
+#[allow(clippy::too_many_lines)]
+fn expand(input: &DeriveInput) -> syn::Result<TokenStream2> {
+    let fields = parse_fields(input)?;
+
+    let name = &input.ident;
+    let vis = &input.vis;
+    let view_name = format_ident!("{}View", name);
+    let view_mut_name = format_ident!("{}ViewMut", name);
+    let contig = quote!(::finfoot::contig);
+
+    let idents: Vec<_> = fields.iter().map(|f| &f.ident).collect();
+    let vises: Vec<_> = fields.iter().map(|f| &f.vis).collect();
+
+    let view_types = fields.iter().map(|f| match &f.kind {
+        Kind::Scalar => quote!(&'a f64),
+        Kind::Array(len) => quote!(&'a [f64; #len]),
+        Kind::Vector => quote!(&'a [f64]),
+        Kind::Nested(ty) => quote!(<#ty as #contig::StateVector>::View<'a>),
+    });
+    let view_mut_types = fields.iter().map(|f| match &f.kind {
+        Kind::Scalar => quote!(&'a mut f64),
+        Kind::Array(len) => quote!(&'a mut [f64; #len]),
+        Kind::Vector => quote!(&'a mut [f64]),
+        Kind::Nested(ty) => quote!(<#ty as #contig::StateVector>::ViewMut<'a>),
+    });
+    let field_layouts = fields.iter().map(|f| {
+        let ident = &f.ident;
+        match &f.kind {
+            Kind::Scalar => quote!(#contig::FieldLayout::Scalar),
+            Kind::Array(len) => quote!(#contig::FieldLayout::Leaf(#len)),
+            Kind::Vector => quote!(#contig::FieldLayout::Leaf(self.#ident.len())),
+            Kind::Nested(_) => quote!(#contig::FieldLayout::Nested(
+                #contig::StateVector::layout(&self.#ident)
+            )),
+        }
+    });
+    let metadata = fields.iter().map(|f| {
+        let name = f.ident.to_string();
+        let none = quote!(::std::option::Option::None);
+        let unit = f.metadata.unit.as_ref().map_or_else(
+            || none.clone(),
+            |unit| quote!(::std::option::Option::Some(#contig::unit_abbreviation::<#unit>())),
+        );
+        let description = f.metadata.description.as_ref().map_or_else(
+            || none.clone(),
+            |description| quote!(::std::option::Option::Some(#description)),
+        );
+        let names = &f.metadata.names;
+        quote!(#contig::FieldMetadata {
+            name: #name,
+            unit: #unit,
+            description: #description,
+            element_names: &[#(#names),*],
+        })
+    });
+    let lens = fields.iter().map(|f| {
+        let ident = &f.ident;
+        match &f.kind {
+            Kind::Scalar => quote!(1),
+            Kind::Array(_) | Kind::Vector => quote!(self.#ident.len()),
+            Kind::Nested(_) => quote!(#contig::StateVector::len(&self.#ident)),
+        }
+    });
+    // Copies field by field, so writing a nested state does not rebuild its layout.
+    let writes = fields.iter().map(|f| {
+        let ident = &f.ident;
+        match &f.kind {
+            Kind::Scalar => quote!(#contig::take_mut(data, &mut offset, 1)[0] = self.#ident;),
+            Kind::Array(_) | Kind::Vector => quote!(
+                #contig::take_mut(data, &mut offset, self.#ident.len()).copy_from_slice(&self.#ident);
+            ),
+            Kind::Nested(_) => quote!(#contig::StateVector::write(
+                &self.#ident,
+                #contig::take_mut(data, &mut offset, #contig::StateVector::len(&self.#ident)),
+            );),
+        }
+    });
+    let reads = fields.iter().enumerate().map(|(i, f)| {
+        let ident = &f.ident;
+        match &f.kind {
+            Kind::Scalar => quote!(#ident: fields.next().unwrap()[0]),
+            Kind::Array(_) => quote!(#ident: *#contig::array(fields.next().unwrap())),
+            Kind::Vector => quote!(#ident: fields.next().unwrap().to_vec()),
+            Kind::Nested(ty) => quote!(#ident: <#ty as #contig::StateVector>::read(
+                layout.child(#i),
+                fields.next().unwrap(),
+            )),
+        }
+    });
+    let view_fields = fields.iter().enumerate().map(|(i, f)| {
+        let ident = &f.ident;
+        match &f.kind {
+            Kind::Scalar => quote!(#ident: &fields.next().unwrap()[0]),
+            Kind::Array(_) => quote!(#ident: #contig::array(fields.next().unwrap())),
+            Kind::Vector => quote!(#ident: fields.next().unwrap()),
+            Kind::Nested(ty) => quote!(#ident: <#ty as #contig::StateVector>::view(
+                layout.child(#i),
+                fields.next().unwrap(),
+            )),
+        }
+    });
+    let view_mut_fields = fields.iter().enumerate().map(|(i, f)| {
+        let ident = &f.ident;
+        match &f.kind {
+            Kind::Scalar => quote!(#ident: #contig::scalar_mut(fields.next().unwrap())),
+            Kind::Array(_) => quote!(#ident: #contig::array_mut(fields.next().unwrap())),
+            Kind::Vector => quote!(#ident: fields.next().unwrap()),
+            Kind::Nested(ty) => quote!(#ident: <#ty as #contig::StateVector>::view_mut(
+                layout.child(#i),
+                fields.next().unwrap(),
+            )),
+        }
+    });
+
+    let name_checks = fields.iter().filter_map(|f| match &f.kind {
+        Kind::Array(len) if !f.metadata.names.is_empty() => {
+            let count = f.metadata.names.len();
+            let message = format!("`{}` needs one name per array element", f.ident);
+            Some(quote!(
+                const _: () = ::std::assert!((#len) == #count, #message);
+            ))
+        }
+        _ => None,
+    });
+
+    Ok(quote! {
+        #(#name_checks)*
+
+        #vis struct #view_name<'a> {
+            #(#vises #idents: #view_types,)*
+        }
+
+        #vis struct #view_mut_name<'a> {
+            #(#vises #idents: #view_mut_types,)*
+        }
+
+        impl #contig::StateVector for #name {
+            type View<'a> = #view_name<'a>;
+            type ViewMut<'a> = #view_mut_name<'a>;
+
+            fn layout(&self) -> #contig::Layout {
+                #contig::Layout::from_fields(::std::vec![#(#field_layouts),*])
+                    .with_metadata(::std::vec![#(#metadata),*])
+            }
+
+            fn len(&self) -> usize {
+                0 #(+ #lens)*
+            }
+
+            fn write(&self, data: &mut [f64]) {
+                ::std::assert_eq!(
+                    data.len(),
+                    #contig::StateVector::len(self),
+                    "data does not have the length of the state",
+                );
+                let mut offset = 0;
+                #(#writes)*
+            }
+
+            fn read(layout: &#contig::Layout, data: &[f64]) -> Self {
+                let mut fields = layout.split(data).into_iter();
+                Self {
+                    #(#reads,)*
+                }
+            }
+
+            fn view<'a>(layout: &#contig::Layout, data: &'a [f64]) -> #view_name<'a> {
+                let mut fields = layout.split(data).into_iter();
+                #view_name {
+                    #(#view_fields,)*
+                }
+            }
+
+            fn view_mut<'a>(layout: &#contig::Layout, data: &'a mut [f64]) -> #view_mut_name<'a> {
+                let mut fields = layout.split_mut(data).into_iter();
+                #view_mut_name {
+                    #(#view_mut_fields,)*
+                }
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_field_kind() {
+        assert!(matches!(
+            field_kind(&syn::parse_quote!(f64)),
+            Ok(Kind::Scalar)
+        ));
+        assert!(matches!(
+            field_kind(&syn::parse_quote!([f64; 3])),
+            Ok(Kind::Array(_))
+        ));
+        assert!(matches!(
+            field_kind(&syn::parse_quote!(Vec<f64>)),
+            Ok(Kind::Vector)
+        ));
+        assert!(matches!(
+            field_kind(&syn::parse_quote!(Child)),
+            Ok(Kind::Nested(_))
+        ));
+        for ty in [
+            syn::parse_quote!(f32),
+            syn::parse_quote!(i32),
+            syn::parse_quote!(usize),
+            syn::parse_quote!(Vec<f32>),
+            syn::parse_quote!(Vec<Child>),
+            syn::parse_quote!([f32; 3]),
+            syn::parse_quote!(&'static f64),
+        ] {
+            assert!(field_kind(&ty).is_err());
+        }
+    }
+
+    #[test]
+    fn test_check_names() {
+        let names: Vec<syn::LitStr> = vec![syn::parse_quote!("x"), syn::parse_quote!("y")];
+        let array = |len: Expr| Kind::Array(len);
+        assert!(check_names(&array(syn::parse_quote!(2)), &names).is_ok());
+        assert!(check_names(&array(syn::parse_quote!(3)), &names).is_err());
+        assert!(check_names(&array(syn::parse_quote!(3)), &[]).is_ok());
+        assert!(check_names(&array(syn::parse_quote!(N)), &names).is_ok());
+        assert!(check_names(&Kind::Vector, &names).is_ok());
+    }
+}
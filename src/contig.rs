@@ -1,57 +1,573 @@
-use std::alloc::{alloc, dealloc, Layout};
-use std::ptr::{self, NonNull};
-use std::slice;
-
-#[allow(dead_code)]
-struct Vector<T> {
-    _phantom: std::marker::PhantomData<T>,
-}
-
-#[allow(dead_code)]
-struct ConcreteType {
-    field0: f64,
-    vec_field: Vector<f64>,
-    field1: f64,
-}
-
-#[allow(dead_code)]
-pub struct ReferenceType<'a> {
-    pub field0: &'a mut f64,
-    pub vec_vield: &'a mut [f64],
-    pub field1: &'a mut f64,
-    raw_buffer: NonNull<u8>,
-}
-
-impl ReferenceType<'_> {
-    pub fn new(length: usize) -> Self {
-        let layout = Layout::new::<f64>();
-        let layout = layout
-            .extend(Layout::array::<f64>(length).unwrap())
-            .unwrap()
-            .0;
-        let layout = layout.extend(Layout::new::<f64>()).unwrap().0;
-
-        let raw_buffer;
-        unsafe {
-            raw_buffer = NonNull::new(alloc(layout)).unwrap();
+use std::fmt;
+use std::marker::PhantomData;
+use std::ops::Range;
+
+use nalgebra::DVector;
+
+pub use finfoot_derive::StateVector;
+
+/// A struct of `f64`, `[f64; N]`, `Vec<f64>` and nested `StateVector` fields stored contiguously in
+/// a flat vector, in field declaration order. Implemented with `#[derive(StateVector)]`, which also
+/// generates `<Name>View` and `<Name>ViewMut` structs holding references into the flat storage.
+/// Nested fields appear in these as the child's own view types.
+pub trait StateVector: Sized {
+    type View<'a>;
+    type ViewMut<'a>;
+
+    fn layout(&self) -> Layout;
+    /// Number of f64 in the flat storage, without building the layout.
+    fn len(&self) -> usize;
+    /// # Panics
+    /// If `data` does not have the length of the state.
+    fn write(&self, data: &mut [f64]);
+    fn read(layout: &Layout, data: &[f64]) -> Self;
+    fn view<'a>(layout: &Layout, data: &'a [f64]) -> Self::View<'a>;
+    fn view_mut<'a>(layout: &Layout, data: &'a mut [f64]) -> Self::ViewMut<'a>;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn to_vector(&self) -> DVector<f64> {
+        let mut data = DVector::zeros(self.len());
+        self.write(data.as_mut_slice());
+        data
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum FieldLayout {
+    Scalar,
+    Leaf(usize),
+    Nested(Layout),
+}
+
+impl FieldLayout {
+    #[must_use]
+    pub fn len(&self) -> usize {
+        match self {
+            FieldLayout::Scalar => 1,
+            FieldLayout::Leaf(len) => *len,
+            FieldLayout::Nested(layout) => layout.len(),
+        }
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// Names, units and descriptions of a field, set with `#[state(...)]` attributes when derived.
+/// `element_names` names the elements of array fields, e.g. `["x", "y", "z"]`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct FieldMetadata {
+    pub name: &'static str,
+    pub unit: Option<&'static str>,
+    pub description: Option<&'static str>,
+    pub element_names: &'static [&'static str],
+}
+
+/// A single f64 of the flat storage, e.g. `vehicle.velocity.x [m/s]`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Element {
+    pub name: String,
+    pub unit: Option<&'static str>,
+    pub description: Option<&'static str>,
+}
+
+impl fmt::Display for Element {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.unit {
+            Some(unit) => write!(f, "{} [{unit}]", self.name),
+            None => write!(f, "{}", self.name),
+        }
+    }
+}
+
+/// Describes element `index` of a state, falling back to the bare index without a layout.
+#[must_use]
+pub fn describe_element(layout: Option<&Layout>, index: usize) -> String {
+    match layout {
+        Some(layout) if index < layout.len() => layout.element(index).to_string(),
+        _ => format!("element {index}"),
+    }
+}
+
+#[must_use]
+pub fn unit_abbreviation<U: uom::si::Unit>() -> &'static str {
+    U::abbreviation()
+}
+
+/// Offsets of each field within the flat storage. Runtime length fields make this a property of a
+/// state value rather than of its type.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Layout {
+    offsets: Vec<usize>,
+    fields: Vec<FieldLayout>,
+    metadata: Vec<FieldMetadata>,
+    name: Option<String>,
+}
+
+impl Layout {
+    #[must_use]
+    pub fn new(lengths: &[usize]) -> Self {
+        Self::from_fields(lengths.iter().map(|&len| FieldLayout::Leaf(len)).collect())
+    }
+
+    #[must_use]
+    pub fn from_fields(fields: Vec<FieldLayout>) -> Self {
+        let mut offsets = Vec::with_capacity(fields.len() + 1);
+        offsets.push(0);
+        for field in &fields {
+            offsets.push(offsets[offsets.len() - 1] + field.len());
+        }
+        Self {
+            offsets,
+            fields,
+            metadata: Vec::new(),
+            name: None,
+        }
+    }
+
+    /// # Panics
+    /// If there is not one `FieldMetadata` per field.
+    #[must_use]
+    pub fn with_metadata(mut self, metadata: Vec<FieldMetadata>) -> Self {
+        assert_eq!(
+            metadata.len(),
+            self.num_fields(),
+            "metadata does not match fields"
+        );
+        self.metadata = metadata;
+        self
+    }
+
+    /// Prefix for all element names, e.g. `vehicle`.
+    #[must_use]
+    pub fn with_name(mut self, name: &str) -> Self {
+        self.name = Some(name.to_string());
+        self
+    }
+
+    #[must_use]
+    pub fn metadata(&self, field: usize) -> Option<&FieldMetadata> {
+        self.metadata.get(field)
+    }
+
+    /// # Panics
+    /// If `index` is out of range.
+    #[must_use]
+    pub fn element(&self, index: usize) -> Element {
+        assert!(index < self.len(), "element {index} out of range");
+        self.element_with_prefix(index, self.name.as_deref())
+    }
+
+    fn element_with_prefix(&self, index: usize, prefix: Option<&str>) -> Element {
+        let field = self.offsets.partition_point(|&offset| offset <= index) - 1;
+        let local = index - self.offsets[field];
+
+        let metadata = self.metadata(field);
+        let field_name = metadata.map_or_else(|| field.to_string(), |m| m.name.to_string());
+        let name = match prefix {
+            Some(prefix) => format!("{prefix}.{field_name}"),
+            None => field_name,
+        };
+
+        let name = match (&self.fields[field], metadata) {
+            (FieldLayout::Nested(layout), _) => {
+                return layout.element_with_prefix(local, Some(&name))
+            }
+            (FieldLayout::Scalar, _) => name,
+            (FieldLayout::Leaf(_), Some(m)) if local < m.element_names.len() => {
+                format!("{name}.{}", m.element_names[local])
+            }
+            (FieldLayout::Leaf(_), _) => format!("{name}[{local}]"),
         };
+
+        Element {
+            name,
+            unit: metadata.and_then(|m| m.unit),
+            description: metadata.and_then(|m| m.description),
+        }
+    }
+
+    #[must_use]
+    pub fn elements(&self) -> Vec<Element> {
+        (0..self.len()).map(|index| self.element(index)).collect()
+    }
+
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.offsets[self.offsets.len() - 1]
     }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    #[must_use]
+    pub fn num_fields(&self) -> usize {
+        self.offsets.len() - 1
+    }
+
+    #[must_use]
+    pub fn range(&self, field: usize) -> Range<usize> {
+        self.offsets[field]..self.offsets[field + 1]
+    }
+
+    #[must_use]
+    pub fn field(&self, field: usize) -> &FieldLayout {
+        &self.fields[field]
+    }
+
+    /// # Panics
+    /// If the field is not a nested state.
+    #[must_use]
+    pub fn child(&self, field: usize) -> &Layout {
+        match &self.fields[field] {
+            FieldLayout::Nested(layout) => layout,
+            _ => panic!("field {field} is not a nested state"),
+        }
+    }
+
+    /// # Panics
+    /// If `data` does not have the length of the layout.
+    #[must_use]
+    pub fn split<'a>(&self, data: &'a [f64]) -> Vec<&'a [f64]> {
+        assert_eq!(data.len(), self.len(), "data does not match layout");
+        (0..self.num_fields())
+            .map(|field| &data[self.range(field)])
+            .collect()
+    }
+
+    /// Disjoint mutable borrows of each field, without any unsafe pointer arithmetic.
+    ///
+    /// # Panics
+    /// If `data` does not have the length of the layout.
+    #[must_use]
+    pub fn split_mut<'a>(&self, data: &'a mut [f64]) -> Vec<&'a mut [f64]> {
+        assert_eq!(data.len(), self.len(), "data does not match layout");
+        let mut rest = data;
+        let mut fields = Vec::with_capacity(self.num_fields());
+        for field in 0..self.num_fields() {
+            let (head, tail) = rest.split_at_mut(self.range(field).len());
+            fields.push(head);
+            rest = tail;
+        }
+        fields
+    }
+}
+
+/// Owned contiguous storage of a state vector. Fields are offsets into a single `DVector`, so the
+/// storage is freed by the vector's own `Drop` and views are ordinary borrows of it.
+pub struct Contiguous<T> {
+    layout: Layout,
+    data: DVector<f64>,
+    _state: PhantomData<fn() -> T>,
+}
+
+impl<T: StateVector> Contiguous<T> {
+    #[must_use]
+    pub fn new(state: &T) -> Self {
+        Self {
+            layout: state.layout(),
+            data: state.to_vector(),
+            _state: PhantomData,
+        }
+    }
+
+    #[must_use]
+    pub fn zeros(layout: Layout) -> Self {
+        Self {
+            data: DVector::zeros(layout.len()),
+            layout,
+            _state: PhantomData,
+        }
+    }
+
+    /// # Panics
+    /// If `data` does not have the length of `layout`.
+    #[must_use]
+    pub fn from_vector(layout: Layout, data: DVector<f64>) -> Self {
+        assert_eq!(data.len(), layout.len(), "data does not match layout");
+        Self {
+            layout,
+            data,
+            _state: PhantomData,
+        }
+    }
+
+    #[must_use]
+    pub fn layout(&self) -> &Layout {
+        &self.layout
+    }
+
+    #[must_use]
+    pub fn view(&self) -> T::View<'_> {
+        T::view(&self.layout, self.data.as_slice())
+    }
+
+    #[must_use]
+    pub fn view_mut(&mut self) -> T::ViewMut<'_> {
+        T::view_mut(&self.layout, self.data.as_mut_slice())
+    }
+
+    #[must_use]
+    pub fn as_vector(&self) -> &DVector<f64> {
+        &self.data
+    }
+
+    #[must_use]
+    pub fn into_vector(self) -> DVector<f64> {
+        self.data
+    }
+
+    #[must_use]
+    pub fn to_state(&self) -> T {
+        T::read(&self.layout, self.data.as_slice())
+    }
+}
+
+impl<T> Clone for Contiguous<T> {
+    fn clone(&self) -> Self {
+        Self {
+            layout: self.layout.clone(),
+            data: self.data.clone(),
+            _state: PhantomData,
+        }
+    }
+}
+
+impl<T> fmt::Debug for Contiguous<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Contiguous")
+            .field("layout", &self.layout)
+            .field("data", &self.data)
+            .finish()
+    }
+}
+
+/// Adapts a derivative written against views of a state into a `DerivativeFunc` over the flat
+/// vector. A parent state's derivative can hand each nested field's views to that child's own
+/// function.
+pub fn derivative_func<'a, T, F>(
+    layout: Layout,
+    f: F,
+) -> impl Fn(f64, &DVector<f64>) -> DVector<f64> + 'a
+where
+    T: StateVector + 'a,
+    F: for<'b> Fn(f64, T::View<'b>, T::ViewMut<'b>) + 'a,
+{
+    move |t, y| {
+        let mut dydt = DVector::zeros(layout.len());
+        f(
+            t,
+            T::view(&layout, y.as_slice()),
+            T::view_mut(&layout, dydt.as_mut_slice()),
+        );
+        dydt
+    }
+}
+
+/// Helpers for code generated by `#[derive(StateVector)]`.
+#[doc(hidden)]
+#[must_use]
+pub fn scalar_mut(data: &mut [f64]) -> &mut f64 {
+    &mut data[0]
+}
+
+/// The `len` values of `data` at `offset`, advancing `offset` past them.
+#[doc(hidden)]
+#[must_use]
+pub fn take_mut<'a>(data: &'a mut [f64], offset: &mut usize, len: usize) -> &'a mut [f64] {
+    let field = &mut data[*offset..*offset + len];
+    *offset += len;
+    field
+}
+
+#[doc(hidden)]
+#[must_use]
+pub fn array<const N: usize>(data: &[f64]) -> &[f64; N] {
+    data.try_into().expect("field length does not match array")
+}
+
+#[doc(hidden)]
+#[must_use]
+pub fn array_mut<const N: usize>(data: &mut [f64]) -> &mut [f64; N] {
+    data.try_into().expect("field length does not match array")
 }
 
 #[cfg(test)]
+#[allow(clippy::float_cmp)]
 mod tests {
     use super::*;
 
+    #[derive(StateVector, Debug, PartialEq)]
+    struct ConcreteType {
+        field0: f64,
+        vec_field: Vec<f64>,
+        array_field: [f64; 2],
+        field1: f64,
+    }
+
     #[test]
     fn test_struct() {
-        let _vec = Vector::<f64> {
-            _phantom: std::marker::PhantomData,
+        let state = ConcreteType {
+            field0: 1.0,
+            vec_field: vec![2.0, 3.0, 4.0],
+            array_field: [5.0, 6.0],
+            field1: 7.0,
         };
-        let _test = ConcreteType {
+        let layout = state.layout();
+        assert_eq!(layout.len(), 7);
+        assert_eq!(layout.range(1), 1..4);
+
+        let mut data = state.to_vector();
+        assert_eq!(data.as_slice(), &[1.0, 2.0, 3.0, 4.0, 5.0, 6.0, 7.0]);
+
+        let view = ConcreteType::view(&layout, data.as_slice());
+        assert_eq!(*view.field0, 1.0);
+        assert_eq!(view.vec_field, &[2.0, 3.0, 4.0]);
+        assert_eq!(view.array_field, &[5.0, 6.0]);
+        assert_eq!(*view.field1, 7.0);
+
+        let view = ConcreteType::view_mut(&layout, data.as_mut_slice());
+        *view.field0 = -1.0;
+        view.vec_field[2] = -4.0;
+        view.array_field[0] = -5.0;
+        *view.field1 = -7.0;
+
+        assert_eq!(
+            ConcreteType::read(&layout, data.as_slice()),
+            ConcreteType {
+                field0: -1.0,
+                vec_field: vec![2.0, 3.0, -4.0],
+                array_field: [-5.0, 6.0],
+                field1: -7.0,
+            }
+        );
+    }
+
+    #[test]
+    fn test_variable_length_middle_field() {
+        for length in [0_u32, 1, 5, 1000] {
+            let state = ConcreteType {
+                field0: -1.0,
+                vec_field: (0..length).map(f64::from).collect(),
+                array_field: [0.5, 0.25],
+                field1: -2.0,
+            };
+            let mut buffer = Contiguous::new(&state);
+            assert_eq!(buffer.layout().len() - 4, usize::try_from(length).unwrap());
+
+            let view = buffer.view_mut();
+            *view.field0 += 1.0;
+            for x in view.vec_field.iter_mut() {
+                *x *= 2.0;
+            }
+            *view.field1 += 1.0;
+
+            let view = buffer.view();
+            assert_eq!(*view.field0, 0.0);
+            assert_eq!(view.vec_field.len(), usize::try_from(length).unwrap());
+            assert_eq!(view.array_field, &state.array_field);
+            assert_eq!(*view.field1, -1.0);
+
+            let expected: Vec<f64> = (0..length).map(|x| 2.0 * f64::from(x)).collect();
+            assert_eq!(buffer.to_state().vec_field, expected);
+        }
+    }
+
+    #[test]
+    fn test_contiguous_drop() {
+        let state = ConcreteType {
+            field0: 0.0,
+            vec_field: vec![1.0; 16],
+            array_field: [0.0; 2],
+            field1: 0.0,
+        };
+
+        let buffers: Vec<_> = (0..8).map(|_| Contiguous::new(&state)).collect();
+        let copy = buffers[3].clone();
+        drop(buffers);
+
+        let zeros = Contiguous::<ConcreteType>::zeros(copy.layout().clone());
+        assert_eq!(zeros.as_vector().len(), copy.into_vector().len());
+    }
+
+    #[derive(StateVector, Debug, PartialEq)]
+    struct ParentType {
+        scalar: f64,
+        first: ConcreteType,
+        second: ConcreteType,
+    }
+
+    #[test]
+    fn test_nested_layout() {
+        let child = |length| ConcreteType {
             field0: 1.0,
-            vec_field: _vec,
-            field1: 2.0,
+            vec_field: vec![2.0; length],
+            array_field: [3.0, 4.0],
+            field1: 5.0,
         };
-        assert_eq!(_test.field0, 1.0);
+        let state = ParentType {
+            scalar: 0.0,
+            first: child(1),
+            second: child(3),
+        };
+
+        let layout = state.layout();
+        assert_eq!(layout.len(), 1 + 5 + 7);
+        assert_eq!(layout.range(2), 6..13);
+        assert_eq!(layout.child(2).range(1), 1..4);
+
+        let mut data = state.to_vector();
+        let view = ParentType::view_mut(&layout, data.as_mut_slice());
+        view.second.vec_field[2] = -2.0;
+        *view.first.field1 = -5.0;
+
+        let view = ParentType::view(&layout, data.as_slice());
+        assert_eq!(view.second.vec_field, &[2.0, 2.0, -2.0]);
+        assert_eq!(data[5], -5.0);
+        assert_eq!(data[9], -2.0);
+        assert_eq!(
+            ParentType::read(&layout, data.as_slice()).second.vec_field[2],
+            -2.0
+        );
+    }
+
+    #[test]
+    fn test_element_names() {
+        let layout = Layout::from_fields(vec![
+            FieldLayout::Scalar,
+            FieldLayout::Leaf(2),
+            FieldLayout::Nested(Layout::new(&[1, 2])),
+        ]);
+        let names: Vec<_> = layout.elements().into_iter().map(|e| e.name).collect();
+        assert_eq!(names, ["0", "1[0]", "1[1]", "2.0[0]", "2.1[0]", "2.1[1]"]);
+
+        let layout = layout.with_name("state").with_metadata(vec![
+            FieldMetadata {
+                name: "time",
+                unit: Some("s"),
+                ..FieldMetadata::default()
+            },
+            FieldMetadata {
+                name: "position",
+                element_names: &["x", "y"],
+                ..FieldMetadata::default()
+            },
+            FieldMetadata {
+                name: "child",
+                ..FieldMetadata::default()
+            },
+        ]);
+        assert_eq!(layout.element(0).to_string(), "state.time [s]");
+        assert_eq!(layout.element(2).to_string(), "state.position.y");
+        assert_eq!(layout.element(5).to_string(), "state.child.1[1]");
+        assert_eq!(describe_element(Some(&layout), 6), "element 6");
+        assert_eq!(describe_element(None, 1), "element 1");
     }
 }
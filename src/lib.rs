@@ -1,2 +1,7 @@
-pub mod ode;
+#[cfg(test)]
+extern crate self as finfoot;
+
 pub mod contig;
+pub mod ode;
+pub mod output;
+pub mod units;
@@ -1,23 +1,145 @@
+use std::fmt;
+
 use nalgebra::DVector;
 
+use crate::contig::{self, Layout};
+
+pub mod adjoint;
+pub mod batch;
+pub mod bvp;
+pub mod dde;
 pub mod dopri5;
+pub mod ensemble;
+pub mod exponential;
+mod extrapolation;
+pub mod gbs;
+pub mod global_error;
+pub mod hybrid;
+pub mod imex;
+pub mod jacobian;
+pub mod lie;
+pub mod projection;
+pub mod rng;
+pub mod rodas3;
+pub mod sde;
+pub mod sensitivity;
+pub mod seulex;
+
+pub type DerivativeFunc<'a> = dyn Fn(f64, &DVector<f64>) -> DVector<f64> + 'a;
 
-pub type DerivativeFunc = dyn Fn(f64, &DVector<f64>) -> DVector<f64>;
+/// Derivative of a parameterized system, f(t, y, p).
+pub type ParamDerivativeFunc<'a> = dyn Fn(f64, &DVector<f64>, &DVector<f64>) -> DVector<f64> + 'a;
 
 #[derive(Debug)]
 pub enum InputError {
     TimeSpan,
     StepSize,
+    Delay,
+    /// A hybrid system mode index out of range.
+    Mode,
 }
 
 #[derive(Debug)]
 pub enum Error {
     Input(InputError),
-    Convergence,
+    /// `element` is the state element with the largest error relative to tolerance.
+    Convergence {
+        t: f64,
+        h: f64,
+        element: usize,
+    },
+    /// The implicit stage equations had a singular iteration matrix even at the smallest step
+    /// tried, or, with h = 0, a state-dependent mass matrix is singular at the initial time.
+    Singular {
+        t: f64,
+        h: f64,
+    },
+    /// Algebraic equations could not be satisfied at the initial time.
+    InitialConditions {
+        residual: f64,
+    },
+    /// Newton iteration on the boundary value problem did not converge.
+    BoundaryConditions {
+        residual: f64,
+    },
+}
+
+impl Error {
+    /// Names the offending element when the state layout is known.
+    #[must_use]
+    pub fn describe(&self, layout: Option<&Layout>) -> String {
+        match self {
+            Error::Input(InputError::TimeSpan) => String::from("invalid time span"),
+            Error::Input(InputError::StepSize) => String::from("invalid initial step size"),
+            Error::Input(InputError::Delay) => String::from("invalid delay"),
+            Error::Input(InputError::Mode) => String::from("invalid mode"),
+            Error::Convergence { t, h, element } => format!(
+                "failed to converge at t = {t} with h = {h}, limited by {}",
+                contig::describe_element(layout, *element)
+            ),
+            Error::Singular { t, h } => {
+                format!("singular iteration matrix at t = {t} with h = {h}")
+            }
+            Error::InitialConditions { residual } => {
+                format!("failed to find consistent initial conditions, residual {residual}")
+            }
+            Error::BoundaryConditions { residual } => {
+                format!("failed to satisfy boundary conditions, residual {residual}")
+            }
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.describe(None))
+    }
 }
 
+impl std::error::Error for Error {}
+
 impl From<InputError> for Error {
     fn from(err: InputError) -> Error {
         Error::Input(err)
     }
 }
+
+// Error ratio bounds for controllers of order 4 methods, h scaling as error_ratio^(1/4). The
+// dopri5 bounds are for order 5.
+const MIN_ERROR_RATIO_4: f64 = 1e-4; // (1/10)^4, 10x decrease in h.
+const MAX_ERROR_RATIO_4: f64 = 1e4; // 10^4, 10x increase in h.
+
+// Shared input checks: the time span must run forwards and the initial step be positive.
+fn validate_span(t_span: [f64; 2], h: f64) -> Result<(), InputError> {
+    if t_span[0] > t_span[1] {
+        return Err(InputError::TimeSpan);
+    }
+    if h <= 0.0 {
+        return Err(InputError::StepSize);
+    }
+    Ok(())
+}
+
+// The smallest ratio of allowed to estimated error over the elements, clamped to [min, max], and
+// the element it belongs to. A NaN ratio, from an error that is not finite, counts as `min`.
+fn error_ratio(allowed: &DVector<f64>, error: &DVector<f64>, min: f64, max: f64) -> (f64, usize) {
+    allowed
+        .iter()
+        .zip(error.iter())
+        .map(|(allowed, error)| {
+            let ratio = allowed / error.abs();
+            if ratio.is_nan() {
+                min
+            } else {
+                ratio.clamp(min, max)
+            }
+        })
+        .enumerate()
+        .fold((max, 0), |(best, element), (i, ratio)| {
+            if ratio < best {
+                (ratio, i)
+            } else {
+                (best, element)
+            }
+        })
+}
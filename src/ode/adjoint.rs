@@ -0,0 +1,209 @@
+use std::fmt;
+
+use nalgebra::DVector;
+
+use super::dopri5::{self, Config, DenseOutput};
+use super::jacobian::Jacobian;
+use super::{Error, ParamDerivativeFunc};
+
+/// Scalar cost term c(t, y, p) and its gradients.
+pub type CostFunc<'a> = dyn Fn(f64, &DVector<f64>, &DVector<f64>) -> f64 + 'a;
+pub type CostGradientFunc<'a> = dyn Fn(f64, &DVector<f64>, &DVector<f64>) -> DVector<f64> + 'a;
+
+pub enum Gradient<'a> {
+    Analytic(&'a CostGradientFunc<'a>),
+    /// Forward differences, one extra cost evaluation per element.
+    FiniteDifference,
+}
+
+impl fmt::Debug for Gradient<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Gradient::Analytic(_) => write!(f, "Analytic"),
+            Gradient::FiniteDifference => write!(f, "FiniteDifference"),
+        }
+    }
+}
+
+pub struct Cost<'a> {
+    pub value: &'a CostFunc<'a>,
+    pub gradient_y: Gradient<'a>,
+    pub gradient_p: Gradient<'a>,
+}
+
+impl fmt::Debug for Cost<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Cost")
+            .field("value", &"CostFunc")
+            .field("gradient_y", &self.gradient_y)
+            .field("gradient_p", &self.gradient_p)
+            .finish()
+    }
+}
+
+impl Cost<'_> {
+    fn gradient_y(&self, t: f64, y: &DVector<f64>, p: &DVector<f64>) -> DVector<f64> {
+        match self.gradient_y {
+            Gradient::Analytic(gradient) => gradient(t, y, p),
+            Gradient::FiniteDifference => finite_difference(|y| (self.value)(t, y, p), y),
+        }
+    }
+
+    fn gradient_p(&self, t: f64, y: &DVector<f64>, p: &DVector<f64>) -> DVector<f64> {
+        match self.gradient_p {
+            Gradient::Analytic(gradient) => gradient(t, y, p),
+            Gradient::FiniteDifference => finite_difference(|p| (self.value)(t, y, p), p),
+        }
+    }
+}
+
+fn finite_difference(c: impl Fn(&DVector<f64>) -> f64, x: &DVector<f64>) -> DVector<f64> {
+    let c_value = c(x);
+    let mut x = x.clone();
+    DVector::from_fn(x.len(), |j, _| {
+        let xj = x[j];
+        let delta = f64::EPSILON.sqrt() * xj.abs().max(1.0);
+        x[j] = xj + delta;
+        let gradient = (c(&x) - c_value) / delta;
+        x[j] = xj;
+        gradient
+    })
+}
+
+/// Objective J = terminal(t1, y(t1), p) + integral of running(t, y, p) over the time span.
+pub struct Input<'a> {
+    pub t_span: [f64; 2],
+    pub y0: &'a DVector<f64>,
+    pub p: &'a DVector<f64>,
+    pub h0: f64,
+    pub f: &'a ParamDerivativeFunc<'a>,
+    pub jacobian_y: Jacobian<'a>,
+    pub jacobian_p: Jacobian<'a>,
+    pub terminal: Option<Cost<'a>>,
+    pub running: Option<Cost<'a>>,
+}
+
+impl fmt::Debug for Input<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Input")
+            .field("t_span", &self.t_span)
+            .field("y0", self.y0)
+            .field("p", self.p)
+            .field("h0", &self.h0)
+            .field("f", &"ParamDerivativeFunc")
+            .field("jacobian_y", &self.jacobian_y)
+            .field("jacobian_p", &self.jacobian_p)
+            .field("terminal", &self.terminal)
+            .field("running", &self.running)
+            .finish()
+    }
+}
+
+#[derive(Debug)]
+pub struct Output {
+    pub cost: f64,
+    pub y: DVector<f64>,
+    /// dJ/dy0.
+    pub gradient_y0: DVector<f64>,
+    /// dJ/dp.
+    pub gradient_p: DVector<f64>,
+    pub num_calls: usize,
+    pub num_forward_steps: usize,
+    pub num_backward_steps: usize,
+}
+
+/// Continuous adjoint. The forward pass keeps the dense output of every step as its checkpoint of
+/// the trajectory, so the backward pass never re-integrates the state. With a = dJ/dy(t) and
+/// q = dJ/dp accumulated from t1 back to t:
+///
+/// ```text
+/// da/dt = -(J_y^T a + dL/dy),  a(t1) = d(terminal)/dy
+/// dq/dt = -(J_p^T a + dL/dp),  q(t1) = d(terminal)/dp
+/// ```
+///
+/// The backward pass is integrated in s = -t so the solver still runs forward in its own time.
+pub fn integrate(input: &Input<'_>, config: &Config) -> Result<Output, Error> {
+    let n = input.y0.len();
+    let num_params = input.p.len();
+    let p = input.p;
+    let [t0, t1] = input.t_span;
+
+    // Forward pass, with the running cost as an extra state element.
+    let forward = |t: f64, z: &DVector<f64>| {
+        let y = z.rows(0, n).into_owned();
+        let mut z_dot = DVector::zeros(n + 1);
+        z_dot.rows_mut(0, n).copy_from(&(input.f)(t, &y, p));
+        if let Some(running) = &input.running {
+            z_dot[n] = (running.value)(t, &y, p);
+        }
+        z_dot
+    };
+    let z0 = input.y0.clone().insert_row(n, 0.0);
+    let forward_input = dopri5::Input {
+        t_span: input.t_span,
+        y0: &z0,
+        h0: input.h0,
+        f: &forward,
+    };
+    let (forward_output, dense) = dopri5::integrate_dense(&forward_input, config)?;
+    let y1 = forward_output.y.rows(0, n).into_owned();
+
+    let mut cost = forward_output.y[n];
+    let mut w1 = DVector::zeros(n + num_params);
+    if let Some(terminal) = &input.terminal {
+        cost += (terminal.value)(t1, &y1, p);
+        w1.rows_mut(0, n)
+            .copy_from(&terminal.gradient_y(t1, &y1, p));
+        w1.rows_mut(n, num_params)
+            .copy_from(&terminal.gradient_p(t1, &y1, p));
+    }
+
+    // Backward pass on w = [a, q].
+    let backward = |s: f64, w: &DVector<f64>| {
+        let t = -s;
+        let y = state_at(&dense, t, n);
+        let adjoint = w.rows(0, n);
+        let f_value = (input.f)(t, &y, p);
+        let jacobian_y = input.jacobian_y.wrt_y(input.f, t, &y, p, &f_value);
+        let jacobian_p = input.jacobian_p.wrt_p(input.f, t, &y, p, &f_value);
+
+        let mut w_dot = DVector::zeros(n + num_params);
+        w_dot
+            .rows_mut(0, n)
+            .copy_from(&(jacobian_y.tr_mul(&adjoint)));
+        w_dot
+            .rows_mut(n, num_params)
+            .copy_from(&(jacobian_p.tr_mul(&adjoint)));
+        if let Some(running) = &input.running {
+            let mut adjoint_dot = w_dot.rows_mut(0, n);
+            adjoint_dot += running.gradient_y(t, &y, p);
+            let mut q_dot = w_dot.rows_mut(n, num_params);
+            q_dot += running.gradient_p(t, &y, p);
+        }
+        w_dot
+    };
+    let backward_input = dopri5::Input {
+        t_span: [-t1, -t0],
+        y0: &w1,
+        h0: input.h0,
+        f: &backward,
+    };
+    let backward_output = dopri5::integrate(&backward_input, config)?;
+
+    Ok(Output {
+        cost,
+        y: y1,
+        gradient_y0: backward_output.y.rows(0, n).into_owned(),
+        gradient_p: backward_output.y.rows(n, num_params).into_owned(),
+        num_calls: forward_output.num_calls + backward_output.num_calls,
+        num_forward_steps: forward_output.num_steps,
+        num_backward_steps: backward_output.num_steps,
+    })
+}
+
+fn state_at(dense: &DenseOutput, t: f64, n: usize) -> DVector<f64> {
+    match dense.segment(t) {
+        Some(segment) => segment.evaluate(t).rows(0, n).into_owned(),
+        None => DVector::zeros(n),
+    }
+}
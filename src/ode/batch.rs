@@ -0,0 +1,181 @@
+use std::fmt;
+
+use nalgebra::DVector;
+
+use super::dopri5::{self, A_COEFF, B_COEFF, C_COEFF, MAX_ERROR_RATIO, MIN_ERROR_RATIO};
+use super::{error_ratio, validate_span, Error};
+
+/// One value per independent system, laid out contiguously so lane loops vectorize.
+pub type Lanes<const L: usize> = [f64; L];
+
+/// Structure of arrays: `y[i][lane]` is element i of system `lane`, and `dydt` has the same shape.
+/// Each lane has its own time.
+pub type BatchDerivativeFunc<'a, const L: usize> =
+    dyn Fn(&Lanes<L>, &[Lanes<L>], &mut [Lanes<L>]) + 'a;
+
+pub struct Input<'a, const L: usize> {
+    pub t_span: [f64; 2],
+    pub y0: &'a [Lanes<L>],
+    pub h0: f64,
+    pub f: &'a BatchDerivativeFunc<'a, L>,
+}
+
+impl<const L: usize> fmt::Debug for Input<'_, L> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Input")
+            .field("t_span", &self.t_span)
+            .field("y0", &self.y0)
+            .field("h0", &self.h0)
+            .field("f", &"BatchDerivativeFunc")
+            .finish()
+    }
+}
+
+#[derive(Debug)]
+pub struct Output<const L: usize> {
+    /// Final time of each lane, before `t_span[1]` for lanes that failed.
+    pub t: Lanes<L>,
+    pub y: Vec<Lanes<L>>,
+    pub h: Lanes<L>,
+    /// Batched derivative calls, each evaluating every lane.
+    pub num_calls: usize,
+    pub num_steps: [usize; L],
+    pub num_rejected: [usize; L],
+    /// Why a lane stopped early. The other lanes carry on to the end of the time span.
+    pub errors: [Option<Error>; L],
+}
+
+/// Advances L independent systems in lockstep with Dormand-Prince 5(4). Every lane keeps its own
+/// step size and is accepted or rejected on its own error; finished lanes are masked with a zero
+/// step until the slowest lane reaches the end of the time span. Lanes that fail are masked the
+/// same way, and their error is reported in the output.
+#[allow(clippy::too_many_lines, clippy::many_single_char_names)]
+pub fn integrate<const L: usize>(
+    input: &Input<'_, L>,
+    config: &dopri5::Config,
+) -> Result<Output<L>, Error> {
+    validate_span(input.t_span, input.h0)?;
+
+    let n = input.y0.len();
+    let t_end = input.t_span[1];
+
+    let mut t = [input.t_span[0]; L];
+    let mut h = [input.h0; L];
+    let mut y = input.y0.to_vec();
+    let mut k = vec![vec![[0.0; L]; n]; 7];
+    let mut stage = vec![[0.0; L]; n];
+    // Element with the smallest error ratio on the last attempted step of each lane.
+    let mut limiting_element = [0; L];
+    let mut num_failures = [0; L];
+    let mut num_steps = [0; L];
+    let mut num_rejected = [0; L];
+    let mut errors: [Option<Error>; L] = std::array::from_fn(|_| None);
+
+    (input.f)(&t, &y, &mut k[0]);
+    let mut num_calls = 1;
+
+    loop {
+        let mut active: [bool; L] = std::array::from_fn(|l| t[l] < t_end && errors[l].is_none());
+        let h_step: Lanes<L> = std::array::from_fn(|l| {
+            if active[l] {
+                h[l].min(t_end - t[l])
+            } else {
+                0.0
+            }
+        });
+        // A lane whose step has underflowed, typically approaching a singularity, fails.
+        for l in 0..L {
+            if active[l] && t[l] + h_step[l] <= t[l] {
+                active[l] = false;
+                errors[l] = Some(Error::Convergence {
+                    t: t[l],
+                    h: h_step[l],
+                    element: limiting_element[l],
+                });
+            }
+        }
+        if !active.contains(&true) {
+            break;
+        }
+
+        // Stages 2 through 7. The last stage is evaluated at the fifth order solution. [FSAL]
+        for s in 1..7 {
+            for i in 0..n {
+                let mut sum = [0.0; L];
+                for (j, k_j) in k.iter().enumerate().take(s) {
+                    let a = A_COEFF[s - 1][j];
+                    for l in 0..L {
+                        sum[l] += a * k_j[i][l];
+                    }
+                }
+                for l in 0..L {
+                    stage[i][l] = y[i][l] + h_step[l] * sum[l];
+                }
+            }
+            let t_stage: Lanes<L> = std::array::from_fn(|l| t[l] + C_COEFF[s] * h_step[l]);
+            (input.f)(&t_stage, &stage, &mut k[s]);
+            num_calls += 1;
+        }
+
+        // h step size control, per lane.
+        let mut error = vec![[0.0; L]; n];
+        for (i, error) in error.iter_mut().enumerate() {
+            for (j, k_j) in k.iter().enumerate() {
+                let b = B_COEFF[0][j] - B_COEFF[1][j];
+                for l in 0..L {
+                    error[l] += b * k_j[i][l];
+                }
+            }
+        }
+        let mut error_ratios = [MAX_ERROR_RATIO; L];
+        for l in (0..L).filter(|&l| active[l]) {
+            let allowed_error = DVector::from_fn(n, |i, _| {
+                (config.rel_tol * stage[i][l].abs()).max(config.abs_tol)
+            });
+            let lane_error = DVector::from_fn(n, |i, _| h_step[l] * error[i][l]);
+            (error_ratios[l], limiting_element[l]) = error_ratio(
+                &allowed_error,
+                &lane_error,
+                MIN_ERROR_RATIO,
+                MAX_ERROR_RATIO,
+            );
+        }
+
+        for l in (0..L).filter(|&l| active[l]) {
+            h[l] = 0.9 * h_step[l] * error_ratios[l].powf(1.0 / 5.0);
+
+            // Discard step if error is too high.
+            if error_ratios[l] < 1.0 {
+                num_rejected[l] += 1;
+                num_failures[l] += 1;
+                if num_failures[l] > 10 {
+                    errors[l] = Some(Error::Convergence {
+                        t: t[l],
+                        h: h_step[l],
+                        element: limiting_element[l],
+                    });
+                }
+                continue;
+            }
+            num_failures[l] = 0;
+            num_steps[l] += 1;
+
+            // Propagate lane.
+            t[l] += h_step[l];
+            for i in 0..n {
+                y[i][l] = stage[i][l];
+                k[0][i][l] = k[6][i][l];
+            }
+        }
+    }
+
+    Ok(Output {
+        t,
+        y,
+        h,
+        num_calls,
+        num_steps,
+        num_rejected,
+        errors,
+    })
+}
@@ -0,0 +1,80 @@
+use nalgebra::{DMatrix, DVector};
+
+use super::jacobian::{self, Difference};
+use super::Error;
+
+pub mod collocation;
+pub mod shooting;
+
+/// Residuals of the boundary conditions g(y(a), y(b), p), one per state element plus one per
+/// unknown parameter. Periodic orbits of unknown period are solved by scaling time by a period
+/// parameter and adding a phase condition.
+pub type BoundaryFunc<'a> =
+    dyn Fn(&DVector<f64>, &DVector<f64>, &DVector<f64>) -> DVector<f64> + 'a;
+
+/// Initial guess of the solution over the interval.
+pub type GuessFunc<'a> = dyn Fn(f64) -> DVector<f64> + 'a;
+
+const MAX_LINE_SEARCH: usize = 10;
+
+// Jacobians of the boundary conditions with respect to y(a), y(b) and p, where `g` is their value.
+fn boundary_jacobians(
+    boundary: &BoundaryFunc<'_>,
+    ya: &DVector<f64>,
+    yb: &DVector<f64>,
+    p: &DVector<f64>,
+    g: &DVector<f64>,
+) -> [DMatrix<f64>; 3] {
+    [
+        jacobian::finite_difference(|ya| boundary(ya, yb, p), ya, g, Difference::Forward, None),
+        jacobian::finite_difference(|yb| boundary(ya, yb, p), yb, g, Difference::Forward, None),
+        jacobian::finite_difference(|p| boundary(ya, yb, p), p, g, Difference::Forward, None),
+    ]
+}
+
+// Newton iteration on a square nonlinear system, halving the step until the residual norm
+// decreases. `step` solves J dx = -r, returning None if the Jacobian is singular. A residual that
+// cannot be evaluated, for example because the trajectory blew up, counts as no decrease. Returns
+// the solution and the number of iterations.
+fn newton(
+    mut x: DVector<f64>,
+    residual: impl Fn(&DVector<f64>) -> Result<DVector<f64>, Error>,
+    step: impl Fn(&DVector<f64>, &DVector<f64>) -> Result<Option<DVector<f64>>, Error>,
+    tolerance: f64,
+    max_iterations: usize,
+) -> Result<(DVector<f64>, usize), Error> {
+    let mut r = residual(&x)?;
+    for iteration in 0..max_iterations {
+        if r.amax() <= tolerance {
+            return Ok((x, iteration));
+        }
+
+        let Some(dx) = step(&x, &r)? else {
+            break;
+        };
+        let norm = r.norm();
+        let mut damping = 1.0;
+        let mut accepted = false;
+        for _ in 0..MAX_LINE_SEARCH {
+            let trial = &x + damping * &dx;
+            if let Ok(r_trial) = residual(&trial) {
+                if r_trial.norm() < norm {
+                    x = trial;
+                    r = r_trial;
+                    accepted = true;
+                    break;
+                }
+            }
+            damping /= 2.0;
+        }
+        if !accepted {
+            break;
+        }
+    }
+
+    if r.amax() <= tolerance {
+        Ok((x, max_iterations))
+    } else {
+        Err(Error::BoundaryConditions { residual: r.amax() })
+    }
+}
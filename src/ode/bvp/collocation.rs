@@ -0,0 +1,423 @@
+use std::fmt;
+
+use nalgebra::{DMatrix, DVector};
+
+use super::{boundary_jacobians, newton, BoundaryFunc, GuessFunc};
+use crate::ode::jacobian::Jacobian;
+use crate::ode::{validate_span, Error, InputError, ParamDerivativeFunc};
+
+pub struct Input<'a> {
+    /// Initial mesh, strictly increasing. Nodes are added where the residual is too large.
+    pub t: &'a [f64],
+    pub guess: &'a GuessFunc<'a>,
+    /// Initial guess of the unknown parameters, empty if there are none.
+    pub p: &'a DVector<f64>,
+    pub f: &'a ParamDerivativeFunc<'a>,
+    pub jacobian_y: Jacobian<'a>,
+    pub jacobian_p: Jacobian<'a>,
+    pub boundary: &'a BoundaryFunc<'a>,
+    /// On the residual of the differential equations relative to 1 + |f|, between mesh nodes.
+    pub tolerance: f64,
+    pub max_nodes: usize,
+    /// Newton iterations per mesh.
+    pub max_iterations: usize,
+}
+
+impl fmt::Debug for Input<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Input")
+            .field("t", &self.t)
+            .field("guess", &"GuessFunc")
+            .field("p", self.p)
+            .field("f", &"ParamDerivativeFunc")
+            .field("jacobian_y", &self.jacobian_y)
+            .field("jacobian_p", &self.jacobian_p)
+            .field("boundary", &"BoundaryFunc")
+            .field("tolerance", &self.tolerance)
+            .field("max_nodes", &self.max_nodes)
+            .field("max_iterations", &self.max_iterations)
+            .finish()
+    }
+}
+
+/// Piecewise cubic solution, C1 across the mesh.
+#[derive(Debug)]
+pub struct Output {
+    pub t: Vec<f64>,
+    pub y: Vec<DVector<f64>>,
+    /// Derivative at every node.
+    pub y_dot: Vec<DVector<f64>>,
+    pub p: DVector<f64>,
+    /// Largest relative residual over the mesh intervals.
+    pub max_residual: f64,
+    /// Refinement stopped at `max_nodes` before the tolerance was met.
+    pub node_limit_reached: bool,
+    pub num_iterations: usize,
+}
+
+impl Output {
+    /// Cubic Hermite interpolation, which is the collocation polynomial on each interval.
+    #[must_use]
+    pub fn evaluate(&self, t: f64) -> DVector<f64> {
+        let i = self.t[1..self.t.len() - 1].partition_point(|&node| node <= t);
+        hermite(
+            [self.t[i], self.t[i + 1]],
+            [&self.y[i], &self.y[i + 1]],
+            [&self.y_dot[i], &self.y_dot[i + 1]],
+            t,
+        )
+        .0
+    }
+}
+
+// Value and derivative of the cubic through the end values and derivatives.
+fn hermite(
+    [t0, t1]: [f64; 2],
+    [y0, y1]: [&DVector<f64>; 2],
+    [d0, d1]: [&DVector<f64>; 2],
+    t: f64,
+) -> (DVector<f64>, DVector<f64>) {
+    let h = t1 - t0;
+    let s = (t - t0) / h;
+    let (s2, s3) = (s * s, s * s * s);
+    let value = (2.0 * s3 - 3.0 * s2 + 1.0) * y0
+        + (s3 - 2.0 * s2 + s) * h * d0
+        + (-2.0 * s3 + 3.0 * s2) * y1
+        + (s3 - s2) * h * d1;
+    let derivative = (6.0 * s2 - 6.0 * s) / h * y0
+        + (3.0 * s2 - 4.0 * s + 1.0) * d0
+        + (-6.0 * s2 + 6.0 * s) / h * y1
+        + (3.0 * s2 - 2.0 * s) * d1;
+    (value, derivative)
+}
+
+// Every interval must be a valid time span with a positive step.
+fn validate_mesh(t: &[f64]) -> Result<(), InputError> {
+    if t.len() < 2 {
+        return Err(InputError::TimeSpan);
+    }
+    t.windows(2)
+        .try_for_each(|pair| validate_span([pair[0], pair[1]], pair[1] - pair[0]))
+}
+
+// Interior points of 5 point Lobatto quadrature on [0, 1] where the collocation residual is
+// checked. It vanishes at the ends and middle by construction.
+const CHECK_POINTS: [f64; 2] = [0.5 - 0.327_326_835_353_988_8, 0.5 + 0.327_326_835_353_988_8];
+
+/// Three stage Lobatto IIIA collocation (Simpson's rule with a cubic midpoint), fourth order, as
+/// in scipy's `solve_bvp`. Each mesh is solved by Newton iteration and intervals whose residual
+/// exceeds the tolerance are split, in three if it exceeds it a hundredfold.
+#[allow(clippy::many_single_char_names)]
+pub fn solve(input: &Input<'_>) -> Result<Output, Error> {
+    validate_mesh(input.t)?;
+
+    let mut t = input.t.to_vec();
+    let mut y: Vec<DVector<f64>> = t.iter().map(|&t| (input.guess)(t)).collect();
+    let mut p = input.p.clone();
+    let mut num_iterations = 0;
+
+    loop {
+        let dim = y[0].len();
+        let mut x0 = DVector::zeros(t.len() * dim + p.len());
+        for (i, yi) in y.iter().enumerate() {
+            x0.rows_mut(i * dim, dim).copy_from(yi);
+        }
+        x0.rows_mut(t.len() * dim, p.len()).copy_from(&p);
+
+        let system = System { input, t: &t, dim };
+        let (x, iterations) = newton(
+            x0,
+            |x| Ok(system.residual(x)),
+            |x, r| Ok(system.newton_step(x, r)),
+            input.tolerance / 10.0,
+            input.max_iterations,
+        )?;
+        num_iterations += iterations;
+
+        let (nodes, params) = system.unpack(&x);
+        y = nodes;
+        p = params;
+        let y_dot: Vec<_> = t
+            .iter()
+            .zip(&y)
+            .map(|(&t, y)| (input.f)(t, y, &p))
+            .collect();
+
+        let errors: Vec<f64> = (0..t.len() - 1)
+            .map(|i| {
+                CHECK_POINTS
+                    .iter()
+                    .map(|&s| {
+                        let tc = t[i] + s * (t[i + 1] - t[i]);
+                        let (value, derivative) = hermite(
+                            [t[i], t[i + 1]],
+                            [&y[i], &y[i + 1]],
+                            [&y_dot[i], &y_dot[i + 1]],
+                            tc,
+                        );
+                        let f = (input.f)(tc, &value, &p);
+                        (derivative - &f)
+                            .zip_map(&f, |r, f| r.abs() / (1.0 + f.abs()))
+                            .max()
+                    })
+                    .fold(0.0, f64::max)
+            })
+            .collect();
+        let max_residual = errors.iter().copied().fold(0.0, f64::max);
+
+        let num_added: usize = errors
+            .iter()
+            .map(|&error| match error {
+                e if e > 100.0 * input.tolerance => 2,
+                e if e > input.tolerance => 1,
+                _ => 0,
+            })
+            .sum();
+        if num_added == 0 || t.len() + num_added > input.max_nodes {
+            return Ok(Output {
+                t,
+                y,
+                y_dot,
+                p,
+                max_residual,
+                node_limit_reached: num_added > 0,
+                num_iterations,
+            });
+        }
+
+        // Refine, interpolating the current solution as the next guess.
+        let mut refined_t = vec![t[0]];
+        let mut refined_y = vec![y[0].clone()];
+        for (i, &error) in errors.iter().enumerate() {
+            let splits: &[f64] = match error {
+                e if e > 100.0 * input.tolerance => &[1.0 / 3.0, 2.0 / 3.0],
+                e if e > input.tolerance => &[0.5],
+                _ => &[],
+            };
+            for &s in splits {
+                let ts = t[i] + s * (t[i + 1] - t[i]);
+                refined_t.push(ts);
+                refined_y.push(
+                    hermite(
+                        [t[i], t[i + 1]],
+                        [&y[i], &y[i + 1]],
+                        [&y_dot[i], &y_dot[i + 1]],
+                        ts,
+                    )
+                    .0,
+                );
+            }
+            refined_t.push(t[i + 1]);
+            refined_y.push(y[i + 1].clone());
+        }
+        t = refined_t;
+        y = refined_y;
+    }
+}
+
+// The collocation equations on one mesh. The unknowns are the states at the nodes followed by the
+// parameters; the residuals are the collocation conditions per interval, divided by the interval
+// length, followed by the boundary conditions.
+struct System<'a> {
+    input: &'a Input<'a>,
+    t: &'a [f64],
+    dim: usize,
+}
+
+// Values on one interval needed by both the residual and its Jacobian.
+struct Interval {
+    h: f64,
+    f0: DVector<f64>,
+    f1: DVector<f64>,
+    t_mid: f64,
+    y_mid: DVector<f64>,
+    f_mid: DVector<f64>,
+}
+
+impl System<'_> {
+    fn unpack(&self, x: &DVector<f64>) -> (Vec<DVector<f64>>, DVector<f64>) {
+        let num_nodes = self.t.len();
+        let y = (0..num_nodes)
+            .map(|i| x.rows(i * self.dim, self.dim).into_owned())
+            .collect();
+        let p = x
+            .rows(num_nodes * self.dim, x.len() - num_nodes * self.dim)
+            .into_owned();
+        (y, p)
+    }
+
+    fn intervals(&self, y: &[DVector<f64>], p: &DVector<f64>) -> Vec<Interval> {
+        let f: Vec<_> = self
+            .t
+            .iter()
+            .zip(y)
+            .map(|(&t, y)| (self.input.f)(t, y, p))
+            .collect();
+        (0..self.t.len() - 1)
+            .map(|i| {
+                let h = self.t[i + 1] - self.t[i];
+                let t_mid = self.t[i] + 0.5 * h;
+                let y_mid = 0.5 * (&y[i] + &y[i + 1]) - h / 8.0 * (&f[i + 1] - &f[i]);
+                let f_mid = (self.input.f)(t_mid, &y_mid, p);
+                Interval {
+                    h,
+                    f0: f[i].clone(),
+                    f1: f[i + 1].clone(),
+                    t_mid,
+                    y_mid,
+                    f_mid,
+                }
+            })
+            .collect()
+    }
+
+    fn residual(&self, x: &DVector<f64>) -> DVector<f64> {
+        let (y, p) = self.unpack(x);
+        let dim = self.dim;
+        let mut r = DVector::zeros(x.len());
+        for (i, interval) in self.intervals(&y, &p).iter().enumerate() {
+            let Interval {
+                h, f0, f1, f_mid, ..
+            } = interval;
+            r.rows_mut(i * dim, dim)
+                .copy_from(&((&y[i + 1] - &y[i]) / *h - (f0 + 4.0 * f_mid + f1) / 6.0));
+        }
+        let row = (self.t.len() - 1) * dim;
+        r.rows_mut(row, x.len() - row)
+            .copy_from(&(self.input.boundary)(&y[0], &y[y.len() - 1], &p));
+        r
+    }
+
+    // Newton step from the block structure of the Jacobian. Each interval's equations
+    // A dy_i + B dy_{i+1} + P dp = -r_i are condensed in turn, carrying the border unknowns
+    // z = [dy_0; dp] along: the equations left over from the previous interval are stacked on the
+    // new ones and dy_i is eliminated from the stack by a QR factorization. The orthogonal
+    // eliminations stay accurate when solution modes grow by many orders of magnitude across the
+    // interval, where solving forwards for dy_{i+1} would not. The boundary conditions close the
+    // remaining system in z and dy_N, and the eliminated rows give the other corrections backwards.
+    // This costs O(nodes) rather than a dense factorization.
+    #[allow(
+        clippy::many_single_char_names,
+        clippy::similar_names,
+        clippy::too_many_lines
+    )]
+    fn newton_step(&self, x: &DVector<f64>, r: &DVector<f64>) -> Option<DVector<f64>> {
+        let input = self.input;
+        let (y, p) = self.unpack(x);
+        let dim = self.dim;
+        let num_params = p.len();
+        let num_border = dim + num_params;
+        let identity = DMatrix::<f64>::identity(dim, dim);
+
+        let derivatives = |t: f64, y: &DVector<f64>, f_value: &DVector<f64>| {
+            (
+                input.jacobian_y.wrt_y(input.f, t, y, &p, f_value),
+                input.jacobian_p.wrt_p(input.f, t, y, &p, f_value),
+            )
+        };
+
+        // Columns of the equations: dy_i, z, dy_{i+1} and the right hand side.
+        let (z_col, next_col, rhs_col) = (dim, dim + num_border, 2 * dim + num_border);
+        // The equations carried to the next interval, in dy_{i+1} and z only.
+        let mut carried = DMatrix::zeros(dim, rhs_col + 1);
+        // Rows that eliminated dy_i, upper triangular in it.
+        let mut eliminated: Vec<DMatrix<f64>> = Vec::with_capacity(self.t.len() - 2);
+
+        let intervals = self.intervals(&y, &p);
+        let (mut j0, mut jp0) = derivatives(self.t[0], &y[0], &intervals[0].f0);
+        for (i, interval) in intervals.iter().enumerate() {
+            let Interval {
+                h,
+                f1,
+                t_mid,
+                y_mid,
+                f_mid,
+                ..
+            } = interval;
+            let h = *h;
+            let (j1, jp1) = derivatives(self.t[i + 1], &y[i + 1], f1);
+            let (j_mid, jp_mid) = derivatives(*t_mid, y_mid, f_mid);
+
+            // y_mid = (y0 + y1) / 2 - h (f1 - f0) / 8
+            let mid_y0 = 0.5 * &identity + h / 8.0 * &j0;
+            let mid_y1 = 0.5 * &identity - h / 8.0 * &j1;
+            let mid_p = -h / 8.0 * (&jp1 - &jp0);
+
+            let a = -&identity / h - (&j0 + 4.0 * &j_mid * mid_y0) / 6.0;
+            let b = &identity / h - (&j1 + 4.0 * &j_mid * mid_y1) / 6.0;
+            let dp = -(&jp0 + 4.0 * (&jp_mid + &j_mid * mid_p) + &jp1) / 6.0;
+
+            let mut equations = DMatrix::zeros(dim, rhs_col + 1);
+            // dy_0 is itself part of z.
+            let a_col = if i == 0 { z_col } else { 0 };
+            equations.view_mut((0, a_col), (dim, dim)).copy_from(&a);
+            equations
+                .view_mut((0, z_col + dim), (dim, num_params))
+                .copy_from(&dp);
+            equations.view_mut((0, next_col), (dim, dim)).copy_from(&b);
+            equations
+                .column_mut(rhs_col)
+                .copy_from(&-r.rows(i * dim, dim));
+            if i == 0 {
+                carried = equations;
+            } else {
+                let mut stack = DMatrix::zeros(2 * dim, rhs_col + 1);
+                stack.rows_mut(0, dim).copy_from(&carried);
+                stack
+                    .view_mut((0, 0), (dim, dim))
+                    .copy_from(&carried.columns(next_col, dim));
+                stack.view_mut((0, next_col), (dim, dim)).fill(0.0);
+                stack.rows_mut(dim, dim).copy_from(&equations);
+
+                let qr = stack.columns(0, dim).into_owned().qr();
+                qr.q_tr_mul(&mut stack);
+                eliminated.push(stack.rows(0, dim).into_owned());
+                carried = stack.rows(dim, dim).into_owned();
+            }
+
+            (j0, jp0) = (j1, jp1);
+        }
+
+        let row = (self.t.len() - 1) * dim;
+        let g = r.rows(row, num_border).into_owned();
+        let [g_a, g_b, g_p] = boundary_jacobians(input.boundary, &y[0], &y[y.len() - 1], &p, &g);
+        // The carried equations and the boundary conditions in [z; dy_N].
+        let mut closing = DMatrix::zeros(dim + num_border, num_border + dim);
+        closing
+            .rows_mut(0, dim)
+            .copy_from(&carried.columns(z_col, num_border + dim));
+        closing
+            .view_mut((dim, 0), (num_border, dim))
+            .copy_from(&g_a);
+        closing
+            .view_mut((dim, dim), (num_border, num_params))
+            .copy_from(&g_p);
+        closing
+            .view_mut((dim, num_border), (num_border, dim))
+            .copy_from(&g_b);
+        let mut closing_rhs = DVector::zeros(dim + num_border);
+        closing_rhs
+            .rows_mut(0, dim)
+            .copy_from(&carried.column(rhs_col));
+        closing_rhs.rows_mut(dim, num_border).copy_from(&-g);
+        let solution = closing.lu().solve(&closing_rhs)?;
+        let z = solution.rows(0, num_border).into_owned();
+
+        let last = self.t.len() - 1;
+        let mut dx = DVector::zeros(x.len());
+        dx.rows_mut(0, dim).copy_from(&z.rows(0, dim));
+        dx.rows_mut(last * dim, dim)
+            .copy_from(&solution.rows(num_border, dim));
+        for (i, rows) in eliminated.iter().enumerate().rev() {
+            let node = i + 1;
+            let rhs = rows.column(rhs_col)
+                - rows.columns(z_col, num_border) * &z
+                - rows.columns(next_col, dim) * dx.rows((node + 1) * dim, dim);
+            let dy = rows.columns(0, dim).solve_upper_triangular(&rhs)?;
+            dx.rows_mut(node * dim, dim).copy_from(&dy);
+        }
+        dx.rows_mut(self.t.len() * dim, num_params)
+            .copy_from(&z.rows(dim, num_params));
+        Some(dx)
+    }
+}
@@ -0,0 +1,202 @@
+use std::fmt;
+
+use nalgebra::{DMatrix, DVector};
+
+use super::{boundary_jacobians, newton, BoundaryFunc, GuessFunc};
+use crate::ode::dopri5::{self, Config};
+use crate::ode::jacobian::Jacobian;
+use crate::ode::sensitivity;
+use crate::ode::{Error, ParamDerivativeFunc};
+
+pub struct Input<'a> {
+    pub t_span: [f64; 2],
+    /// Sampled at the start of every segment for the initial shooting states.
+    pub guess: &'a GuessFunc<'a>,
+    /// Initial guess of the unknown parameters, empty if there are none.
+    pub p: &'a DVector<f64>,
+    /// One segment is single shooting. More segments keep unstable problems from blowing up
+    /// between nodes at the cost of a larger Newton system.
+    pub num_segments: usize,
+    pub h0: f64,
+    pub f: &'a ParamDerivativeFunc<'a>,
+    pub jacobian_y: Jacobian<'a>,
+    pub jacobian_p: Jacobian<'a>,
+    pub boundary: &'a BoundaryFunc<'a>,
+    /// On the largest continuity or boundary condition residual.
+    pub tolerance: f64,
+    pub max_iterations: usize,
+}
+
+impl fmt::Debug for Input<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Input")
+            .field("t_span", &self.t_span)
+            .field("guess", &"GuessFunc")
+            .field("p", self.p)
+            .field("num_segments", &self.num_segments)
+            .field("h0", &self.h0)
+            .field("f", &"ParamDerivativeFunc")
+            .field("jacobian_y", &self.jacobian_y)
+            .field("jacobian_p", &self.jacobian_p)
+            .field("boundary", &"BoundaryFunc")
+            .field("tolerance", &self.tolerance)
+            .field("max_iterations", &self.max_iterations)
+            .finish()
+    }
+}
+
+#[derive(Debug)]
+pub struct Output {
+    /// Segment boundaries, including both ends of the interval.
+    pub t: Vec<f64>,
+    /// State at every node in `t`.
+    pub y: Vec<DVector<f64>>,
+    pub p: DVector<f64>,
+    pub num_iterations: usize,
+}
+
+/// The unknowns are the states at the start of every segment followed by the parameters. The
+/// residuals are the continuity conditions between segments followed by the boundary conditions.
+#[allow(clippy::too_many_lines)]
+pub fn solve(input: &Input<'_>, config: &Config) -> Result<Output, Error> {
+    let num_segments = input.num_segments.max(1);
+    let [t0, t1] = input.t_span;
+    #[allow(clippy::cast_precision_loss)]
+    let nodes: Vec<f64> = (0..=num_segments)
+        .map(|k| t0 + (t1 - t0) * k as f64 / num_segments as f64)
+        .collect();
+
+    let dim = (input.guess)(t0).len();
+    let num_params = input.p.len();
+    let num_unknowns = num_segments * dim + num_params;
+
+    let mut x0 = DVector::zeros(num_unknowns);
+    for (k, &t) in nodes[..num_segments].iter().enumerate() {
+        x0.rows_mut(k * dim, dim).copy_from(&(input.guess)(t));
+    }
+    x0.rows_mut(num_segments * dim, num_params)
+        .copy_from(input.p);
+
+    let unpack = |x: &DVector<f64>| {
+        let states: Vec<DVector<f64>> = (0..num_segments)
+            .map(|k| x.rows(k * dim, dim).into_owned())
+            .collect();
+        (states, x.rows(num_segments * dim, num_params).into_owned())
+    };
+
+    let residual_from = |x: &DVector<f64>, ends: &[DVector<f64>]| {
+        let (states, p) = unpack(x);
+        let mut r = DVector::zeros(num_unknowns);
+        for k in 0..num_segments - 1 {
+            r.rows_mut(k * dim, dim)
+                .copy_from(&(&ends[k] - &states[k + 1]));
+        }
+        r.rows_mut((num_segments - 1) * dim, dim + num_params)
+            .copy_from(&(input.boundary)(&states[0], &ends[num_segments - 1], &p));
+        r
+    };
+
+    let residual = |x: &DVector<f64>| {
+        let (states, p) = unpack(x);
+        let ends = (0..num_segments)
+            .map(|k| {
+                let f = |t, y: &DVector<f64>| (input.f)(t, y, &p);
+                let output = dopri5::integrate(
+                    &dopri5::Input {
+                        t_span: [nodes[k], nodes[k + 1]],
+                        y0: &states[k],
+                        h0: input.h0,
+                        f: &f,
+                    },
+                    config,
+                )?;
+                Ok(output.y)
+            })
+            .collect::<Result<Vec<_>, Error>>()?;
+        Ok(residual_from(x, &ends))
+    };
+
+    let jacobian = |x: &DVector<f64>| -> Result<DMatrix<f64>, Error> {
+        let (states, p) = unpack(x);
+        let segments = (0..num_segments)
+            .map(|k| {
+                sensitivity::integrate(
+                    &sensitivity::Input {
+                        t_span: [nodes[k], nodes[k + 1]],
+                        y0: &states[k],
+                        p: &p,
+                        h0: input.h0,
+                        f: input.f,
+                        jacobian_y: input.jacobian_y.clone(),
+                        jacobian_p: input.jacobian_p.clone(),
+                        error_control: false,
+                    },
+                    config,
+                )
+            })
+            .collect::<Result<Vec<_>, Error>>()?;
+
+        let ends: Vec<_> = segments.iter().map(|segment| segment.y.clone()).collect();
+        let g = residual_from(x, &ends)
+            .rows((num_segments - 1) * dim, dim + num_params)
+            .into_owned();
+        let last = &segments[num_segments - 1];
+        let [g_a, g_b, g_p] = boundary_jacobians(input.boundary, &states[0], &last.y, &p, &g);
+
+        let mut jacobian = DMatrix::zeros(num_unknowns, num_unknowns);
+        let params = num_segments * dim;
+        for (k, segment) in segments[..num_segments - 1].iter().enumerate() {
+            let row = k * dim;
+            jacobian
+                .view_mut((row, row), (dim, dim))
+                .copy_from(&segment.dy_dy0);
+            jacobian
+                .view_mut((row, row + dim), (dim, dim))
+                .fill_diagonal(-1.0);
+            jacobian
+                .view_mut((row, params), (dim, num_params))
+                .copy_from(&segment.dy_dp);
+        }
+
+        // Boundary rows: y(b) depends on the last segment's start and on the parameters.
+        let row = (num_segments - 1) * dim;
+        let rows = dim + num_params;
+        let mut first = jacobian.view_mut((row, 0), (rows, dim));
+        first += &g_a;
+        let mut last_start = jacobian.view_mut((row, row), (rows, dim));
+        last_start += &g_b * &last.dy_dy0;
+        jacobian
+            .view_mut((row, params), (rows, num_params))
+            .copy_from(&(g_p + &g_b * &last.dy_dp));
+        Ok(jacobian)
+    };
+
+    let (x, num_iterations) = newton(
+        x0,
+        residual,
+        |x, r| Ok(jacobian(x)?.lu().solve(&-r)),
+        input.tolerance,
+        input.max_iterations,
+    )?;
+
+    // Recover the end state, which is not an unknown.
+    let (mut y, p) = unpack(&x);
+    let f = |t, y: &DVector<f64>| (input.f)(t, y, &p);
+    let end = dopri5::integrate(
+        &dopri5::Input {
+            t_span: [nodes[num_segments - 1], t1],
+            y0: &y[num_segments - 1],
+            h0: input.h0,
+            f: &f,
+        },
+        config,
+    )?;
+    y.push(end.y);
+
+    Ok(Output {
+        t: nodes,
+        y,
+        p,
+        num_iterations,
+    })
+}
@@ -0,0 +1,173 @@
+use std::cell::RefCell;
+use std::fmt;
+
+use nalgebra::DVector;
+
+use super::dopri5::{self, Config, DenseOutput};
+use super::{Error, InputError};
+
+/// Initial data y(t) for t <= `t_span[0]`.
+pub type HistoryFunc<'a> = dyn Fn(f64) -> DVector<f64> + 'a;
+
+/// y'(t) = f(t, y(t), history), where `history.at(t - tau)` gives delayed states.
+pub type DelayDerivativeFunc<'a> = dyn Fn(f64, &DVector<f64>, &History<'_>) -> DVector<f64> + 'a;
+
+/// Solution so far, backed by the initial history function before t0 and by Dormand-Prince dense
+/// output after it.
+pub struct History<'a> {
+    t0: f64,
+    initial: &'a HistoryFunc<'a>,
+    dense: &'a DenseOutput,
+}
+
+impl fmt::Debug for History<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("History")
+            .field("t0", &self.t0)
+            .field("initial", &"HistoryFunc")
+            .field("dense", self.dense)
+            .finish()
+    }
+}
+
+impl History<'_> {
+    /// Times past the last accepted step, only reachable with state dependent delays shorter than
+    /// the step, extrapolate the last step.
+    #[must_use]
+    pub fn at(&self, t: f64) -> DVector<f64> {
+        match self.dense.segment(t) {
+            Some(segment) if t > self.t0 => segment.evaluate(t),
+            _ => (self.initial)(t.min(self.t0)),
+        }
+    }
+}
+
+pub struct Input<'a> {
+    pub t_span: [f64; 2],
+    pub history: &'a HistoryFunc<'a>,
+    pub h0: f64,
+    pub f: &'a DelayDerivativeFunc<'a>,
+    /// Constant delays. Steps are limited to the smallest one, and land on the points where
+    /// derivative discontinuities from t0 propagate. State dependent delays need not be listed.
+    pub delays: &'a [f64],
+}
+
+impl fmt::Debug for Input<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Input")
+            .field("t_span", &self.t_span)
+            .field("history", &"HistoryFunc")
+            .field("h0", &self.h0)
+            .field("f", &"DelayDerivativeFunc")
+            .field("delays", &self.delays)
+            .finish()
+    }
+}
+
+#[derive(Debug)]
+pub struct Output {
+    pub y: DVector<f64>,
+    pub h: f64,
+    pub num_calls: usize,
+    pub num_steps: usize,
+    pub num_rejected: usize,
+    /// Continuous solution over the time span.
+    pub dense: DenseOutput,
+    /// Discontinuity points that were stepped to.
+    pub breakpoints: Vec<f64>,
+}
+
+// A jump in the k-th derivative at t0 is smoothed to the (k + 1)-th after each delay, so beyond
+// this many delays it is below the order of the method.
+const MAX_DISCONTINUITY_ORDER: usize = 5;
+
+// Points t0 + sum of up to MAX_DISCONTINUITY_ORDER constant delays, inside the time span.
+fn breakpoints(t_span: [f64; 2], delays: &[f64]) -> Vec<f64> {
+    let tolerance = 1e-12 * t_span[1].abs().max(1.0);
+    let mut level = vec![t_span[0]];
+    let mut points = Vec::new();
+    for _ in 0..MAX_DISCONTINUITY_ORDER {
+        level = level
+            .iter()
+            .flat_map(|t| delays.iter().map(move |tau| t + tau))
+            .filter(|&t| t < t_span[1] - tolerance)
+            .collect();
+        level.sort_by(f64::total_cmp);
+        level.dedup_by(|a, b| (*a - *b).abs() <= tolerance);
+        points.extend_from_slice(&level);
+    }
+    points.sort_by(f64::total_cmp);
+    points.dedup_by(|a, b| (*a - *b).abs() <= tolerance);
+    points.push(t_span[1]);
+    points
+}
+
+pub fn integrate(input: &Input<'_>, config: &Config) -> Result<Output, Error> {
+    integrate_with_observer(input, config, &mut |_, _| {})
+}
+
+/// `observer` gets y0 at `t_span[0]` and then every accepted step, breakpoints included, but none
+/// of the history before `t_span[0]`.
+pub fn integrate_with_observer(
+    input: &Input<'_>,
+    config: &Config,
+    observer: &mut dyn FnMut(f64, &DVector<f64>),
+) -> Result<Output, Error> {
+    if input.delays.iter().any(|&tau| tau <= 0.0) {
+        return Err(InputError::Delay.into());
+    }
+    let max_step = input.delays.iter().copied().fold(f64::INFINITY, f64::min);
+
+    let t0 = input.t_span[0];
+    let y0 = (input.history)(t0);
+    let dense = RefCell::new(DenseOutput::new());
+    let f = |t: f64, y: &DVector<f64>| {
+        let dense = dense.borrow();
+        let history = History {
+            t0,
+            initial: input.history,
+            dense: &dense,
+        };
+        (input.f)(t, y, &history)
+    };
+
+    let breakpoints = breakpoints(input.t_span, input.delays);
+    let mut state = dopri5::State::new(&dopri5::Input {
+        t_span: [t0, breakpoints[0]],
+        y0: &y0,
+        h0: input.h0,
+        f: &f,
+    })?;
+    observer(t0, &y0);
+
+    // Integrate between consecutive breakpoints, restarting the FSAL derivative at each since the
+    // solution is not smooth there.
+    for &t_end in &breakpoints {
+        let section = dopri5::Input {
+            t_span: [state.t, t_end],
+            y0: &y0,
+            h0: input.h0,
+            f: &f,
+        };
+        state.k1 = None;
+        while !state.is_finished(&section) {
+            state.h = state.h.min(max_step);
+            let mut step = DenseOutput::new();
+            state.step_dense(&section, config, &mut step)?;
+            dense.borrow_mut().segments.append(&mut step.segments);
+            observer(state.t, &state.y);
+        }
+    }
+
+    let mut breakpoints = breakpoints;
+    breakpoints.pop();
+    Ok(Output {
+        y: state.y,
+        h: state.h,
+        num_calls: state.num_calls,
+        num_steps: state.num_steps,
+        num_rejected: state.num_rejected,
+        dense: dense.into_inner(),
+        breakpoints,
+    })
+}
@@ -1,14 +1,24 @@
 use std::fmt;
+#[cfg(feature = "serde")]
+use std::fs;
+#[cfg(feature = "serde")]
+use std::io::{self, Write};
+#[cfg(feature = "serde")]
+use std::path::Path;
 
 use nalgebra::DVector;
+use uom::si::f64::Time;
+use uom::si::time::second;
 
-use super::{DerivativeFunc, Error, InputError};
+use super::{error_ratio, validate_span, DerivativeFunc, Error};
+use crate::contig::{self, Layout};
+use crate::units::{StateElement, UnitState};
 
 pub struct Input<'a> {
     pub t_span: [f64; 2],
     pub y0: &'a DVector<f64>,
     pub h0: f64,
-    pub f: &'a DerivativeFunc,
+    pub f: &'a DerivativeFunc<'a>,
 }
 
 impl fmt::Debug for Input<'_> {
@@ -33,141 +43,425 @@ pub struct Output {
     pub y: DVector<f64>,
     pub h: f64,
     pub num_calls: usize,
+    pub num_steps: usize,
+    pub num_rejected: usize,
+    /// Number of attempted steps whose size was limited by each element.
+    pub num_limited: Vec<usize>,
+}
+
+impl Output {
+    /// Elements that limited the step size, most frequent first.
+    #[must_use]
+    pub fn limiting_elements(&self, layout: Option<&Layout>) -> Vec<(String, usize)> {
+        let mut limiting: Vec<_> = (0..self.num_limited.len())
+            .filter(|&i| self.num_limited[i] > 0)
+            .collect();
+        limiting.sort_by_key(|&i| std::cmp::Reverse(self.num_limited[i]));
+        limiting
+            .into_iter()
+            .map(|i| (contig::describe_element(layout, i), self.num_limited[i]))
+            .collect()
+    }
+}
+
+/// Fourth order continuous extension of one accepted step over [t, t + h].
+#[derive(Clone, Debug)]
+pub struct Segment {
+    pub t: f64,
+    pub h: f64,
+    coefficients: [DVector<f64>; 5],
+}
+
+impl Segment {
+    #[must_use]
+    pub fn evaluate(&self, t: f64) -> DVector<f64> {
+        let [c1, c2, c3, c4, c5] = &self.coefficients;
+        let theta = (t - self.t) / self.h;
+        let theta1 = 1.0 - theta;
+        c1 + theta * (c2 + theta1 * (c3 + theta * (c4 + theta1 * c5)))
+    }
+}
+
+/// Continuous solution over all accepted steps, in order of increasing time.
+#[derive(Clone, Debug, Default)]
+pub struct DenseOutput {
+    pub segments: Vec<Segment>,
+}
+
+impl DenseOutput {
+    #[must_use]
+    pub fn new() -> DenseOutput {
+        DenseOutput::default()
+    }
+
+    /// Segment covering t, or the first or last segment when t is outside the integrated span.
+    #[must_use]
+    pub fn segment(&self, t: f64) -> Option<&Segment> {
+        let index = self
+            .segments
+            .partition_point(|segment| segment.t + segment.h < t);
+        self.segments
+            .get(index.min(self.segments.len().saturating_sub(1)))
+    }
+
+    /// # Panics
+    /// If there are no segments.
+    #[must_use]
+    pub fn evaluate(&self, t: f64) -> DVector<f64> {
+        self.segment(t).expect("dense output is empty").evaluate(t)
+    }
 }
 
 pub fn integrate(input: &Input<'_>, config: &Config) -> Result<Output, Error> {
-    validate_input(input)?;
-
-    let mut t = input.t_span[0];
-    let mut y = input.y0.clone();
-    let mut h = input.h0;
-    let mut k1: Option<DVector<f64>> = None;
-    let mut num_calls = 0;
-    let mut num_failures = 0;
-
-    loop {
-        h = h.min(input.t_span[1] - t);
-        let t_next = t + h;
-
-        let step_output = dopri5_step(t, &y, input.f, h, &k1);
-        num_calls += step_output.num_calls;
-
-        // h step size control.
-        let error = step_output.error.abs();
-        let allowed_error = (config.rel_tol * step_output.y.abs()).map(|x| x.max(config.abs_tol));
-
-        const MIN_ERROR_RATIO: f64 = 1e-5; // (1/10)^5, 10x decrease in h.
-        const MAX_ERROR_RATIO: f64 = 1e5; // 10^5, 10x increase in h.
-
-        let error_ratio = allowed_error
-            .zip_map(&error, |a, b| {
-                (a / b).clamp(MIN_ERROR_RATIO, MAX_ERROR_RATIO)
-            })
-            .amin();
-
-        h = 0.9 * h * error_ratio.powf(1.0 / 5.0);
-
-        // Discard step if error is too high.
-        if error_ratio < 1.0 {
-            num_failures += 1;
-            if num_failures > 10 {
-                return Err(Error::Convergence);
+    integrate_with_observer(input, config, &mut |_, _| {})
+}
+
+/// The observer is called with the initial state and then after every accepted step.
+pub fn integrate_with_observer(
+    input: &Input<'_>,
+    config: &Config,
+    observer: &mut dyn FnMut(f64, &DVector<f64>),
+) -> Result<Output, Error> {
+    let state = State::new(input)?;
+    observer(state.t, &state.y);
+    integrate_from(state, input, config, observer)
+}
+
+pub fn integrate_dense(input: &Input<'_>, config: &Config) -> Result<(Output, DenseOutput), Error> {
+    let mut state = State::new(input)?;
+    let mut dense = DenseOutput::new();
+    while !state.is_finished(input) {
+        state.step_dense(input, config, &mut dense)?;
+    }
+    Ok((state.into_output(), dense))
+}
+
+/// Continues integration from a previously saved state to the end of `input.t_span`; `input.y0` and
+/// `input.h0` are ignored. The observer is only called after accepted steps.
+pub fn integrate_from(
+    mut state: State,
+    input: &Input<'_>,
+    config: &Config,
+    observer: &mut dyn FnMut(f64, &DVector<f64>),
+) -> Result<Output, Error> {
+    while !state.is_finished(input) {
+        state.step(input, config)?;
+        observer(state.t, &state.y);
+    }
+    Ok(state.into_output())
+}
+
+/// Complete solver state between accepted steps. Resuming from a copy of the state reproduces the
+/// uninterrupted integration exactly.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct State {
+    pub t: f64,
+    pub y: DVector<f64>,
+    pub h: f64,
+    /// Derivative at (t, y) carried over from the last step. (FSAL)
+    pub k1: Option<DVector<f64>>,
+    /// Consecutive rejected steps, reset on acceptance.
+    pub num_failures: usize,
+    pub num_calls: usize,
+    pub num_steps: usize,
+    pub num_rejected: usize,
+    pub num_limited: Vec<usize>,
+}
+
+impl State {
+    pub fn new(input: &Input<'_>) -> Result<State, Error> {
+        validate_span(input.t_span, input.h0)?;
+        Ok(State {
+            t: input.t_span[0],
+            y: input.y0.clone(),
+            h: input.h0,
+            k1: None,
+            num_failures: 0,
+            num_calls: 0,
+            num_steps: 0,
+            num_rejected: 0,
+            num_limited: vec![0; input.y0.len()],
+        })
+    }
+
+    #[must_use]
+    pub fn is_finished(&self, input: &Input<'_>) -> bool {
+        self.t >= input.t_span[1]
+    }
+
+    /// Attempts steps until one is accepted.
+    pub fn step(&mut self, input: &Input<'_>, config: &Config) -> Result<(), Error> {
+        self.step_controlled(input, config, self.y.len())
+    }
+
+    /// As `step`, also appending the continuous extension of the accepted step to `dense`.
+    pub fn step_dense(
+        &mut self,
+        input: &Input<'_>,
+        config: &Config,
+        dense: &mut DenseOutput,
+    ) -> Result<(), Error> {
+        self.advance(input, config, self.y.len(), Some(dense))
+    }
+
+    // As `step`, with only the first `num_controlled` elements taking part in step size control.
+    pub(super) fn step_controlled(
+        &mut self,
+        input: &Input<'_>,
+        config: &Config,
+        num_controlled: usize,
+    ) -> Result<(), Error> {
+        self.advance(input, config, num_controlled, None)
+    }
+
+    fn advance(
+        &mut self,
+        input: &Input<'_>,
+        config: &Config,
+        num_controlled: usize,
+        mut dense: Option<&mut DenseOutput>,
+    ) -> Result<(), Error> {
+        loop {
+            self.h = self.h.min(input.t_span[1] - self.t);
+            let t_next = self.t + self.h;
+            // The step has underflowed, typically approaching a singularity of the solution.
+            if t_next <= self.t {
+                let element = (0..self.num_limited.len())
+                    .max_by_key(|&i| self.num_limited[i])
+                    .unwrap_or(0);
+                return Err(Error::Convergence {
+                    t: self.t,
+                    h: self.h,
+                    element,
+                });
             }
 
-            continue;
-        }
-        num_failures = 0;
+            let step_output = dopri5_step(
+                self.t,
+                &self.y,
+                input.f,
+                self.h,
+                self.k1.as_ref(),
+                dense.is_some(),
+            );
+            self.num_calls += step_output.num_calls;
+
+            // h step size control. A non-finite step, from a solution blowing up, is rejected
+            // rather than accepted on a NaN ratio.
+            let allowed_error = (config.rel_tol * step_output.y.rows(0, num_controlled).abs())
+                .map(|x| x.max(config.abs_tol));
+            let (error_ratio, limiting_element) = error_ratio(
+                &allowed_error,
+                &step_output.error.rows(0, num_controlled).into_owned(),
+                MIN_ERROR_RATIO,
+                MAX_ERROR_RATIO,
+            );
+            // Steps with every ratio at its bound are not limited by any element.
+            if error_ratio < MAX_ERROR_RATIO {
+                self.num_limited[limiting_element] += 1;
+            }
 
-        // Propagate state.
-        t = t_next;
-        y = step_output.y;
-        k1 = Some(step_output.k7); // First same as last property. [FSAL]
+            let h_attempted = self.h;
+            self.h = 0.9 * self.h * error_ratio.powf(1.0 / 5.0);
+
+            // Discard step if error is too high.
+            if error_ratio < 1.0 {
+                self.num_rejected += 1;
+                self.num_failures += 1;
+                if self.num_failures > 10 {
+                    return Err(Error::Convergence {
+                        t: self.t,
+                        h: h_attempted,
+                        element: limiting_element,
+                    });
+                }
+
+                continue;
+            }
+            self.num_failures = 0;
+            self.num_steps += 1;
 
-        // Terminate integration.
-        if t >= input.t_span[1] {
-            break;
+            // Propagate state.
+            if let (Some(dense), Some(segment)) = (dense.as_deref_mut(), step_output.segment) {
+                dense.segments.push(segment);
+            }
+            self.t = t_next;
+            self.y = step_output.y;
+            self.k1 = Some(step_output.k7); // First same as last property. [FSAL]
+            return Ok(());
         }
     }
 
-    Ok(Output { y, h, num_calls })
-}
+    #[must_use]
+    pub fn into_output(self) -> Output {
+        Output {
+            y: self.y,
+            h: self.h,
+            num_calls: self.num_calls,
+            num_steps: self.num_steps,
+            num_rejected: self.num_rejected,
+            num_limited: self.num_limited,
+        }
+    }
 
-fn validate_input(input: &Input<'_>) -> Result<(), InputError> {
-    if input.t_span[0] > input.t_span[1] {
-        return Err(InputError::TimeSpan);
+    /// Writes a checkpoint as JSON, going through a temporary file so an interrupted save never
+    /// leaves a truncated checkpoint behind. Floats round-trip exactly.
+    #[cfg(feature = "serde")]
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let tmp = path.with_extension("tmp");
+        let mut writer = io::BufWriter::new(fs::File::create(&tmp)?);
+        serde_json::to_writer(&mut writer, self)?;
+        writer.flush()?;
+        writer.get_ref().sync_all()?;
+        drop(writer);
+        fs::rename(tmp, path)
     }
-    if input.h0 <= 0.0 {
-        return Err(InputError::StepSize);
+
+    #[cfg(feature = "serde")]
+    pub fn load(path: &Path) -> io::Result<State> {
+        let reader = io::BufReader::new(fs::File::open(path)?);
+        Ok(serde_json::from_reader(reader)?)
+    }
+}
+
+pub struct UnitInput<'a, S: UnitState> {
+    pub t_span: [Time; 2],
+    pub y0: &'a S,
+    pub h0: Time,
+    pub f: &'a dyn Fn(Time, &S) -> S::Derivative,
+}
+
+impl<S: UnitState> fmt::Debug for UnitInput<'_, S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("UnitInput")
+            .field("t_span", &self.t_span)
+            .field("y0", &"UnitState")
+            .field("h0", &self.h0)
+            .field("f", &"Fn(Time, &S) -> S::Derivative")
+            .finish()
     }
-    Ok(())
 }
 
-struct StepOutput {
-    y: DVector<f64>,
-    error: DVector<f64>,
-    k7: DVector<f64>,
-    num_calls: usize,
+#[derive(Debug)]
+pub struct UnitOutput<S> {
+    pub y: S,
+    pub h: Time,
+    pub num_calls: usize,
+}
+
+pub fn integrate_units<S: UnitState>(
+    input: &UnitInput<'_, S>,
+    config: &Config,
+) -> Result<UnitOutput<S>, Error> {
+    let mut y0 = DVector::zeros(S::LEN);
+    input.y0.pack(y0.as_mut_slice());
+
+    // Derivatives are packed in base units, so they integrate directly into base unit states.
+    let f = |t: f64, y: &DVector<f64>| {
+        let dydt = (input.f)(Time::new::<second>(t), &S::unpack(y.as_slice()));
+        let mut out = DVector::zeros(S::Derivative::LEN);
+        dydt.pack(out.as_mut_slice());
+        out
+    };
+
+    let output = integrate(
+        &Input {
+            t_span: input.t_span.map(|t| t.get::<second>()),
+            y0: &y0,
+            h0: input.h0.get::<second>(),
+            f: &f,
+        },
+        config,
+    )?;
+
+    Ok(UnitOutput {
+        y: S::unpack(output.y.as_slice()),
+        h: Time::new::<second>(output.h),
+        num_calls: output.num_calls,
+    })
+}
+
+pub(super) struct StepOutput {
+    pub(super) y: DVector<f64>,
+    pub(super) error: DVector<f64>,
+    pub(super) k7: DVector<f64>,
+    pub(super) num_calls: usize,
+    pub(super) segment: Option<Segment>,
 }
 
-#[allow(clippy::too_many_lines)]
-fn dopri5_step(
+pub(super) const MIN_ERROR_RATIO: f64 = 1e-5; // (1/10)^5, 10x decrease in h.
+pub(super) const MAX_ERROR_RATIO: f64 = 1e5; // 10^5, 10x increase in h.
+
+pub(super) const C_COEFF: [f64; 7] = [0.0, 1.0 / 5.0, 3.0 / 10.0, 4.0 / 5.0, 8.0 / 9.0, 1.0, 1.0];
+pub(super) const A_COEFF: [[f64; 6]; 6] = [
+    [1.0 / 5.0, 0.0, 0.0, 0.0, 0.0, 0.0],
+    [3.0 / 40.0, 9.0 / 40.0, 0.0, 0.0, 0.0, 0.0],
+    [44.0 / 45.0, -56.0 / 15.0, 32.0 / 9.0, 0.0, 0.0, 0.0],
+    [
+        19372.0 / 6561.0,
+        -25360.0 / 2187.0,
+        64448.0 / 6561.0,
+        -212.0 / 729.0,
+        0.0,
+        0.0,
+    ],
+    [
+        9017.0 / 3168.0,
+        -355.0 / 33.0,
+        46732.0 / 5247.0,
+        49.0 / 176.0,
+        -5103.0 / 18656.0,
+        0.0,
+    ],
+    [
+        35.0 / 384.0,
+        0.0,
+        500.0 / 1113.0,
+        125.0 / 192.0,
+        -2187.0 / 6784.0,
+        11.0 / 84.0,
+    ],
+];
+pub(super) const B_COEFF: [[f64; 7]; 2] = [
+    [
+        35.0 / 384.0,
+        0.0,
+        500.0 / 1113.0,
+        125.0 / 192.0,
+        -2187.0 / 6784.0,
+        11.0 / 84.0,
+        0.0,
+    ],
+    [
+        5179.0 / 57600.0,
+        0.0,
+        7571.0 / 16695.0,
+        393.0 / 640.0,
+        -92097.0 / 339200.0,
+        187.0 / 2100.0,
+        1.0 / 40.0,
+    ],
+];
+
+pub(super) const D_COEFF: [f64; 7] = [
+    -12715105075.0 / 11282082432.0,
+    0.0,
+    87487479700.0 / 32700410799.0,
+    -10690763975.0 / 1880347072.0,
+    701980252875.0 / 199316789632.0,
+    -1453857185.0 / 822651844.0,
+    69997945.0 / 29380423.0,
+];
+
+pub(super) fn dopri5_step(
     t: f64,
     y: &DVector<f64>,
-    f: &DerivativeFunc,
+    f: &DerivativeFunc<'_>,
     h: f64,
-    k1: &Option<DVector<f64>>,
+    k1: Option<&DVector<f64>>,
+    dense: bool,
 ) -> StepOutput {
-    const C_COEFF: [f64; 7] = [0.0, 1.0 / 5.0, 3.0 / 10.0, 4.0 / 5.0, 8.0 / 9.0, 1.0, 1.0];
-    const A_COEFF: [[f64; 6]; 6] = [
-        [1.0 / 5.0, 0.0, 0.0, 0.0, 0.0, 0.0],
-        [3.0 / 40.0, 9.0 / 40.0, 0.0, 0.0, 0.0, 0.0],
-        [44.0 / 45.0, -56.0 / 15.0, 32.0 / 9.0, 0.0, 0.0, 0.0],
-        [
-            19372.0 / 6561.0,
-            -25360.0 / 2187.0,
-            64448.0 / 6561.0,
-            -212.0 / 729.0,
-            0.0,
-            0.0,
-        ],
-        [
-            9017.0 / 3168.0,
-            -355.0 / 33.0,
-            46732.0 / 5247.0,
-            49.0 / 176.0,
-            -5103.0 / 18656.0,
-            0.0,
-        ],
-        [
-            35.0 / 384.0,
-            0.0,
-            500.0 / 1113.0,
-            125.0 / 192.0,
-            -2187.0 / 6784.0,
-            11.0 / 84.0,
-        ],
-    ];
-    const B_COEFF: [[f64; 7]; 2] = [
-        [
-            35.0 / 384.0,
-            0.0,
-            500.0 / 1113.0,
-            125.0 / 192.0,
-            -2187.0 / 6784.0,
-            11.0 / 84.0,
-            0.0,
-        ],
-        [
-            5179.0 / 57600.0,
-            0.0,
-            7571.0 / 16695.0,
-            393.0 / 640.0,
-            -92097.0 / 339200.0,
-            187.0 / 2100.0,
-            1.0 / 40.0,
-        ],
-    ];
-
     let num_calls = match k1 {
         Some(_) => 6,
         None => 7,
@@ -175,12 +469,11 @@ fn dopri5_step(
 
     // Lazily initialize k1 if it is not provided.
     let default_k1;
-    let k1 = match k1 {
-        Some(k1) => k1,
-        None => {
-            default_k1 = f(t, y);
-            &default_k1
-        }
+    let k1 = if let Some(k1) = k1 {
+        k1
+    } else {
+        default_k1 = f(t, y);
+        &default_k1
     };
 
     let k2 = f(t + C_COEFF[1] * h, &(y + (h * A_COEFF[0][0]) * k1));
@@ -233,10 +526,30 @@ fn dopri5_step(
 
     let error = &fifth_order - fourth_order;
 
+    // Dense output coefficients, as in Hairer's DOPRI5.
+    let segment = dense.then(|| {
+        let difference = &fifth_order - y;
+        let b_spline = h * k1 - &difference;
+        let c4 = &difference - h * &k7 - &b_spline;
+        let c5 = h
+            * (D_COEFF[0] * k1
+                + D_COEFF[2] * &k3
+                + D_COEFF[3] * &k4
+                + D_COEFF[4] * &k5
+                + D_COEFF[5] * &k6
+                + D_COEFF[6] * &k7);
+        Segment {
+            t,
+            h,
+            coefficients: [y.clone(), difference, b_spline, c4, c5],
+        }
+    });
+
     StepOutput {
         y: fifth_order,
         error,
         k7,
         num_calls,
+        segment,
     }
 }
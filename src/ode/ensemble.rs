@@ -0,0 +1,250 @@
+use std::fmt;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+
+use nalgebra::{DMatrix, DVector};
+
+use super::dopri5::{self, DenseOutput};
+use super::hybrid::{self, Direction, GuardFunc};
+use super::rng::Rng;
+use super::{DerivativeFunc, Error};
+
+/// A single dispersed trajectory produced by the ensemble generator.
+pub struct Member<'a> {
+    pub t_span: [f64; 2],
+    pub y0: DVector<f64>,
+    pub h0: f64,
+    pub f: Box<DerivativeFunc<'a>>,
+    /// Events to locate along the trajectory, if any.
+    pub detectors: Vec<Detector<'a>>,
+}
+
+impl fmt::Debug for Member<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Member")
+            .field("t_span", &self.t_span)
+            .field("y0", &self.y0)
+            .field("h0", &self.h0)
+            .field("f", &"DerivativeFunc")
+            .field("detectors", &self.detectors)
+            .finish()
+    }
+}
+
+/// Zero crossing of `guard` in `direction`, located as for hybrid transitions but without changing
+/// the trajectory.
+pub struct Detector<'a> {
+    pub guard: Box<GuardFunc<'a>>,
+    pub direction: Direction,
+}
+
+impl fmt::Debug for Detector<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Detector")
+            .field("guard", &"GuardFunc")
+            .field("direction", &self.direction)
+            .finish()
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Event {
+    pub t: f64,
+    /// Index into the member's `detectors`.
+    pub detector: usize,
+    pub y: DVector<f64>,
+}
+
+#[derive(Debug)]
+pub struct Config {
+    pub num_members: usize,
+    pub seed: u64,
+    /// Zero uses all available cores.
+    pub num_threads: usize,
+}
+
+#[derive(Debug)]
+pub struct Ensemble {
+    /// Indexed by member, in generation order.
+    pub results: Vec<Result<dopri5::Output, Error>>,
+    /// Located events of each member in time order, including those before a failure.
+    pub events: Vec<Vec<Event>>,
+}
+
+fn integrate(
+    member: &Member<'_>,
+    solver: &dopri5::Config,
+) -> (Result<dopri5::Output, Error>, Vec<Event>) {
+    let input = dopri5::Input {
+        t_span: member.t_span,
+        y0: &member.y0,
+        h0: member.h0,
+        f: &member.f,
+    };
+    let mut events = Vec::new();
+    if member.detectors.is_empty() {
+        return (dopri5::integrate(&input, solver), events);
+    }
+
+    let mut state = match dopri5::State::new(&input) {
+        Ok(state) => state,
+        Err(err) => return (Err(err), events),
+    };
+    while !state.is_finished(&input) {
+        let start: Vec<f64> = member
+            .detectors
+            .iter()
+            .map(|detector| (detector.guard)(state.t, &state.y))
+            .collect();
+        let mut dense = DenseOutput::new();
+        if let Err(err) = state.step_dense(&input, solver, &mut dense) {
+            return (Err(err), events);
+        }
+        let Some(segment) = dense.segments.pop() else {
+            continue;
+        };
+
+        // Every crossing within the step, not only the first.
+        let num_located = events.len();
+        for (i, detector) in member.detectors.iter().enumerate() {
+            let mut after = f64::NEG_INFINITY;
+            while let Some(t) = hybrid::first_crossing(
+                &detector.guard,
+                detector.direction,
+                &segment,
+                start[i],
+                (state.t, &state.y),
+                after,
+            ) {
+                events.push(Event {
+                    t,
+                    detector: i,
+                    y: segment.evaluate(t),
+                });
+                after = t + hybrid::root_tolerance(t);
+            }
+        }
+        events[num_located..].sort_by(|a, b| a.t.total_cmp(&b.t));
+    }
+    (Ok(state.into_output()), events)
+}
+
+/// Generates and integrates `config.num_members` members across a pool of scoped threads. Each
+/// member gets its own generator seeded from the ensemble seed and its index, so results do not
+/// depend on which thread runs it.
+///
+/// # Panics
+/// If the generator or a derivative function panics.
+pub fn run<'a, G>(generator: G, solver: &dopri5::Config, config: &Config) -> Ensemble
+where
+    G: Fn(usize, &mut Rng) -> Member<'a> + Sync,
+{
+    let num_threads = match config.num_threads {
+        0 => thread::available_parallelism().map_or(1, std::num::NonZero::get),
+        n => n,
+    }
+    .min(config.num_members.max(1));
+
+    let next = AtomicUsize::new(0);
+    let results = Mutex::new((0..config.num_members).map(|_| None).collect::<Vec<_>>());
+
+    thread::scope(|scope| {
+        for _ in 0..num_threads {
+            scope.spawn(|| loop {
+                let index = next.fetch_add(1, Ordering::Relaxed);
+                if index >= config.num_members {
+                    break;
+                }
+
+                let member = generator(index, &mut Rng::for_stream(config.seed, index));
+                results.lock().unwrap()[index] = Some(integrate(&member, solver));
+            });
+        }
+    });
+
+    let (results, events) = results
+        .into_inner()
+        .unwrap()
+        .into_iter()
+        .map(|result| result.expect("every member is integrated"))
+        .unzip();
+    Ensemble { results, events }
+}
+
+impl Ensemble {
+    /// Final states of the members that integrated successfully.
+    #[must_use]
+    pub fn final_states(&self) -> Vec<&DVector<f64>> {
+        self.results
+            .iter()
+            .filter_map(|result| result.as_ref().ok().map(|output| &output.y))
+            .collect()
+    }
+
+    /// Time of the first event of `detector` in each member where it was located.
+    #[must_use]
+    pub fn first_event_times(&self, detector: usize) -> Vec<f64> {
+        self.events
+            .iter()
+            .filter_map(|events| events.iter().find(|event| event.detector == detector))
+            .map(|event| event.t)
+            .collect()
+    }
+
+    #[must_use]
+    pub fn num_failed(&self) -> usize {
+        self.results.iter().filter(|result| result.is_err()).count()
+    }
+
+    /// Statistics below are over successful members and are `None` when there are too few.
+    #[allow(clippy::cast_precision_loss)]
+    #[must_use]
+    pub fn mean(&self) -> Option<DVector<f64>> {
+        let states = self.final_states();
+        let first = states.first()?;
+        let sum = states
+            .iter()
+            .fold(DVector::zeros(first.len()), |sum, y| sum + *y);
+        Some(sum / states.len() as f64)
+    }
+
+    /// Sample covariance of the final states.
+    #[allow(clippy::cast_precision_loss)]
+    #[must_use]
+    pub fn covariance(&self) -> Option<DMatrix<f64>> {
+        let states = self.final_states();
+        if states.len() < 2 {
+            return None;
+        }
+        let mean = self.mean()?;
+        let n = mean.len();
+        let sum = states.iter().fold(DMatrix::zeros(n, n), |sum, y| {
+            let d = *y - &mean;
+            sum + &d * d.transpose()
+        });
+        Some(sum / (states.len() - 1) as f64)
+    }
+
+    /// Per-element percentile, p in [0, 100], interpolating linearly between order statistics.
+    #[allow(
+        clippy::cast_precision_loss,
+        clippy::cast_possible_truncation,
+        clippy::cast_sign_loss
+    )]
+    #[must_use]
+    pub fn percentile(&self, p: f64) -> Option<DVector<f64>> {
+        let states = self.final_states();
+        let first = states.first()?;
+        let rank = p.clamp(0.0, 100.0) / 100.0 * (states.len() - 1) as f64;
+        let lower = rank.floor() as usize;
+        let upper = rank.ceil() as usize;
+        let fraction = rank - rank.floor();
+
+        Some(DVector::from_fn(first.len(), |i, _| {
+            let mut values: Vec<f64> = states.iter().map(|y| y[i]).collect();
+            values.sort_by(f64::total_cmp);
+            values[lower] + fraction * (values[upper] - values[lower])
+        }))
+    }
+}
@@ -0,0 +1,324 @@
+use std::fmt;
+
+use nalgebra::{DMatrix, DVector};
+
+use super::dopri5::Config;
+use super::jacobian::Jacobian;
+use super::{
+    error_ratio, validate_span, DerivativeFunc, Error, MAX_ERROR_RATIO_4, MIN_ERROR_RATIO_4,
+};
+
+/// How the actions of the matrix functions `phi_k(h A)` on vectors are computed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Action {
+    /// Pade approximant with scaling and squaring of a dense matrix of the system size, exact to
+    /// rounding. O(n^3) per action.
+    Pade,
+    /// Arnoldi projection onto a Krylov subspace of the given dimension, with Pade on the small
+    /// projected matrix. O(n^2 m) per action for a dense operator; the dimension needed grows with
+    /// the square root of |h A| for the symmetric negative operators of diffusion.
+    Krylov { dimension: usize },
+}
+
+/// y' = A y + N(t, y) with a stiff constant linear part A.
+pub struct Input<'a> {
+    pub t_span: [f64; 2],
+    pub y0: &'a DVector<f64>,
+    /// Fixed step for ETDRK4, initial step for exprb43.
+    pub h: f64,
+    pub linear: &'a DMatrix<f64>,
+    pub nonlinear: &'a DerivativeFunc<'a>,
+    /// Of the nonlinear part alone. Only used by exprb43.
+    pub jacobian: Jacobian<'a>,
+    pub action: Action,
+}
+
+impl fmt::Debug for Input<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Input")
+            .field("t_span", &self.t_span)
+            .field("y0", self.y0)
+            .field("h", &self.h)
+            .field("linear", self.linear)
+            .field("nonlinear", &"DerivativeFunc")
+            .field("jacobian", &self.jacobian)
+            .field("action", &self.action)
+            .finish()
+    }
+}
+
+#[derive(Debug)]
+pub struct Output {
+    pub y: DVector<f64>,
+    pub h: f64,
+    /// Evaluations of the nonlinear part.
+    pub num_calls: usize,
+    pub num_jacobians: usize,
+    pub num_steps: usize,
+    pub num_rejected: usize,
+}
+
+// sum_k phi_k(h A) v_k over the given v_0, ..., v_p, where phi_0 = exp and
+// phi_{k+1}(z) = (phi_k(z) - 1 / k!) / z. All terms come from one exponential of the augmented
+// matrix [[h A, W], [0, J]] with W = [v_p, ..., v_1] and J the p x p shift, applied to [v_0; e_p]
+// (Al-Mohy & Higham, 2011).
+#[allow(clippy::many_single_char_names)]
+fn phi_combination(a: &DMatrix<f64>, h: f64, v: &[DVector<f64>], action: Action) -> DVector<f64> {
+    let n = a.nrows();
+    let p = v.len() - 1;
+    let mut start = DVector::zeros(n + p);
+    start.rows_mut(0, n).copy_from(&v[0]);
+    if p > 0 {
+        start[n + p - 1] = 1.0;
+    }
+    // x -> [[h A, W], [0, J]] x
+    let augmented = |x: &DVector<f64>| {
+        let mut y = DVector::zeros(n + p);
+        let mut top = h * (a * x.rows(0, n));
+        for j in 0..p {
+            top.axpy(x[n + j], &v[p - j], 1.0);
+            if j + 1 < p {
+                y[n + j] = x[n + j + 1];
+            }
+        }
+        y.rows_mut(0, n).copy_from(&top);
+        y
+    };
+
+    match action {
+        Action::Pade => {
+            let mut matrix = DMatrix::zeros(n + p, n + p);
+            matrix.view_mut((0, 0), (n, n)).copy_from(&(h * a));
+            for j in 0..p {
+                matrix.view_mut((0, n + j), (n, 1)).copy_from(&v[p - j]);
+                if j + 1 < p {
+                    matrix[(n + j, n + j + 1)] = 1.0;
+                }
+            }
+            (matrix.exp() * start).rows(0, n).into_owned()
+        }
+        // The shift block adds p dimensions to the subspace.
+        Action::Krylov { dimension } => krylov_exp(&augmented, &start, dimension + p)
+            .rows(0, n)
+            .into_owned(),
+    }
+}
+
+// exp(M) b ~ |b| V_m exp(H_m) e_1 from m steps of Arnoldi, stopping early on breakdown, where the
+// subspace is invariant and the result exact.
+fn krylov_exp(
+    matrix: &dyn Fn(&DVector<f64>) -> DVector<f64>,
+    b: &DVector<f64>,
+    dimension: usize,
+) -> DVector<f64> {
+    let beta = b.norm();
+    if beta == 0.0 {
+        return b.clone();
+    }
+    let m = dimension.clamp(1, b.len());
+    let mut basis = vec![b / beta];
+    let mut hessenberg = DMatrix::zeros(m + 1, m);
+    let mut size = m;
+    for j in 0..m {
+        let mut w = matrix(&basis[j]);
+        // Modified Gram-Schmidt.
+        for (i, v) in basis.iter().enumerate() {
+            let projection = v.dot(&w);
+            hessenberg[(i, j)] = projection;
+            w.axpy(-projection, v, 1.0);
+        }
+        let norm = w.norm();
+        hessenberg[(j + 1, j)] = norm;
+        if norm <= 1e-12 * beta.max(hessenberg.view((0, j), (j + 1, 1)).amax()) {
+            size = j + 1;
+            break;
+        }
+        basis.push(w / norm);
+    }
+
+    let small = hessenberg.view((0, 0), (size, size)).into_owned().exp();
+    let mut result = DVector::zeros(b.len());
+    for (i, v) in basis.iter().take(size).enumerate() {
+        result.axpy(beta * small[(i, 0)], v, 1.0);
+    }
+    result
+}
+
+/// Cox-Matthews ETDRK4, fourth order for non-stiff and most stiff semi-linear problems. Fixed steps
+/// of `input.h`, the last shortened to end on `t_span[1]`.
+pub fn etdrk4(input: &Input<'_>) -> Result<Output, Error> {
+    etdrk4_with_observer(input, &mut |_, _| {})
+}
+
+/// Fixed steps of h, the last one shortened to end on `t_span[1]`. `observer` sees y0 and each
+/// step.
+#[allow(clippy::many_single_char_names)]
+pub fn etdrk4_with_observer(
+    input: &Input<'_>,
+    observer: &mut dyn FnMut(f64, &DVector<f64>),
+) -> Result<Output, Error> {
+    validate_span(input.t_span, input.h)?;
+    let (a, n, action) = (input.linear, input.nonlinear, input.action);
+    let mut t = input.t_span[0];
+    let mut y = input.y0.clone();
+    let mut num_steps = 0;
+    observer(t, &y);
+
+    while t < input.t_span[1] {
+        let h = input.h.min(input.t_span[1] - t);
+        let half = h / 2.0;
+        let n_y = n(t, &y);
+        let u = phi_combination(a, half, &[y.clone(), half * &n_y], action);
+        let n_u = n(t + half, &u);
+        let v = phi_combination(a, half, &[y.clone(), half * &n_u], action);
+        let n_v = n(t + half, &v);
+        let w = phi_combination(a, half, &[u, half * (2.0 * &n_v - &n_y)], action);
+        let n_w = n(t + h, &w);
+
+        let middle = &n_u + &n_v;
+        y = phi_combination(
+            a,
+            h,
+            &[
+                y,
+                h * &n_y,
+                h * (-3.0 * &n_y + 2.0 * &middle - &n_w),
+                h * (4.0 * (&n_y - &middle + &n_w)),
+            ],
+            action,
+        );
+        t += h;
+        num_steps += 1;
+        observer(t, &y);
+    }
+
+    Ok(Output {
+        y,
+        h: input.h,
+        num_calls: 4 * num_steps,
+        num_jacobians: 0,
+        num_steps,
+        num_rejected: 0,
+    })
+}
+
+pub fn exprb43(input: &Input<'_>, config: &Config) -> Result<Output, Error> {
+    exprb43_with_observer(input, config, &mut |_, _| {})
+}
+
+/// Exponential Rosenbrock exprb43 (Hochbruck, Ostermann & Schweitzer, 2009): the full Jacobian
+/// J = A + N'(y) is linearized at every step, so the remainder g(t, y) = f(t, y) - J y - v t is
+/// small and stiff nonlinearities are also treated exactly. Fourth order with an embedded third
+/// order solution for step size control.
+///
+/// Rejected steps are retried from the same linearization and never reach `observer`, which sees
+/// y0 and then each accepted step.
+#[allow(clippy::many_single_char_names, clippy::too_many_lines)]
+pub fn exprb43_with_observer(
+    input: &Input<'_>,
+    config: &Config,
+    observer: &mut dyn FnMut(f64, &DVector<f64>),
+) -> Result<Output, Error> {
+    validate_span(input.t_span, input.h)?;
+    let (a, action) = (input.linear, input.action);
+    let f = |t: f64, y: &DVector<f64>| a * y + (input.nonlinear)(t, y);
+    let mut t = input.t_span[0];
+    let mut y = input.y0.clone();
+    let mut h = input.h;
+    let mut num_calls = 0;
+    let mut num_jacobians = 0;
+    let mut num_steps = 0;
+    let mut num_rejected = 0;
+    observer(t, &y);
+
+    while t < input.t_span[1] {
+        // Linearization at the start of the step, shared by any rejected attempts.
+        let jacobian = a + input.jacobian.evaluate(input.nonlinear, t, &y);
+        let f0 = f(t, &y);
+        let delta = f64::EPSILON.sqrt() * t.abs().max(1.0);
+        let f_t = (f(t + delta, &y) - &f0) / delta;
+        num_calls += 2;
+        num_jacobians += 1;
+        // g(t + s, z) - g(t, y)
+        let remainder = |s: f64, z: &DVector<f64>, f_z: DVector<f64>| {
+            f_z - &f0 - &jacobian * (z - &y) - s * &f_t
+        };
+
+        let mut num_failures = 0;
+        loop {
+            h = h.min(input.t_span[1] - t);
+            let half = h / 2.0;
+
+            let u = &y
+                + phi_combination(
+                    &jacobian,
+                    half,
+                    &[DVector::zeros(y.len()), half * &f0, half * half * &f_t],
+                    action,
+                );
+            let d_u = remainder(half, &u, f(t + half, &u));
+            let v = &y
+                + phi_combination(
+                    &jacobian,
+                    h,
+                    &[DVector::zeros(y.len()), h * (&f0 + &d_u), h * h * &f_t],
+                    action,
+                );
+            let d_v = remainder(h, &v, f(t + h, &v));
+            num_calls += 2;
+
+            let third = h * (16.0 * &d_u - 2.0 * &d_v);
+            let fourth = h * (-48.0 * &d_u + 12.0 * &d_v);
+            let zero = DVector::zeros(y.len());
+            let y_next = &y
+                + phi_combination(
+                    &jacobian,
+                    h,
+                    &[zero.clone(), h * &f0, h * h * &f_t, third, fourth.clone()],
+                    action,
+                );
+            let error = phi_combination(
+                &jacobian,
+                h,
+                &[zero.clone(), zero.clone(), zero.clone(), zero, fourth],
+                action,
+            );
+
+            // h step size control.
+            let allowed_error = (config.rel_tol * y_next.abs()).map(|x| x.max(config.abs_tol));
+            let (error_ratio, limiting_element) =
+                error_ratio(&allowed_error, &error, MIN_ERROR_RATIO_4, MAX_ERROR_RATIO_4);
+
+            let h_attempted = h;
+            h = 0.9 * h * error_ratio.powf(1.0 / 4.0);
+
+            // Discard step if error is too high.
+            if error_ratio < 1.0 {
+                num_rejected += 1;
+                num_failures += 1;
+                if num_failures > 10 {
+                    return Err(Error::Convergence {
+                        t,
+                        h: h_attempted,
+                        element: limiting_element,
+                    });
+                }
+                continue;
+            }
+            num_steps += 1;
+            t += h_attempted;
+            y = y_next;
+            observer(t, &y);
+            break;
+        }
+    }
+
+    Ok(Output {
+        y,
+        h,
+        num_calls,
+        num_jacobians,
+        num_steps,
+        num_rejected,
+    })
+}
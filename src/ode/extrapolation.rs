@@ -0,0 +1,188 @@
+// Order and step size control shared by the extrapolation solvers, after ODEX and SEULEX (Hairer,
+// Norsett & Wanner, Solving Ordinary Differential Equations I and II).
+
+use nalgebra::DVector;
+
+use super::dopri5::Config;
+use super::Error;
+
+// A base method with an asymptotic error expansion in powers of h^GAMMA.
+pub(super) trait Scheme {
+    // Substeps of every row of the extrapolation table.
+    const SEQUENCE: &'static [usize];
+    const GAMMA: i32;
+
+    // Work of a row of n substeps, in derivative evaluations or equivalent.
+    fn work(n: usize) -> f64;
+
+    // Called once at the start of every step attempt.
+    fn begin(&mut self, t: f64, y: &DVector<f64>);
+
+    // The base method over [t, t + h] in n substeps, or None if the substeps diverge.
+    fn row(&mut self, t: f64, y: &DVector<f64>, h: f64, n: usize) -> Option<DVector<f64>>;
+}
+
+pub(super) struct Output {
+    pub(super) y: DVector<f64>,
+    pub(super) h: f64,
+    // Column of the extrapolation table that was used for the last step.
+    pub(super) column: usize,
+    pub(super) num_steps: usize,
+    pub(super) num_rejected: usize,
+}
+
+const SAFETY: f64 = 0.94;
+const TARGET: f64 = 0.65;
+const MIN_FACTOR: f64 = 0.02;
+const MAX_FACTOR: f64 = 4.0;
+
+// Largest error relative to the tolerance, and its element.
+fn scaled_error(
+    error: &DVector<f64>,
+    y0: &DVector<f64>,
+    y1: &DVector<f64>,
+    config: &Config,
+) -> (f64, usize) {
+    (0..error.len())
+        .map(|i| {
+            let scale = config.abs_tol + config.rel_tol * y0[i].abs().max(y1[i].abs());
+            let ratio = (error[i] / scale).abs();
+            (if ratio.is_nan() { f64::INFINITY } else { ratio }, i)
+        })
+        .max_by(|a, b| a.0.total_cmp(&b.0))
+        .unwrap_or((0.0, 0))
+}
+
+#[allow(clippy::too_many_lines)]
+pub(super) fn integrate<S: Scheme>(
+    scheme: &mut S,
+    t_span: [f64; 2],
+    y0: &DVector<f64>,
+    h0: f64,
+    config: &Config,
+    observer: &mut dyn FnMut(f64, &DVector<f64>),
+) -> Result<Output, Error> {
+    let sequence = S::SEQUENCE;
+    let num_rows = sequence.len();
+    let work: Vec<f64> = (0..num_rows)
+        .scan(0.0, |total, j| {
+            *total += S::work(sequence[j]);
+            Some(*total)
+        })
+        .collect();
+
+    // Higher columns for tighter tolerances.
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    let mut column =
+        ((-config.rel_tol.max(1e-40).log10() * 0.6 + 0.5).max(1.0) as usize).clamp(1, num_rows - 2);
+
+    let mut t = t_span[0];
+    let mut y = y0.clone();
+    let mut h = h0;
+    let mut num_steps = 0;
+    let mut num_rejected = 0;
+    let mut num_failures = 0;
+    let mut last_rejected = false;
+    observer(t, &y);
+
+    while t < t_span[1] {
+        h = h.min(t_span[1] - t);
+        if t + h <= t {
+            return Err(Error::Convergence { t, h, element: 0 });
+        }
+        scheme.begin(t, &y);
+
+        let last = (column + 1).min(num_rows - 1);
+        let mut table: Vec<Vec<DVector<f64>>> = Vec::with_capacity(last + 1);
+        let mut h_new = vec![h; num_rows];
+        let mut accepted = None;
+        let mut h_rejected = h / 2.0;
+        let mut element = 0;
+        for j in 0..=last {
+            let Some(base) = scheme.row(t, &y, h, sequence[j]) else {
+                break;
+            };
+            // Aitken-Neville extrapolation to h = 0 along the row.
+            let mut entries = vec![base];
+            for k in 1..=j {
+                #[allow(clippy::cast_precision_loss)]
+                let ratio = (sequence[j] as f64 / sequence[j - k] as f64).powi(S::GAMMA);
+                let next =
+                    &entries[k - 1] + (&entries[k - 1] - &table[j - 1][k - 1]) / (ratio - 1.0);
+                entries.push(next);
+            }
+            table.push(entries);
+            if j == 0 {
+                continue;
+            }
+
+            let (error, worst) =
+                scaled_error(&(&table[j][j] - &table[j][j - 1]), &y, &table[j][j], config);
+            element = worst;
+            let exponent = f64::from(S::GAMMA * i32::try_from(j).unwrap_or(i32::MAX) + 1);
+            let factor =
+                (SAFETY * (TARGET / error).powf(1.0 / exponent)).clamp(MIN_FACTOR, MAX_FACTOR);
+            h_new[j] = h * factor;
+            h_rejected = h_new[j];
+
+            if j + 1 >= column {
+                if error <= 1.0 {
+                    accepted = Some(j);
+                    break;
+                }
+                // Stop early if the remaining rows cannot be expected to reach the tolerance.
+                #[allow(clippy::cast_precision_loss)]
+                let reachable: f64 = (j + 1..=last)
+                    .map(|i| (sequence[i] as f64 / sequence[0] as f64).powi(S::GAMMA))
+                    .product();
+                if error > reachable {
+                    break;
+                }
+            }
+        }
+
+        let Some(accepted) = accepted else {
+            num_rejected += 1;
+            num_failures += 1;
+            if num_failures > 10 {
+                return Err(Error::Convergence { t, h, element });
+            }
+            h = h_rejected.min(h / 2.0);
+            column = column.min(table.len().saturating_sub(1)).max(1);
+            last_rejected = true;
+            continue;
+        };
+        num_failures = 0;
+        num_steps += 1;
+        t += h;
+        y = table[accepted].swap_remove(accepted);
+        observer(t, &y);
+
+        // Choose the column with the least work per unit step for the next step.
+        let cost = |j: usize| work[j] / h_new[j];
+        let (next_column, next_h) = if accepted >= 2 && cost(accepted - 1) < 0.8 * cost(accepted) {
+            (accepted - 1, h_new[accepted - 1])
+        } else if accepted + 1 < num_rows - 1
+            && !last_rejected
+            && (accepted < 2 || cost(accepted) < 0.9 * cost(accepted - 1))
+        {
+            (
+                accepted + 1,
+                h_new[accepted] * work[accepted + 1] / work[accepted],
+            )
+        } else {
+            (accepted, h_new[accepted])
+        };
+        column = next_column.clamp(1, num_rows - 2);
+        h = if last_rejected { next_h.min(h) } else { next_h };
+        last_rejected = false;
+    }
+
+    Ok(Output {
+        y,
+        h,
+        column,
+        num_steps,
+        num_rejected,
+    })
+}
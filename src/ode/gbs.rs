@@ -0,0 +1,112 @@
+use std::fmt;
+
+use nalgebra::DVector;
+
+use super::dopri5::Config;
+use super::extrapolation::{self, Scheme};
+use super::{validate_span, DerivativeFunc, Error};
+
+pub struct Input<'a> {
+    pub t_span: [f64; 2],
+    pub y0: &'a DVector<f64>,
+    pub h0: f64,
+    pub f: &'a DerivativeFunc<'a>,
+}
+
+impl fmt::Debug for Input<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Input")
+            .field("t_span", &self.t_span)
+            .field("y0", self.y0)
+            .field("h0", &self.h0)
+            .field("f", &"DerivativeFunc")
+            .finish()
+    }
+}
+
+#[derive(Debug)]
+pub struct Output {
+    pub y: DVector<f64>,
+    pub h: f64,
+    /// Order of the extrapolated solution chosen for the next step.
+    pub order: usize,
+    pub num_calls: usize,
+    pub num_steps: usize,
+    pub num_rejected: usize,
+}
+
+struct ModifiedMidpoint<'a, 'b> {
+    f: &'a DerivativeFunc<'b>,
+    f0: DVector<f64>,
+    num_calls: usize,
+}
+
+impl Scheme for ModifiedMidpoint<'_, '_> {
+    const SEQUENCE: &'static [usize] = &[2, 4, 6, 8, 10, 12, 14, 16, 18];
+    const GAMMA: i32 = 2;
+
+    #[allow(clippy::cast_precision_loss)]
+    fn work(n: usize) -> f64 {
+        n as f64
+    }
+
+    fn begin(&mut self, t: f64, y: &DVector<f64>) {
+        self.f0 = (self.f)(t, y);
+        self.num_calls += 1;
+    }
+
+    // z_{m+1} = z_{m-1} + 2 h f(t_m, z_m), with an Euler step to start. For even n the error
+    // expands in even powers of h.
+    #[allow(clippy::cast_precision_loss, clippy::many_single_char_names)]
+    fn row(&mut self, t: f64, y: &DVector<f64>, h: f64, n: usize) -> Option<DVector<f64>> {
+        let h = h / n as f64;
+        let mut previous = y.clone();
+        let mut z = y + h * &self.f0;
+        for m in 1..n {
+            let next = &previous + 2.0 * h * (self.f)(t + m as f64 * h, &z);
+            previous = std::mem::replace(&mut z, next);
+        }
+        self.num_calls += n - 1;
+        Some(z)
+    }
+}
+
+pub fn integrate(input: &Input<'_>, config: &Config) -> Result<Output, Error> {
+    integrate_with_observer(input, config, &mut |_, _| {})
+}
+
+/// Gragg-Bulirsch-Stoer extrapolation of the modified midpoint rule, with adaptive order and step
+/// size after ODEX (Hairer, Norsett & Wanner). Orders up to 18 make tight tolerances cheap for
+/// smooth non-stiff problems.
+///
+/// `observer` sees y0 and then the extrapolated solution at the end of each accepted step.
+pub fn integrate_with_observer(
+    input: &Input<'_>,
+    config: &Config,
+    observer: &mut dyn FnMut(f64, &DVector<f64>),
+) -> Result<Output, Error> {
+    validate_span(input.t_span, input.h0)?;
+
+    let mut scheme = ModifiedMidpoint {
+        f: input.f,
+        f0: DVector::zeros(0),
+        num_calls: 0,
+    };
+    let output = extrapolation::integrate(
+        &mut scheme,
+        input.t_span,
+        input.y0,
+        input.h0,
+        config,
+        observer,
+    )?;
+
+    Ok(Output {
+        y: output.y,
+        h: output.h,
+        order: 2 * (output.column + 1),
+        num_calls: scheme.num_calls,
+        num_steps: output.num_steps,
+        num_rejected: output.num_rejected,
+    })
+}
@@ -0,0 +1,113 @@
+use nalgebra::DVector;
+
+use super::dopri5::{self, dopri5_step, Config};
+use super::Error;
+
+// Tolerances of the second solution relative to the first, for `Method::Tolerances`.
+const TOLERANCE_FACTOR: f64 = 1e-2;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Method {
+    /// Solves again with both tolerances divided by 100. The difference estimates the error
+    /// provided the global error is roughly proportional to the tolerance, which holds for
+    /// non-stiff problems away from the rounding limit.
+    Tolerances,
+    /// Retraces the accepted steps, each split in two, and extrapolates the fifth order error of
+    /// the fixed mesh. Independent of tolerance proportionality, and about twice the cost of the
+    /// solve.
+    Richardson,
+}
+
+#[derive(Debug)]
+pub struct Output {
+    pub y: DVector<f64>,
+    pub h: f64,
+    /// Estimated `y - y_exact` at the end of the span, per element.
+    pub error: DVector<f64>,
+    /// Including the calls of the estimate.
+    pub num_calls: usize,
+    pub num_steps: usize,
+    pub num_rejected: usize,
+}
+
+impl Output {
+    /// Largest estimated error relative to the tolerances the solve was asked for, as in step size
+    /// control.
+    #[must_use]
+    pub fn error_ratio(&self, config: &Config) -> f64 {
+        self.error
+            .iter()
+            .zip(&self.y)
+            .map(|(error, y)| error.abs() / (config.rel_tol * y.abs()).max(config.abs_tol))
+            .fold(0.0, f64::max)
+    }
+}
+
+pub fn integrate(
+    input: &dopri5::Input<'_>,
+    config: &Config,
+    method: Method,
+) -> Result<Output, Error> {
+    integrate_with_observer(input, config, method, &mut |_, _| {})
+}
+
+/// The solution is exactly that of `dopri5::integrate`, with an estimate of its global error. The
+/// observer is called with the initial state and then after every accepted step of that solution.
+pub fn integrate_with_observer(
+    input: &dopri5::Input<'_>,
+    config: &Config,
+    method: Method,
+    observer: &mut dyn FnMut(f64, &DVector<f64>),
+) -> Result<Output, Error> {
+    let mut mesh = Vec::new();
+    let output = dopri5::integrate_with_observer(input, config, &mut |t, y| {
+        mesh.push(t);
+        observer(t, y);
+    })?;
+
+    let (reference, num_calls) = match method {
+        Method::Tolerances => {
+            let fine = dopri5::integrate(
+                input,
+                &Config {
+                    rel_tol: config.rel_tol * TOLERANCE_FACTOR,
+                    abs_tol: config.abs_tol * TOLERANCE_FACTOR,
+                },
+            )?;
+            (fine.y, fine.num_calls)
+        }
+        Method::Richardson => halved_steps(input, &mesh),
+    };
+
+    let error = match method {
+        Method::Tolerances => &output.y - reference,
+        // e_h = C h^5 and e_{h/2} = C h^5 / 32, so e_h = 32 / 31 (y_h - y_{h/2}).
+        Method::Richardson => (&output.y - reference) * (32.0 / 31.0),
+    };
+
+    Ok(Output {
+        y: output.y,
+        h: output.h,
+        error,
+        num_calls: output.num_calls + num_calls,
+        num_steps: output.num_steps,
+        num_rejected: output.num_rejected,
+    })
+}
+
+// Fixed steps over the mesh with every interval split in two.
+fn halved_steps(input: &dopri5::Input<'_>, mesh: &[f64]) -> (DVector<f64>, usize) {
+    let mut y = input.y0.clone();
+    let mut k1 = None;
+    let mut num_calls = 0;
+    for interval in mesh.windows(2) {
+        let h = (interval[1] - interval[0]) / 2.0;
+        for t in [interval[0], interval[0] + h] {
+            let step = dopri5_step(t, &y, input.f, h, k1.as_ref(), false);
+            num_calls += step.num_calls;
+            y = step.y;
+            k1 = Some(step.k7); // [FSAL]
+        }
+    }
+    (y, num_calls)
+}
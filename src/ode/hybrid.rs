@@ -0,0 +1,317 @@
+use std::fmt;
+
+use nalgebra::DVector;
+
+use super::dopri5::{self, Config, DenseOutput, Segment};
+use super::{DerivativeFunc, Error, InputError};
+
+pub type GuardFunc<'a> = dyn Fn(f64, &DVector<f64>) -> f64 + 'a;
+pub type ResetFunc<'a> = dyn Fn(f64, &DVector<f64>) -> DVector<f64> + 'a;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Direction {
+    Rising,
+    Falling,
+    Either,
+}
+
+impl Direction {
+    // Guards must change sign strictly, so a reset that leaves a guard at zero does not trigger
+    // it again.
+    fn crossed(self, start: f64, end: f64) -> bool {
+        let rising = start < 0.0 && end >= 0.0;
+        let falling = start > 0.0 && end <= 0.0;
+        match self {
+            Direction::Rising => rising,
+            Direction::Falling => falling,
+            Direction::Either => rising || falling,
+        }
+    }
+}
+
+/// Switches to mode `target` when `guard` crosses zero in `direction`, mapping the state through
+/// `reset`.
+pub struct Transition<'a> {
+    pub guard: &'a GuardFunc<'a>,
+    pub direction: Direction,
+    pub reset: &'a ResetFunc<'a>,
+    pub target: usize,
+}
+
+impl fmt::Debug for Transition<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Transition")
+            .field("guard", &"GuardFunc")
+            .field("direction", &self.direction)
+            .field("reset", &"ResetFunc")
+            .field("target", &self.target)
+            .finish()
+    }
+}
+
+pub struct Mode<'a> {
+    pub f: &'a DerivativeFunc<'a>,
+    pub transitions: Vec<Transition<'a>>,
+}
+
+impl fmt::Debug for Mode<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Mode")
+            .field("f", &"DerivativeFunc")
+            .field("transitions", &self.transitions)
+            .finish()
+    }
+}
+
+pub struct Input<'a> {
+    pub t_span: [f64; 2],
+    pub y0: &'a DVector<f64>,
+    pub mode0: usize,
+    pub h0: f64,
+    pub modes: &'a [Mode<'a>],
+    /// Integration stops, without applying it, at the first event past this many, which bounds
+    /// Zeno-like chattering.
+    pub max_events: usize,
+}
+
+impl fmt::Debug for Input<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Input")
+            .field("t_span", &self.t_span)
+            .field("y0", self.y0)
+            .field("mode0", &self.mode0)
+            .field("h0", &self.h0)
+            .field("modes", &self.modes)
+            .field("max_events", &self.max_events)
+            .finish()
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Event {
+    pub t: f64,
+    pub from: usize,
+    pub to: usize,
+    /// Index of the transition within the `from` mode.
+    pub transition: usize,
+    pub y_before: DVector<f64>,
+    pub y_after: DVector<f64>,
+}
+
+#[derive(Debug)]
+pub struct Output {
+    /// Final time, before `t_span[1]` if the event limit was reached.
+    pub t: f64,
+    pub y: DVector<f64>,
+    pub mode: usize,
+    pub h: f64,
+    pub num_calls: usize,
+    pub num_steps: usize,
+    pub num_rejected: usize,
+    pub events: Vec<Event>,
+    pub event_limit_reached: bool,
+}
+
+const MAX_ROOT_ITERATIONS: usize = 100;
+
+// Guards are sampled at this many sub-intervals of each step, so a guard that crosses zero and
+// back within one long step, like a ball completing a whole bounce, is still caught.
+const NUM_GUARD_SAMPLES: usize = 4;
+
+// Lower bound on the first step after an event, as a fraction of the step it was located in, for
+// events right at the start of a step.
+const MIN_RESTART_FRACTION: f64 = 1e-2;
+
+pub(super) fn root_tolerance(t: f64) -> f64 {
+    4.0 * f64::EPSILON * t.abs().max(1.0)
+}
+
+// Zero of the guard within [a, b] on the segment, by the Illinois variant of regula falsi.
+// Returns a time at or just after the crossing so the guard has changed sign there.
+fn locate(
+    guard: &GuardFunc<'_>,
+    segment: &Segment,
+    [mut a, mut b]: [f64; 2],
+    [mut g_a, mut g_b]: [f64; 2],
+) -> (f64, f64) {
+    let tolerance = root_tolerance(segment.t);
+    let mut side = 0;
+    for _ in 0..MAX_ROOT_ITERATIONS {
+        if b - a <= tolerance {
+            break;
+        }
+        let c = ((a * g_b - b * g_a) / (g_b - g_a)).clamp(a, b);
+        let g_c = guard(c, &segment.evaluate(c));
+        if g_c == 0.0 || (g_c > 0.0) == (g_b > 0.0) {
+            b = c;
+            g_b = g_c;
+            if side == -1 {
+                g_a /= 2.0;
+            }
+            side = -1;
+        } else {
+            a = c;
+            g_a = g_c;
+            if side == 1 {
+                g_b /= 2.0;
+            }
+            side = 1;
+        }
+    }
+    (b, g_b)
+}
+
+// Earliest crossing of the guard within the step, sampling the dense segment.
+// Crossings at or before `after` are skipped: right after an event a guard sits at zero up to
+// round-off and may appear to cross it again at the event itself.
+pub(super) fn first_crossing(
+    guard: &GuardFunc<'_>,
+    direction: Direction,
+    segment: &Segment,
+    start: f64,
+    end: (f64, &DVector<f64>),
+    after: f64,
+) -> Option<f64> {
+    let (mut t_a, mut g_a) = (segment.t, start);
+    let mut k = 1;
+    while k <= NUM_GUARD_SAMPLES {
+        let (t_b, g_b) = if k == NUM_GUARD_SAMPLES {
+            (end.0, guard(end.0, end.1))
+        } else {
+            #[allow(clippy::cast_precision_loss)]
+            let t = segment.t + segment.h * k as f64 / NUM_GUARD_SAMPLES as f64;
+            (t, guard(t, &segment.evaluate(t)))
+        };
+        if direction.crossed(g_a, g_b) {
+            let (t, g) = locate(guard, segment, [t_a, t_b], [g_a, g_b]);
+            if t > after {
+                return Some(t);
+            }
+            (t_a, g_a) = (t, g);
+            continue;
+        }
+        (t_a, g_a) = (t_b, g_b);
+        k += 1;
+    }
+    None
+}
+
+pub fn integrate(input: &Input<'_>, config: &Config) -> Result<Output, Error> {
+    integrate_with_observer(input, config, &mut |_, _| {})
+}
+
+/// The observer is called with the initial state, after every accepted step and with the reset
+/// state at every event.
+pub fn integrate_with_observer(
+    input: &Input<'_>,
+    config: &Config,
+    observer: &mut dyn FnMut(f64, &DVector<f64>),
+) -> Result<Output, Error> {
+    let num_modes = input.modes.len();
+    if input.mode0 >= num_modes
+        || input
+            .modes
+            .iter()
+            .flat_map(|mode| &mode.transitions)
+            .any(|transition| transition.target >= num_modes)
+    {
+        return Err(InputError::Mode.into());
+    }
+
+    let mut mode = input.mode0;
+    let mut events = Vec::new();
+    let mut after = f64::NEG_INFINITY;
+
+    let mut section = dopri5::Input {
+        t_span: input.t_span,
+        y0: input.y0,
+        h0: input.h0,
+        f: input.modes[mode].f,
+    };
+    let mut state = dopri5::State::new(&section)?;
+    observer(state.t, &state.y);
+
+    let mut event_limit_reached = false;
+    while !state.is_finished(&section) {
+        let transitions = &input.modes[mode].transitions;
+        let start: Vec<f64> = transitions
+            .iter()
+            .map(|transition| (transition.guard)(state.t, &state.y))
+            .collect();
+
+        let mut dense = DenseOutput::new();
+        state.step_dense(&section, config, &mut dense)?;
+        let Some(segment) = dense.segments.pop() else {
+            continue;
+        };
+
+        // Earliest triggered transition within the step.
+        let triggered = transitions
+            .iter()
+            .enumerate()
+            .filter_map(|(i, transition)| {
+                first_crossing(
+                    transition.guard,
+                    transition.direction,
+                    &segment,
+                    start[i],
+                    (state.t, &state.y),
+                    after,
+                )
+                .map(|t| (t, i))
+            })
+            .min_by(|a, b| a.0.total_cmp(&b.0));
+
+        let Some((t_event, i)) = triggered else {
+            observer(state.t, &state.y);
+            continue;
+        };
+
+        // The event past the limit is not applied. Integration stops at it in the old mode.
+        let transition = &transitions[i];
+        let y_before = segment.evaluate(t_event);
+        if events.len() >= input.max_events {
+            state.t = t_event;
+            state.y = y_before;
+            observer(state.t, &state.y);
+            event_limit_reached = true;
+            break;
+        }
+        let y_after = (transition.reset)(t_event, &y_before);
+        events.push(Event {
+            t: t_event,
+            from: mode,
+            to: transition.target,
+            transition: i,
+            y_before,
+            y_after: y_after.clone(),
+        });
+
+        // Restart from the reset state in the new mode. The derivative carried over from the
+        // last step belongs to the old mode and state. [FSAL] The step is cut back to the part of
+        // the last one that was used, since the step size control knows nothing of the reset and
+        // a long first step could pass over the next event entirely.
+        mode = transition.target;
+        section.f = input.modes[mode].f;
+        state.h = state
+            .h
+            .min((t_event - segment.t).max(MIN_RESTART_FRACTION * segment.h));
+        state.t = t_event;
+        state.y = y_after;
+        state.k1 = None;
+        after = t_event + root_tolerance(t_event);
+        observer(state.t, &state.y);
+    }
+
+    Ok(Output {
+        t: state.t,
+        y: state.y,
+        mode,
+        h: state.h,
+        num_calls: state.num_calls,
+        num_steps: state.num_steps,
+        num_rejected: state.num_rejected,
+        events,
+        event_limit_reached,
+    })
+}
@@ -0,0 +1,272 @@
+use std::fmt;
+
+use nalgebra::{DMatrix, DVector};
+
+use super::dopri5::Config;
+use super::jacobian::Jacobian;
+use super::{
+    error_ratio, validate_span, DerivativeFunc, Error, MAX_ERROR_RATIO_4, MIN_ERROR_RATIO_4,
+};
+
+/// `y' = f_E(t, y) + f_I(t, y)` with the stiff part `f_I`.
+pub struct Input<'a> {
+    pub t_span: [f64; 2],
+    pub y0: &'a DVector<f64>,
+    pub h0: f64,
+    pub explicit: &'a DerivativeFunc<'a>,
+    pub implicit: &'a DerivativeFunc<'a>,
+    /// Of the implicit part alone.
+    pub jacobian: Jacobian<'a>,
+}
+
+impl fmt::Debug for Input<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Input")
+            .field("t_span", &self.t_span)
+            .field("y0", self.y0)
+            .field("h0", &self.h0)
+            .field("explicit", &"DerivativeFunc")
+            .field("implicit", &"DerivativeFunc")
+            .field("jacobian", &self.jacobian)
+            .finish()
+    }
+}
+
+#[derive(Debug)]
+pub struct Output {
+    pub y: DVector<f64>,
+    pub h: f64,
+    /// Evaluations of the explicit and the implicit part.
+    pub num_calls: usize,
+    pub num_implicit_calls: usize,
+    pub num_jacobians: usize,
+    pub num_steps: usize,
+    pub num_rejected: usize,
+    /// Steps rejected because a stage iteration failed to converge, included in `num_rejected`.
+    pub num_newton_failures: usize,
+}
+
+// ARK4(3)6L[2]SA (Kennedy & Carpenter, 2003): an explicit and a stiffly accurate L-stable ESDIRK
+// tableau sharing c and b, fourth order with an embedded third order solution.
+const GAMMA: f64 = 0.25;
+const C_COEFF: [f64; 6] = [0.0, 1.0 / 2.0, 83.0 / 250.0, 31.0 / 50.0, 17.0 / 20.0, 1.0];
+const EXPLICIT_COEFF: [[f64; 5]; 5] = [
+    [1.0 / 2.0, 0.0, 0.0, 0.0, 0.0],
+    [13861.0 / 62500.0, 6889.0 / 62500.0, 0.0, 0.0, 0.0],
+    [
+        -116_923_316_275.0 / 2_393_684_061_468.0,
+        -2_731_218_467_317.0 / 15_368_042_101_831.0,
+        9_408_046_702_089.0 / 11_113_171_139_209.0,
+        0.0,
+        0.0,
+    ],
+    [
+        -451_086_348_788.0 / 2_902_428_689_909.0,
+        -2_682_348_792_572.0 / 7_519_795_681_897.0,
+        12_662_868_775_082.0 / 11_960_479_115_383.0,
+        3_355_817_975_965.0 / 11_060_851_509_271.0,
+        0.0,
+    ],
+    [
+        647_845_179_188.0 / 3_216_320_057_751.0,
+        73_281_519_250.0 / 8_382_639_484_533.0,
+        552_539_513_391.0 / 3_454_668_386_233.0,
+        3_354_512_671_639.0 / 8_306_763_924_573.0,
+        4040.0 / 17871.0,
+    ],
+];
+// Below the diagonal, which is GAMMA for every stage after the first.
+const IMPLICIT_COEFF: [[f64; 5]; 5] = [
+    [GAMMA, 0.0, 0.0, 0.0, 0.0],
+    [8611.0 / 62500.0, -1743.0 / 31250.0, 0.0, 0.0, 0.0],
+    [
+        5_012_029.0 / 34_652_500.0,
+        -654_441.0 / 2_922_500.0,
+        174_375.0 / 388_108.0,
+        0.0,
+        0.0,
+    ],
+    [
+        15_267_082_809.0 / 155_376_265_600.0,
+        -71_443_401.0 / 120_774_400.0,
+        730_878_875.0 / 902_184_768.0,
+        2_285_395.0 / 8_070_912.0,
+        0.0,
+    ],
+    // Stiffly accurate: the last stage is the solution.
+    [
+        82889.0 / 524_892.0,
+        0.0,
+        15625.0 / 83664.0,
+        69875.0 / 102_672.0,
+        -2260.0 / 8211.0,
+    ],
+];
+const B_COEFF: [[f64; 6]; 2] = [
+    [
+        82889.0 / 524_892.0,
+        0.0,
+        15625.0 / 83664.0,
+        69875.0 / 102_672.0,
+        -2260.0 / 8211.0,
+        1.0 / 4.0,
+    ],
+    [
+        4_586_570_599.0 / 29_645_900_160.0,
+        0.0,
+        178_811_875.0 / 945_068_544.0,
+        814_220_225.0 / 1_159_782_912.0,
+        -3_700_637.0 / 11_593_932.0,
+        61727.0 / 225_920.0,
+    ],
+];
+
+// Stage iterations stop once the scaled correction is this fraction of the error tolerance.
+const NEWTON_TOLERANCE: f64 = 1e-2;
+const MAX_NEWTON_ITERATIONS: usize = 10;
+
+pub fn integrate(input: &Input<'_>, config: &Config) -> Result<Output, Error> {
+    integrate_with_observer(input, config, &mut |_, _| {})
+}
+
+/// The implicit stages are solved by simplified Newton iteration with the Jacobian of the implicit
+/// part frozen at the start of the step. A stage that does not converge rejects the step and
+/// quarters h.
+///
+/// Steps rejected for error or a failed stage iteration are not observed; `observer` sees y0 and
+/// then the state after each accepted step.
+#[allow(clippy::too_many_lines)]
+pub fn integrate_with_observer(
+    input: &Input<'_>,
+    config: &Config,
+    observer: &mut dyn FnMut(f64, &DVector<f64>),
+) -> Result<Output, Error> {
+    validate_span(input.t_span, input.h0)?;
+
+    let dim = input.y0.len();
+    let mut t = input.t_span[0];
+    let mut y = input.y0.clone();
+    let mut h = input.h0;
+    let mut num_calls = 0;
+    let mut num_implicit_calls = 0;
+    let mut num_jacobians = 0;
+    let mut num_steps = 0;
+    let mut num_rejected = 0;
+    let mut num_newton_failures = 0;
+    observer(t, &y);
+
+    while t < input.t_span[1] {
+        // Linearization at the start of the step, shared by any rejected attempts.
+        let jacobian = input.jacobian.evaluate(input.implicit, t, &y);
+        let explicit0 = (input.explicit)(t, &y);
+        let implicit0 = (input.implicit)(t, &y);
+        num_calls += 1;
+        num_implicit_calls += 1;
+        num_jacobians += 1;
+        let scale = y.map(|y| config.abs_tol + config.rel_tol * y.abs());
+
+        let mut num_failures = 0;
+        loop {
+            h = h.min(input.t_span[1] - t);
+            if t + h <= t {
+                return Err(Error::Convergence { t, h, element: 0 });
+            }
+            let lu = (DMatrix::identity(dim, dim) - (h * GAMMA) * &jacobian).lu();
+
+            let mut explicit = vec![explicit0.clone()];
+            let mut implicit = vec![implicit0.clone()];
+            let mut z = y.clone();
+            let mut converged = true;
+            for (i, (a_explicit, a_implicit)) in
+                EXPLICIT_COEFF.iter().zip(&IMPLICIT_COEFF).enumerate()
+            {
+                let t_stage = t + C_COEFF[i + 1] * h;
+                let mut known = y.clone();
+                for j in 0..=i {
+                    known.axpy(h * a_explicit[j], &explicit[j], 1.0);
+                    known.axpy(h * a_implicit[j], &implicit[j], 1.0);
+                }
+
+                // z = known + h gamma f_I(t_stage, z), starting from the previous stage.
+                let mut stage_converged = false;
+                for _ in 0..MAX_NEWTON_ITERATIONS {
+                    let residual = &known + (h * GAMMA) * (input.implicit)(t_stage, &z) - &z;
+                    num_implicit_calls += 1;
+                    let Some(correction) = lu.solve(&residual) else {
+                        break;
+                    };
+                    z += &correction;
+                    if correction.component_div(&scale).amax() <= NEWTON_TOLERANCE {
+                        stage_converged = true;
+                        break;
+                    }
+                }
+                if !stage_converged || !z.iter().all(|z| z.is_finite()) {
+                    converged = false;
+                    break;
+                }
+                // From the stage equation rather than another evaluation, which would amplify the
+                // iteration error by the stiffness.
+                implicit.push((&z - &known) / (h * GAMMA));
+                explicit.push((input.explicit)(t_stage, &z));
+                num_calls += 1;
+            }
+
+            let h_attempted = h;
+            if !converged {
+                num_rejected += 1;
+                num_newton_failures += 1;
+                num_failures += 1;
+                if num_failures > 10 {
+                    return Err(Error::Convergence { t, h, element: 0 });
+                }
+                h *= 0.25;
+                continue;
+            }
+
+            let mut y_next = y.clone();
+            let mut error = DVector::zeros(dim);
+            for (j, (explicit_j, implicit_j)) in explicit.iter().zip(&implicit).enumerate() {
+                let derivative = explicit_j + implicit_j;
+                y_next.axpy(h * B_COEFF[0][j], &derivative, 1.0);
+                error.axpy(h * (B_COEFF[0][j] - B_COEFF[1][j]), &derivative, 1.0);
+            }
+
+            // h step size control.
+            let allowed_error = (config.rel_tol * y_next.abs()).map(|x| x.max(config.abs_tol));
+            let (error_ratio, limiting_element) =
+                error_ratio(&allowed_error, &error, MIN_ERROR_RATIO_4, MAX_ERROR_RATIO_4);
+            h = 0.9 * h * error_ratio.powf(1.0 / 4.0);
+
+            // Discard step if error is too high.
+            if error_ratio < 1.0 {
+                num_rejected += 1;
+                num_failures += 1;
+                if num_failures > 10 {
+                    return Err(Error::Convergence {
+                        t,
+                        h: h_attempted,
+                        element: limiting_element,
+                    });
+                }
+                continue;
+            }
+            num_steps += 1;
+            t += h_attempted;
+            y = y_next;
+            observer(t, &y);
+            break;
+        }
+    }
+
+    Ok(Output {
+        y,
+        h,
+        num_calls,
+        num_implicit_calls,
+        num_jacobians,
+        num_steps,
+        num_rejected,
+        num_newton_failures,
+    })
+}
@@ -0,0 +1,502 @@
+use std::fmt;
+use std::ops::Range;
+use std::ops::{Add, AddAssign, Div, DivAssign, Mul, MulAssign, Neg, Sub, SubAssign};
+
+use nalgebra::{DMatrix, DVector};
+
+use super::{DerivativeFunc, ParamDerivativeFunc};
+
+pub type JacobianFunc<'a> = dyn Fn(f64, &DVector<f64>) -> DMatrix<f64> + 'a;
+
+/// A derivative function evaluated on dual numbers, usually a `Real`-generic function instantiated
+/// with `Dual`.
+pub type DualDerivativeFunc<'a> = dyn Fn(Dual, &DVector<Dual>) -> DVector<Dual> + 'a;
+
+/// For a parameterized f(t, y, p), analytic and dual functions take the state and parameters
+/// stacked as z = [y; p]. An analytic function returns the block for y or p, whichever it is used
+/// for, and a dual function returns f and is seeded in that block only.
+#[derive(Clone)]
+pub enum Jacobian<'a> {
+    Analytic(&'a JacobianFunc<'a>),
+    FiniteDifference {
+        difference: Difference,
+        sparsity: Option<Sparsity>,
+    },
+    Dual(&'a DualDerivativeFunc<'a>),
+}
+
+impl fmt::Debug for Jacobian<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Jacobian::Analytic(_) => write!(f, "Analytic"),
+            Jacobian::FiniteDifference {
+                difference,
+                sparsity,
+            } => f
+                .debug_struct("FiniteDifference")
+                .field("difference", difference)
+                .field("sparsity", sparsity)
+                .finish(),
+            Jacobian::Dual(_) => write!(f, "Dual"),
+        }
+    }
+}
+
+impl Jacobian<'_> {
+    /// Jacobian of f with respect to y at (t, y).
+    #[must_use]
+    pub fn evaluate(&self, f: &DerivativeFunc<'_>, t: f64, y: &DVector<f64>) -> DMatrix<f64> {
+        match self {
+            Jacobian::Analytic(jacobian) => jacobian(t, y),
+            Jacobian::FiniteDifference {
+                difference,
+                sparsity,
+            } => finite_difference(|y| f(t, y), y, &f(t, y), *difference, sparsity.as_ref()),
+            Jacobian::Dual(f) => dual(f, t, y),
+        }
+    }
+
+    /// Jacobian of f with respect to y at (t, y, p), where `f_value` is f(t, y, p).
+    #[must_use]
+    pub fn wrt_y(
+        &self,
+        f: &ParamDerivativeFunc<'_>,
+        t: f64,
+        y: &DVector<f64>,
+        p: &DVector<f64>,
+        f_value: &DVector<f64>,
+    ) -> DMatrix<f64> {
+        match self {
+            Jacobian::Analytic(jacobian) => jacobian(t, &stack(y, p)),
+            Jacobian::FiniteDifference {
+                difference,
+                sparsity,
+            } => finite_difference(|y| f(t, y, p), y, f_value, *difference, sparsity.as_ref()),
+            Jacobian::Dual(f) => dual_columns(f, t, &stack(y, p), f_value.len(), 0..y.len()),
+        }
+    }
+
+    /// Jacobian of f with respect to p at (t, y, p), n x `num_params`.
+    #[must_use]
+    pub fn wrt_p(
+        &self,
+        f: &ParamDerivativeFunc<'_>,
+        t: f64,
+        y: &DVector<f64>,
+        p: &DVector<f64>,
+        f_value: &DVector<f64>,
+    ) -> DMatrix<f64> {
+        match self {
+            Jacobian::Analytic(jacobian) => jacobian(t, &stack(y, p)),
+            Jacobian::FiniteDifference {
+                difference,
+                sparsity,
+            } => finite_difference(|p| f(t, y, p), p, f_value, *difference, sparsity.as_ref()),
+            Jacobian::Dual(f) => dual_columns(
+                f,
+                t,
+                &stack(y, p),
+                f_value.len(),
+                y.len()..y.len() + p.len(),
+            ),
+        }
+    }
+}
+
+fn stack(y: &DVector<f64>, p: &DVector<f64>) -> DVector<f64> {
+    DVector::from_iterator(y.len() + p.len(), y.iter().chain(p.iter()).copied())
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Difference {
+    /// One evaluation per column (or color), first order accurate.
+    Forward,
+    /// Two evaluations per column (or color), second order accurate.
+    Central,
+}
+
+/// Structural nonzeros of a Jacobian, with a Curtis-Powell-Reid column coloring: columns of the
+/// same color share no nonzero row, so they can be perturbed together.
+#[derive(Clone, Debug)]
+pub struct Sparsity {
+    num_rows: usize,
+    // Nonzero rows of each column.
+    columns: Vec<Vec<usize>>,
+    colors: Vec<usize>,
+    num_colors: usize,
+}
+
+impl Sparsity {
+    /// # Panics
+    /// If an entry is outside the matrix.
+    #[must_use]
+    pub fn new(num_rows: usize, num_columns: usize, entries: &[(usize, usize)]) -> Sparsity {
+        let mut columns = vec![Vec::new(); num_columns];
+        for &(i, j) in entries {
+            assert!(
+                i < num_rows && j < num_columns,
+                "entry ({i}, {j}) out of range"
+            );
+            columns[j].push(i);
+        }
+        for rows in &mut columns {
+            rows.sort_unstable();
+            rows.dedup();
+        }
+
+        // Greedy coloring in column order.
+        let mut row_colors: Vec<Vec<usize>> = vec![Vec::new(); num_rows];
+        let mut colors = Vec::with_capacity(num_columns);
+        let mut num_colors = 0;
+        for rows in &columns {
+            let color = (0..=num_colors)
+                .find(|color| rows.iter().all(|&i| !row_colors[i].contains(color)))
+                .unwrap_or_default();
+            for &i in rows {
+                row_colors[i].push(color);
+            }
+            colors.push(color);
+            num_colors = num_colors.max(color + 1);
+        }
+
+        Sparsity {
+            num_rows,
+            columns,
+            colors,
+            num_colors,
+        }
+    }
+
+    #[must_use]
+    pub fn dense(n: usize) -> Sparsity {
+        let entries: Vec<_> = (0..n).flat_map(|i| (0..n).map(move |j| (i, j))).collect();
+        Sparsity::new(n, n, &entries)
+    }
+
+    /// Square band with `lower` subdiagonals and `upper` superdiagonals.
+    #[must_use]
+    pub fn banded(n: usize, lower: usize, upper: usize) -> Sparsity {
+        let entries: Vec<_> = (0..n)
+            .flat_map(|i| (i.saturating_sub(lower)..n.min(i + upper + 1)).map(move |j| (i, j)))
+            .collect();
+        Sparsity::new(n, n, &entries)
+    }
+
+    #[must_use]
+    pub fn shape(&self) -> (usize, usize) {
+        (self.num_rows, self.columns.len())
+    }
+
+    #[must_use]
+    pub fn num_colors(&self) -> usize {
+        self.num_colors
+    }
+
+    #[must_use]
+    pub fn colors(&self) -> &[usize] {
+        &self.colors
+    }
+}
+
+/// Finite difference Jacobian of f at x, where `f_value` is f(x). With a sparsity pattern only one
+/// evaluation (two for central differences) is made per color instead of per column.
+#[must_use]
+pub fn finite_difference(
+    f: impl Fn(&DVector<f64>) -> DVector<f64>,
+    x: &DVector<f64>,
+    f_value: &DVector<f64>,
+    difference: Difference,
+    sparsity: Option<&Sparsity>,
+) -> DMatrix<f64> {
+    let steps = x.map(|xj| f64::EPSILON.sqrt() * xj.abs().max(1.0));
+    let steps = match difference {
+        Difference::Forward => steps,
+        // Balances truncation and rounding error for the second order formula.
+        Difference::Central => steps.map(|step| step * f64::EPSILON.powf(-1.0 / 6.0)),
+    };
+
+    let mut jacobian = DMatrix::zeros(f_value.len(), x.len());
+    let groups: Vec<Vec<usize>> = match sparsity {
+        Some(sparsity) => {
+            let mut groups = vec![Vec::new(); sparsity.num_colors];
+            for (j, &color) in sparsity.colors.iter().enumerate() {
+                groups[color].push(j);
+            }
+            groups
+        }
+        None => (0..x.len()).map(|j| vec![j]).collect(),
+    };
+
+    let mut perturbed = x.clone();
+    for group in groups {
+        let shifted = |sign: f64, perturbed: &mut DVector<f64>| {
+            for &j in &group {
+                perturbed[j] = x[j] + sign * steps[j];
+            }
+            let value = f(perturbed);
+            for &j in &group {
+                perturbed[j] = x[j];
+            }
+            value
+        };
+        let (delta, scale) = match difference {
+            Difference::Forward => (shifted(1.0, &mut perturbed) - f_value, 1.0),
+            Difference::Central => (
+                shifted(1.0, &mut perturbed) - shifted(-1.0, &mut perturbed),
+                2.0,
+            ),
+        };
+
+        for &j in &group {
+            let column = delta.clone() / (scale * steps[j]);
+            match sparsity {
+                Some(sparsity) => {
+                    for &i in &sparsity.columns[j] {
+                        jacobian[(i, j)] = column[i];
+                    }
+                }
+                None => jacobian.set_column(j, &column),
+            }
+        }
+    }
+    jacobian
+}
+
+/// Forward mode automatic differentiation, one dual evaluation per column.
+#[must_use]
+pub fn dual(f: &DualDerivativeFunc<'_>, t: f64, y: &DVector<f64>) -> DMatrix<f64> {
+    dual_columns(f, t, y, y.len(), 0..y.len())
+}
+
+// The columns of the Jacobian of f at x with respect to x[columns] only, with `rows` the length
+// of f. There may be no columns, as for the parameters of a problem without any.
+fn dual_columns(
+    f: &DualDerivativeFunc<'_>,
+    t: f64,
+    x: &DVector<f64>,
+    rows: usize,
+    columns: Range<usize>,
+) -> DMatrix<f64> {
+    let mut jacobian = DMatrix::zeros(rows, columns.len());
+    for (column, j) in columns.enumerate() {
+        let x = DVector::from_fn(x.len(), |i, _| Dual {
+            re: x[i],
+            eps: if i == j { 1.0 } else { 0.0 },
+        });
+        jacobian.set_column(column, &f(Dual::from(t), &x).map(|value| value.eps));
+    }
+    jacobian
+}
+
+/// Scalar operations needed to write a derivative function once for both `f64` and `Dual`.
+pub trait Real:
+    nalgebra::Scalar
+    + Copy
+    + From<f64>
+    + Add<Output = Self>
+    + Sub<Output = Self>
+    + Mul<Output = Self>
+    + Div<Output = Self>
+    + Neg<Output = Self>
+    + Add<f64, Output = Self>
+    + Sub<f64, Output = Self>
+    + Mul<f64, Output = Self>
+    + Div<f64, Output = Self>
+    + AddAssign
+    + SubAssign
+    + MulAssign
+    + DivAssign
+{
+    #[must_use]
+    fn value(self) -> f64;
+    #[must_use]
+    fn abs(self) -> Self;
+    #[must_use]
+    fn sqrt(self) -> Self;
+    #[must_use]
+    fn exp(self) -> Self;
+    #[must_use]
+    fn ln(self) -> Self;
+    #[must_use]
+    fn sin(self) -> Self;
+    #[must_use]
+    fn cos(self) -> Self;
+    #[must_use]
+    fn powi(self, n: i32) -> Self;
+    #[must_use]
+    fn powf(self, n: f64) -> Self;
+}
+
+impl Real for f64 {
+    fn value(self) -> f64 {
+        self
+    }
+    fn abs(self) -> f64 {
+        f64::abs(self)
+    }
+    fn sqrt(self) -> f64 {
+        f64::sqrt(self)
+    }
+    fn exp(self) -> f64 {
+        f64::exp(self)
+    }
+    fn ln(self) -> f64 {
+        f64::ln(self)
+    }
+    fn sin(self) -> f64 {
+        f64::sin(self)
+    }
+    fn cos(self) -> f64 {
+        f64::cos(self)
+    }
+    fn powi(self, n: i32) -> f64 {
+        f64::powi(self, n)
+    }
+    fn powf(self, n: f64) -> f64 {
+        f64::powf(self, n)
+    }
+}
+
+/// re + eps * e with e^2 = 0, carrying a directional derivative alongside the value.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Dual {
+    pub re: f64,
+    pub eps: f64,
+}
+
+impl From<f64> for Dual {
+    fn from(re: f64) -> Dual {
+        Dual { re, eps: 0.0 }
+    }
+}
+
+impl Add for Dual {
+    type Output = Dual;
+    fn add(self, rhs: Dual) -> Dual {
+        Dual {
+            re: self.re + rhs.re,
+            eps: self.eps + rhs.eps,
+        }
+    }
+}
+
+impl Sub for Dual {
+    type Output = Dual;
+    fn sub(self, rhs: Dual) -> Dual {
+        Dual {
+            re: self.re - rhs.re,
+            eps: self.eps - rhs.eps,
+        }
+    }
+}
+
+impl Mul for Dual {
+    type Output = Dual;
+    fn mul(self, rhs: Dual) -> Dual {
+        Dual {
+            re: self.re * rhs.re,
+            eps: self.eps * rhs.re + self.re * rhs.eps,
+        }
+    }
+}
+
+impl Div for Dual {
+    type Output = Dual;
+    fn div(self, rhs: Dual) -> Dual {
+        Dual {
+            re: self.re / rhs.re,
+            eps: (self.eps * rhs.re - self.re * rhs.eps) / (rhs.re * rhs.re),
+        }
+    }
+}
+
+impl Neg for Dual {
+    type Output = Dual;
+    fn neg(self) -> Dual {
+        Dual {
+            re: -self.re,
+            eps: -self.eps,
+        }
+    }
+}
+
+macro_rules! impl_dual_ops {
+    ($($op:ident, $method:ident, $op_assign:ident, $method_assign:ident;)*) => {
+        $(
+            impl $op<f64> for Dual {
+                type Output = Dual;
+                fn $method(self, rhs: f64) -> Dual {
+                    self.$method(Dual::from(rhs))
+                }
+            }
+
+            impl $op<Dual> for f64 {
+                type Output = Dual;
+                fn $method(self, rhs: Dual) -> Dual {
+                    Dual::from(self).$method(rhs)
+                }
+            }
+
+            impl $op_assign for Dual {
+                fn $method_assign(&mut self, rhs: Dual) {
+                    *self = self.$method(rhs);
+                }
+            }
+        )*
+    };
+}
+
+impl_dual_ops! {
+    Add, add, AddAssign, add_assign;
+    Sub, sub, SubAssign, sub_assign;
+    Mul, mul, MulAssign, mul_assign;
+    Div, div, DivAssign, div_assign;
+}
+
+impl Dual {
+    // Applies a function with value `re` and derivative `derivative` at self.re.
+    fn chain(self, re: f64, derivative: f64) -> Dual {
+        Dual {
+            re,
+            eps: self.eps * derivative,
+        }
+    }
+}
+
+impl Real for Dual {
+    fn value(self) -> f64 {
+        self.re
+    }
+    // Takes the sub-gradient 0 at 0, where `signum` would give 1.
+    fn abs(self) -> Dual {
+        let derivative = if self.re == 0.0 {
+            0.0
+        } else {
+            self.re.signum()
+        };
+        self.chain(self.re.abs(), derivative)
+    }
+    fn sqrt(self) -> Dual {
+        let re = self.re.sqrt();
+        self.chain(re, 0.5 / re)
+    }
+    fn exp(self) -> Dual {
+        let re = self.re.exp();
+        self.chain(re, re)
+    }
+    fn ln(self) -> Dual {
+        self.chain(self.re.ln(), 1.0 / self.re)
+    }
+    fn sin(self) -> Dual {
+        self.chain(self.re.sin(), self.re.cos())
+    }
+    fn cos(self) -> Dual {
+        self.chain(self.re.cos(), -self.re.sin())
+    }
+    fn powi(self, n: i32) -> Dual {
+        self.chain(self.re.powi(n), f64::from(n) * self.re.powi(n - 1))
+    }
+    fn powf(self, n: f64) -> Dual {
+        self.chain(self.re.powf(n), n * self.re.powf(n - 1.0))
+    }
+}
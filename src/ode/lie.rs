@@ -0,0 +1,308 @@
+use std::fmt;
+
+use nalgebra::{DVector, UnitQuaternion, Vector3};
+
+use super::dopri5::{Config, A_COEFF, B_COEFF, C_COEFF, MAX_ERROR_RATIO, MIN_ERROR_RATIO};
+use super::{error_ratio, validate_span, Error};
+
+/// Euclidean elements alongside attitudes on SO(3). A `Rotation3` attitude converts with
+/// `UnitQuaternion::from_rotation_matrix` and back with `to_rotation_matrix`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct State {
+    pub x: DVector<f64>,
+    pub q: Vec<UnitQuaternion<f64>>,
+}
+
+/// Time derivative of a `State`: the Euclidean derivative and the body frame angular velocity of
+/// every attitude, q' = q (0, omega / 2). Also used for increments in the Lie algebra.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Tangent {
+    pub x: DVector<f64>,
+    pub omega: Vec<Vector3<f64>>,
+}
+
+impl State {
+    /// Moves by `increment`, adding to the Euclidean elements and composing each attitude with
+    /// exp(omega) in the body frame. Attitudes stay unit quaternions up to rounding, which is
+    /// removed on every step.
+    #[must_use]
+    pub fn retract(&self, increment: &Tangent) -> State {
+        State {
+            x: &self.x + &increment.x,
+            q: self
+                .q
+                .iter()
+                .zip(&increment.omega)
+                .map(|(q, omega)| {
+                    let mut q = q * UnitQuaternion::from_scaled_axis(*omega);
+                    q.renormalize_fast();
+                    q
+                })
+                .collect(),
+        }
+    }
+}
+
+pub type LieDerivativeFunc<'a> = dyn Fn(f64, &State) -> Tangent + 'a;
+
+pub struct Input<'a> {
+    pub t_span: [f64; 2],
+    pub y0: &'a State,
+    /// Fixed step for Crouch-Grossman, initial step for Runge-Kutta-Munthe-Kaas.
+    pub h: f64,
+    pub f: &'a LieDerivativeFunc<'a>,
+}
+
+impl fmt::Debug for Input<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Input")
+            .field("t_span", &self.t_span)
+            .field("y0", self.y0)
+            .field("h", &self.h)
+            .field("f", &"LieDerivativeFunc")
+            .finish()
+    }
+}
+
+#[derive(Debug)]
+pub struct Output {
+    pub y: State,
+    pub h: f64,
+    pub num_calls: usize,
+    pub num_steps: usize,
+    pub num_rejected: usize,
+}
+
+// h sum_j coefficients_j k_j
+fn combine(h: f64, coefficients: &[f64], k: &[Tangent]) -> Tangent {
+    let mut x = DVector::zeros(k[0].x.len());
+    let mut omega = vec![Vector3::zeros(); k[0].omega.len()];
+    for (&a, k) in coefficients.iter().zip(k) {
+        x.axpy(h * a, &k.x, 1.0);
+        for (omega, k_omega) in omega.iter_mut().zip(&k.omega) {
+            *omega += h * a * k_omega;
+        }
+    }
+    Tangent { x, omega }
+}
+
+// Inverse of the derivative of the exponential map on so(3). With R = R0 exp(u) and body angular
+// velocity omega, u' = dexpinv(u, omega) = omega + u x omega / 2 + c(|u|) u x (u x omega).
+fn dexpinv(u: &Vector3<f64>, omega: &Vector3<f64>) -> Vector3<f64> {
+    let theta = u.norm();
+    // (1 - (theta / 2) cot(theta / 2)) / theta^2, by its series near zero.
+    let c = if theta < 1e-4 {
+        1.0 / 12.0 + theta * theta / 720.0
+    } else {
+        let half = theta / 2.0;
+        (1.0 - half / half.tan()) / (theta * theta)
+    };
+    let u_omega = u.cross(omega);
+    omega + 0.5 * u_omega + c * u.cross(&u_omega)
+}
+
+pub fn integrate(input: &Input<'_>, config: &Config) -> Result<Output, Error> {
+    integrate_with_observer(input, config, &mut |_, _| {})
+}
+
+/// Runge-Kutta-Munthe-Kaas on the Dormand-Prince tableau: every stage integrates the Lie algebra
+/// increment u with u' = dexpinv(u, omega), which is a Euclidean ODE, and maps back with the
+/// exponential. Fifth order with the same embedded error estimate and step size control as
+/// `dopri5`. Attitude errors are in radians, measured against the tolerances as elements of
+/// magnitude one, and in `Error::Convergence` they are numbered after the Euclidean elements, three
+/// per attitude.
+///
+/// `observer` receives y0 and then each accepted `State`, attitudes included.
+pub fn integrate_with_observer(
+    input: &Input<'_>,
+    config: &Config,
+    observer: &mut dyn FnMut(f64, &State),
+) -> Result<Output, Error> {
+    validate_span(input.t_span, input.h)?;
+    let mut t = input.t_span[0];
+    let mut y = input.y0.clone();
+    let mut h = input.h;
+    let mut f1 = None;
+    let mut num_failures = 0;
+    let mut num_calls = 0;
+    let mut num_steps = 0;
+    let mut num_rejected = 0;
+    observer(t, &y);
+
+    while t < input.t_span[1] {
+        h = h.min(input.t_span[1] - t);
+        if t + h <= t {
+            return Err(Error::Convergence { t, h, element: 0 });
+        }
+
+        let step = rkmk_step(t, &y, input.f, h, f1.take());
+        num_calls += step.num_calls;
+
+        let allowed_x = (config.rel_tol * step.y.x.abs()).map(|x| x.max(config.abs_tol));
+        let allowed_omega = config.rel_tol.max(config.abs_tol);
+        let num_angles = 3 * step.error.omega.len();
+        let allowed = DVector::from_iterator(
+            allowed_x.len() + num_angles,
+            allowed_x
+                .iter()
+                .copied()
+                .chain(std::iter::repeat_n(allowed_omega, num_angles)),
+        );
+        let error = DVector::from_iterator(
+            allowed.len(),
+            step.error
+                .x
+                .iter()
+                .chain(step.error.omega.iter().flat_map(|omega| omega.iter()))
+                .copied(),
+        );
+        let (error_ratio, element) =
+            error_ratio(&allowed, &error, MIN_ERROR_RATIO, MAX_ERROR_RATIO);
+
+        let h_attempted = h;
+        h = 0.9 * h * error_ratio.powf(1.0 / 5.0);
+
+        if error_ratio < 1.0 {
+            num_rejected += 1;
+            num_failures += 1;
+            if num_failures > 10 {
+                return Err(Error::Convergence {
+                    t,
+                    h: h_attempted,
+                    element,
+                });
+            }
+            f1 = Some(step.f1);
+            continue;
+        }
+        num_failures = 0;
+        num_steps += 1;
+
+        t += h_attempted;
+        y = step.y;
+        f1 = Some(step.f7); // The last stage is evaluated at the new state. [FSAL]
+        observer(t, &y);
+    }
+
+    Ok(Output {
+        y,
+        h,
+        num_calls,
+        num_steps,
+        num_rejected,
+    })
+}
+
+struct StepOutput {
+    y: State,
+    error: Tangent,
+    // Derivative at the start and end of the step.
+    f1: Tangent,
+    f7: Tangent,
+    num_calls: usize,
+}
+
+#[allow(clippy::many_single_char_names)]
+fn rkmk_step(
+    t: f64,
+    y: &State,
+    f: &LieDerivativeFunc<'_>,
+    h: f64,
+    f1: Option<Tangent>,
+) -> StepOutput {
+    let mut num_calls = 0;
+    let f1 = f1.unwrap_or_else(|| {
+        num_calls += 1;
+        f(t, y)
+    });
+
+    // dexpinv is the identity at u = 0, so the first stage is the derivative itself.
+    let mut k = vec![f1.clone()];
+    let mut fifth_order = None;
+    let mut f7 = None;
+    for (i, a) in A_COEFF.iter().enumerate() {
+        let u = combine(h, &a[..=i], &k);
+        let stage = y.retract(&u);
+        let value = f(t + C_COEFF[i + 1] * h, &stage);
+        num_calls += 1;
+        k.push(Tangent {
+            x: value.x.clone(),
+            omega: u
+                .omega
+                .iter()
+                .zip(&value.omega)
+                .map(|(u, omega)| dexpinv(u, omega))
+                .collect(),
+        });
+        // With Dormand Prince the last stage is at the fifth order solution.
+        if i == A_COEFF.len() - 1 {
+            fifth_order = Some((stage, u));
+            f7 = Some(value);
+        }
+    }
+    let (Some((y_next, u5)), Some(f7)) = (fifth_order, f7) else {
+        unreachable!("the tableau has stages");
+    };
+
+    let u4 = combine(h, &B_COEFF[1], &k);
+    let error = Tangent {
+        x: &u5.x - &u4.x,
+        omega: u5
+            .omega
+            .iter()
+            .zip(&u4.omega)
+            .map(|(u5, u4)| u5 - u4)
+            .collect(),
+    };
+
+    StepOutput {
+        y: y_next,
+        error,
+        f1,
+        f7,
+        num_calls,
+    }
+}
+
+// Crouch-Grossman (1993), third order. Commutator free: stages compose exponentials of the
+// derivatives themselves, without dexpinv.
+const CG_C: [f64; 3] = [0.0, 3.0 / 4.0, 17.0 / 24.0];
+const CG_A: [[f64; 2]; 3] = [[0.0, 0.0], [3.0 / 4.0, 0.0], [119.0 / 216.0, 17.0 / 108.0]];
+const CG_B: [f64; 3] = [13.0 / 51.0, -2.0 / 3.0, 24.0 / 17.0];
+
+#[allow(clippy::many_single_char_names)]
+fn crouch_grossman_step(t: f64, y: &State, f: &LieDerivativeFunc<'_>, h: f64) -> State {
+    let flow = |y: &State, coefficients: &[f64], k: &[Tangent]| {
+        coefficients.iter().zip(k).fold(y.clone(), |y, (&a, k)| {
+            y.retract(&combine(h, &[a], std::slice::from_ref(k)))
+        })
+    };
+
+    let mut k = Vec::with_capacity(CG_B.len());
+    for (i, &c) in CG_C.iter().enumerate() {
+        let stage = flow(y, &CG_A[i][..i], &k);
+        k.push(f(t + c * h, &stage));
+    }
+    flow(y, &CG_B, &k)
+}
+
+/// Fixed steps of `input.h`, the last shortened to end on `t_span[1]`.
+pub fn crouch_grossman(input: &Input<'_>) -> Result<Output, Error> {
+    validate_span(input.t_span, input.h)?;
+    let mut t = input.t_span[0];
+    let mut y = input.y0.clone();
+    let mut num_steps = 0;
+    while t < input.t_span[1] {
+        let h = input.h.min(input.t_span[1] - t);
+        y = crouch_grossman_step(t, &y, input.f, h);
+        t += h;
+        num_steps += 1;
+    }
+    Ok(Output {
+        y,
+        h: input.h,
+        num_calls: CG_B.len() * num_steps,
+        num_steps,
+        num_rejected: 0,
+    })
+}
@@ -0,0 +1,179 @@
+use std::fmt;
+
+use nalgebra::{DMatrix, DVector};
+
+use super::dopri5::{self, Config};
+use super::jacobian::Jacobian;
+use super::{DerivativeFunc, Error};
+
+const MAX_ITERATIONS: usize = 10;
+
+/// A known invariant written as a constraint g(t, y) = 0, for example energy minus its initial
+/// value, or |q|^2 - 1 for a quaternion.
+pub struct Invariant<'a> {
+    pub g: &'a DerivativeFunc<'a>,
+    pub jacobian: Jacobian<'a>,
+    /// Project onto g = 0 after every accepted step, otherwise only monitor the drift.
+    pub project: bool,
+}
+
+impl fmt::Debug for Invariant<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Invariant")
+            .field("g", &"DerivativeFunc")
+            .field("jacobian", &self.jacobian)
+            .field("project", &self.project)
+            .finish()
+    }
+}
+
+pub struct Input<'a> {
+    pub t_span: [f64; 2],
+    pub y0: &'a DVector<f64>,
+    pub h0: f64,
+    pub f: &'a DerivativeFunc<'a>,
+    pub invariants: &'a [Invariant<'a>],
+    /// Largest constraint residual accepted by the projection.
+    pub tolerance: f64,
+}
+
+impl fmt::Debug for Input<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Input")
+            .field("t_span", &self.t_span)
+            .field("y0", self.y0)
+            .field("h0", &self.h0)
+            .field("f", &"DerivativeFunc")
+            .field("invariants", &self.invariants)
+            .field("tolerance", &self.tolerance)
+            .finish()
+    }
+}
+
+/// Largest residuals |g|_inf of one invariant over the run.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Drift {
+    /// Along the returned solution, after any projection.
+    pub max: f64,
+    /// After each step but before its projection. Equal to `max` for monitored invariants.
+    pub max_step: f64,
+    pub last: f64,
+}
+
+#[derive(Debug)]
+pub struct Output {
+    pub y: DVector<f64>,
+    pub h: f64,
+    pub num_calls: usize,
+    pub num_steps: usize,
+    pub num_rejected: usize,
+    /// Projections that stopped above the tolerance after the iteration limit.
+    pub num_unconverged: usize,
+    /// One per invariant, in order.
+    pub drift: Vec<Drift>,
+}
+
+pub fn integrate(input: &Input<'_>, config: &Config) -> Result<Output, Error> {
+    integrate_with_observer(input, config, &mut |_, _| {})
+}
+
+/// The observer is called with the initial state and then after every accepted, projected step.
+pub fn integrate_with_observer(
+    input: &Input<'_>,
+    config: &Config,
+    observer: &mut dyn FnMut(f64, &DVector<f64>),
+) -> Result<Output, Error> {
+    let dopri5_input = dopri5::Input {
+        t_span: input.t_span,
+        y0: input.y0,
+        h0: input.h0,
+        f: input.f,
+    };
+    let mut state = dopri5::State::new(&dopri5_input)?;
+    let mut drift = vec![Drift::default(); input.invariants.len()];
+    let mut num_unconverged = 0;
+    observer(state.t, &state.y);
+
+    while !state.is_finished(&dopri5_input) {
+        state.step(&dopri5_input, config)?;
+
+        for (drift, invariant) in drift.iter_mut().zip(input.invariants) {
+            drift.max_step = drift.max_step.max((invariant.g)(state.t, &state.y).amax());
+        }
+
+        if input.invariants.iter().any(|invariant| invariant.project) {
+            let (y, converged) = project(input, state.t, &state.y);
+            if !converged {
+                num_unconverged += 1;
+            }
+            state.y = y;
+            // The derivative carried over belongs to the unprojected state. [FSAL]
+            state.k1 = None;
+        }
+
+        for (drift, invariant) in drift.iter_mut().zip(input.invariants) {
+            drift.last = (invariant.g)(state.t, &state.y).amax();
+            drift.max = drift.max.max(drift.last);
+        }
+        observer(state.t, &state.y);
+    }
+
+    let output = state.into_output();
+    Ok(Output {
+        y: output.y,
+        h: output.h,
+        num_calls: output.num_calls,
+        num_steps: output.num_steps,
+        num_rejected: output.num_rejected,
+        num_unconverged,
+        drift,
+    })
+}
+
+// Closest point on the intersection of the projected constraints, by simplified Newton iteration
+// with the constraint Jacobian G fixed at the unprojected point: y -= G^+ g(y). The pseudo-inverse
+// gives the smallest correction and tolerates redundant constraints.
+fn project(input: &Input<'_>, t: f64, y: &DVector<f64>) -> (DVector<f64>, bool) {
+    let projected: Vec<_> = input
+        .invariants
+        .iter()
+        .filter(|invariant| invariant.project)
+        .collect();
+    let constraints = |y: &DVector<f64>| {
+        let values: Vec<_> = projected
+            .iter()
+            .map(|invariant| (invariant.g)(t, y))
+            .collect();
+        DVector::from_iterator(
+            values.iter().map(DVector::len).sum(),
+            values.iter().flat_map(|value| value.iter().copied()),
+        )
+    };
+
+    let blocks: Vec<_> = projected
+        .iter()
+        .map(|invariant| invariant.jacobian.evaluate(invariant.g, t, y))
+        .collect();
+    let num_rows = blocks.iter().map(DMatrix::nrows).sum();
+    let mut jacobian = DMatrix::zeros(num_rows, y.len());
+    let mut row = 0;
+    for block in &blocks {
+        jacobian.rows_mut(row, block.nrows()).copy_from(block);
+        row += block.nrows();
+    }
+    let svd = jacobian.svd(true, true);
+
+    let mut y = y.clone();
+    for _ in 0..MAX_ITERATIONS {
+        let g = constraints(&y);
+        if g.amax() <= input.tolerance {
+            return (y, true);
+        }
+        let Ok(correction) = svd.solve(&g, f64::EPSILON * svd.singular_values.max()) else {
+            break;
+        };
+        y -= correction;
+    }
+    let converged = constraints(&y).amax() <= input.tolerance;
+    (y, converged)
+}
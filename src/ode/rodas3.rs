@@ -0,0 +1,329 @@
+use std::fmt;
+
+use nalgebra::{DMatrix, DVector};
+
+use super::dopri5::Config;
+use super::jacobian::{self, Difference, Jacobian};
+use super::{error_ratio, validate_span, DerivativeFunc, Error};
+
+pub type MassMatrixFunc<'a> = dyn Fn(f64, &DVector<f64>) -> DMatrix<f64> + 'a;
+
+/// M in M y' = f(t, y). A singular M makes the zero rows algebraic equations.
+pub enum MassMatrix<'a> {
+    Identity,
+    Constant(DMatrix<f64>),
+    /// Must be nonsingular. Integrated as y' = z, 0 = M(t, y) z - f(t, y), which has a constant
+    /// mass matrix.
+    StateDependent(&'a MassMatrixFunc<'a>),
+}
+
+impl fmt::Debug for MassMatrix<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MassMatrix::Identity => write!(f, "Identity"),
+            MassMatrix::Constant(mass) => f.debug_tuple("Constant").field(mass).finish(),
+            MassMatrix::StateDependent(_) => write!(f, "StateDependent"),
+        }
+    }
+}
+
+impl MassMatrix<'_> {
+    #[must_use]
+    pub fn evaluate(&self, t: f64, y: &DVector<f64>) -> DMatrix<f64> {
+        match self {
+            MassMatrix::Identity => DMatrix::identity(y.len(), y.len()),
+            MassMatrix::Constant(mass) => mass.clone(),
+            MassMatrix::StateDependent(mass) => mass(t, y),
+        }
+    }
+}
+
+pub struct Input<'a> {
+    pub t_span: [f64; 2],
+    pub y0: &'a DVector<f64>,
+    pub h0: f64,
+    pub f: &'a DerivativeFunc<'a>,
+    pub mass: MassMatrix<'a>,
+    pub jacobian: Jacobian<'a>,
+}
+
+impl fmt::Debug for Input<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Input")
+            .field("t_span", &self.t_span)
+            .field("y0", self.y0)
+            .field("h0", &self.h0)
+            .field("f", &"DerivativeFunc")
+            .field("mass", &self.mass)
+            .field("jacobian", &self.jacobian)
+            .finish()
+    }
+}
+
+#[derive(Debug)]
+pub struct Output {
+    pub y: DVector<f64>,
+    pub h: f64,
+    pub num_calls: usize,
+    pub num_jacobians: usize,
+    pub num_steps: usize,
+    pub num_rejected: usize,
+}
+
+// Rodas3 (Sandu et al., 1997): four stages, third order with an embedded second order solution,
+// stiffly accurate so the algebraic equations hold at the end of every step.
+const GAMMA: f64 = 0.5;
+const A_COEFF: [[f64; 3]; 4] = [
+    [0.0, 0.0, 0.0],
+    [0.0, 0.0, 0.0],
+    [2.0, 0.0, 0.0],
+    [2.0, 0.0, 1.0],
+];
+const C_COEFF: [[f64; 3]; 4] = [
+    [0.0, 0.0, 0.0],
+    [4.0, 0.0, 0.0],
+    [1.0, -1.0, 0.0],
+    [1.0, -1.0, -8.0 / 3.0],
+];
+const ALPHA: [f64; 4] = [0.0, 0.0, 1.0, 1.0];
+const GAMMA_SUM: [f64; 4] = [0.5, 1.5, 0.0, 0.0];
+const M_COEFF: [f64; 4] = [2.0, 0.0, 1.0, 1.0];
+
+const MIN_ERROR_RATIO: f64 = 1e-3; // (1/10)^3, 10x decrease in h.
+const MAX_ERROR_RATIO: f64 = 1e3; // 10^3, 10x increase in h.
+
+pub fn integrate(input: &Input<'_>, config: &Config) -> Result<Output, Error> {
+    integrate_with_observer(input, config, &mut |_, _| {})
+}
+
+/// `observer` gets y0 and then y after each accepted step, also when a state-dependent mass matrix
+/// makes the solver integrate the augmented [y, y'] system.
+#[allow(clippy::too_many_lines)]
+pub fn integrate_with_observer(
+    input: &Input<'_>,
+    config: &Config,
+    observer: &mut dyn FnMut(f64, &DVector<f64>),
+) -> Result<Output, Error> {
+    validate_span(input.t_span, input.h0)?;
+    if let MassMatrix::StateDependent(mass) = input.mass {
+        return integrate_state_dependent(input, mass, config, observer);
+    }
+
+    let mut t = input.t_span[0];
+    let mut y = input.y0.clone();
+    observer(t, &y);
+
+    let mut h = input.h0;
+    let mut num_calls = 0;
+    let mut num_jacobians = 0;
+    let mut num_steps = 0;
+    let mut num_rejected = 0;
+
+    while t < input.t_span[1] {
+        // Linearization at the start of the step, shared by any rejected attempts.
+        let mass = input.mass.evaluate(t, &y);
+        let jacobian = input.jacobian.evaluate(input.f, t, &y);
+        let f0 = (input.f)(t, &y);
+        let delta = f64::EPSILON.sqrt() * t.abs().max(1.0);
+        let f_t = ((input.f)(t + delta, &y) - &f0) / delta;
+        num_calls += 2;
+        num_jacobians += 1;
+
+        let mut num_failures = 0;
+        loop {
+            h = h.min(input.t_span[1] - t);
+            // The step has underflowed, typically approaching a singularity of the solution.
+            if t + h <= t {
+                return Err(Error::Convergence { t, h, element: 0 });
+            }
+
+            let lu = (&mass / (h * GAMMA) - &jacobian).lu();
+            let mut k: Vec<DVector<f64>> = Vec::with_capacity(4);
+            let mut singular = false;
+            for s in 0..4 {
+                // The second stage is evaluated at y, like the first.
+                let f_s = if s < 2 {
+                    f0.clone()
+                } else {
+                    let mut y_s = y.clone();
+                    for (j, k_j) in k.iter().enumerate() {
+                        y_s.axpy(A_COEFF[s][j], k_j, 1.0);
+                    }
+                    num_calls += 1;
+                    (input.f)(t + ALPHA[s] * h, &y_s)
+                };
+
+                let mut sum = DVector::zeros(y.len());
+                for (j, k_j) in k.iter().enumerate() {
+                    sum.axpy(C_COEFF[s][j] / h, k_j, 1.0);
+                }
+                let rhs = f_s + &mass * sum + (h * GAMMA_SUM[s]) * &f_t;
+                let Some(k_s) = lu.solve(&rhs) else {
+                    singular = true;
+                    break;
+                };
+                k.push(k_s);
+            }
+
+            // h step size control. A singular iteration matrix is retried with a smaller step,
+            // which strengthens the mass matrix term, and has no limiting element.
+            let (error_ratio, limiting_element, y_next) = if singular {
+                (MIN_ERROR_RATIO, None, None)
+            } else {
+                let mut y_next = y.clone();
+                for (k_j, m) in k.iter().zip(M_COEFF) {
+                    y_next.axpy(m, k_j, 1.0);
+                }
+                let allowed_error = (config.rel_tol * y_next.abs()).map(|x| x.max(config.abs_tol));
+                let (error_ratio, limiting_element) =
+                    error_ratio(&allowed_error, &k[3], MIN_ERROR_RATIO, MAX_ERROR_RATIO);
+                (error_ratio, Some(limiting_element), Some(y_next))
+            };
+
+            let h_attempted = h;
+            h = 0.9 * h * error_ratio.powf(1.0 / 3.0);
+
+            // Discard step if error is too high.
+            match y_next {
+                Some(y_next) if error_ratio >= 1.0 => {
+                    num_steps += 1;
+                    t += h_attempted;
+                    y = y_next;
+                    observer(t, &y);
+                    break;
+                }
+                _ => {
+                    num_rejected += 1;
+                    num_failures += 1;
+                    if num_failures > 10 {
+                        return Err(match limiting_element {
+                            Some(element) => Error::Convergence {
+                                t,
+                                h: h_attempted,
+                                element,
+                            },
+                            None => Error::Singular { t, h: h_attempted },
+                        });
+                    }
+                }
+            }
+        }
+    }
+
+    Ok(Output {
+        y,
+        h,
+        num_calls,
+        num_jacobians,
+        num_steps,
+        num_rejected,
+    })
+}
+
+fn integrate_state_dependent(
+    input: &Input<'_>,
+    mass: &MassMatrixFunc<'_>,
+    config: &Config,
+    observer: &mut dyn FnMut(f64, &DVector<f64>),
+) -> Result<Output, Error> {
+    let n = input.y0.len();
+    let split = |w: &DVector<f64>| (w.rows(0, n).into_owned(), w.rows(n, n).into_owned());
+
+    let f = |t: f64, w: &DVector<f64>| {
+        let (y, z) = split(w);
+        let mut w_dot = DVector::zeros(2 * n);
+        w_dot.rows_mut(0, n).copy_from(&z);
+        w_dot
+            .rows_mut(n, n)
+            .copy_from(&(mass(t, &y) * &z - (input.f)(t, &y)));
+        w_dot
+    };
+    let jacobian = |t: f64, w: &DVector<f64>| {
+        let (y, z) = split(w);
+        let mass_z = |y: &DVector<f64>| mass(t, y) * &z;
+        let mass_z_jacobian =
+            jacobian::finite_difference(mass_z, &y, &mass_z(&y), Difference::Forward, None);
+        let mut jacobian = DMatrix::zeros(2 * n, 2 * n);
+        jacobian.view_mut((0, n), (n, n)).fill_with_identity();
+        jacobian
+            .view_mut((n, 0), (n, n))
+            .copy_from(&(mass_z_jacobian - input.jacobian.evaluate(input.f, t, &y)));
+        jacobian.view_mut((n, n), (n, n)).copy_from(&mass(t, &y));
+        jacobian
+    };
+
+    let t0 = input.t_span[0];
+    let z0 = mass(t0, input.y0)
+        .lu()
+        .solve(&(input.f)(t0, input.y0))
+        .ok_or(Error::Singular { t: t0, h: 0.0 })?;
+    let mut w0 = DVector::zeros(2 * n);
+    w0.rows_mut(0, n).copy_from(input.y0);
+    w0.rows_mut(n, n).copy_from(&z0);
+
+    let mut mass_diagonal = DVector::zeros(2 * n);
+    mass_diagonal.rows_mut(0, n).fill(1.0);
+    let output = integrate_with_observer(
+        &Input {
+            t_span: input.t_span,
+            y0: &w0,
+            h0: input.h0,
+            f: &f,
+            mass: MassMatrix::Constant(DMatrix::from_diagonal(&mass_diagonal)),
+            jacobian: Jacobian::Analytic(&jacobian),
+        },
+        config,
+        &mut |t, w| observer(t, &w.rows(0, n).into_owned()),
+    )?;
+
+    Ok(Output {
+        y: output.y.rows(0, n).into_owned(),
+        ..output
+    })
+}
+
+/// Adjusts the algebraic part of `input.y0` until the algebraic equations hold to `tolerance`,
+/// keeping the differential part fixed. The algebraic equations are the left null space of M and
+/// the algebraic variables its right null space, so this also works for mass matrices that mix
+/// variables.
+#[allow(clippy::cast_precision_loss)]
+pub fn consistent_initial_conditions(
+    input: &Input<'_>,
+    tolerance: f64,
+) -> Result<DVector<f64>, Error> {
+    let t = input.t_span[0];
+    let n = input.y0.len();
+    let mass = input.mass.evaluate(t, input.y0);
+
+    let svd = mass.svd(true, true);
+    let (Some(left), Some(right_t)) = (svd.u, svd.v_t) else {
+        return Ok(input.y0.clone());
+    };
+    let threshold = svd.singular_values.max() * f64::EPSILON * n as f64;
+    let null: Vec<_> = (0..n)
+        .filter(|&i| svd.singular_values[i] <= threshold)
+        .collect();
+    if null.is_empty() {
+        return Ok(input.y0.clone());
+    }
+    let equations = left.select_columns(&null);
+    let variables = right_t.select_rows(&null).transpose();
+
+    let mut y = input.y0.clone();
+    let mut residual = f64::INFINITY;
+    for _ in 0..20 {
+        let residuals = equations.tr_mul(&(input.f)(t, &y));
+        residual = residuals.amax();
+        if residual <= tolerance {
+            return Ok(y);
+        }
+
+        let jacobian = input.jacobian.evaluate(input.f, t, &y);
+        let reduced = equations.tr_mul(&(jacobian * &variables));
+        let Some(dz) = reduced.lu().solve(&-residuals) else {
+            break;
+        };
+        y += &variables * dz;
+    }
+    Err(Error::InitialConditions { residual })
+}
@@ -0,0 +1,208 @@
+use std::fmt;
+
+use nalgebra::DVector;
+
+use super::dopri5::Config;
+use super::rng::Rng;
+use super::{error_ratio, validate_span, DerivativeFunc, Error};
+
+/// Diagonal noise: element i of the diffusion scales Wiener process i.
+pub type DiffusionFunc<'a> = dyn Fn(f64, &DVector<f64>) -> DVector<f64> + 'a;
+
+/// Ito SDE dy = drift(t, y) dt + diffusion(t, y) * dW with independent Wiener processes W.
+pub struct Input<'a> {
+    pub t_span: [f64; 2],
+    pub y0: &'a DVector<f64>,
+    /// Fixed step for Euler-Maruyama and Milstein, initial step for the adaptive method.
+    pub h: f64,
+    pub drift: &'a DerivativeFunc<'a>,
+    pub diffusion: &'a DiffusionFunc<'a>,
+}
+
+impl fmt::Debug for Input<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Input")
+            .field("t_span", &self.t_span)
+            .field("y0", self.y0)
+            .field("h", &self.h)
+            .field("drift", &"DerivativeFunc")
+            .field("diffusion", &"DiffusionFunc")
+            .finish()
+    }
+}
+
+#[derive(Debug)]
+pub struct Output {
+    pub y: DVector<f64>,
+    /// W(t1) - W(t0) along the sampled path.
+    pub w: DVector<f64>,
+    pub num_steps: usize,
+    pub num_rejected: usize,
+}
+
+fn wiener_increment(rng: &mut Rng, h: f64, n: usize) -> DVector<f64> {
+    DVector::from_fn(n, |_, _| h.sqrt() * rng.normal())
+}
+
+// Fixed step integration with `step(t, y, h, dw) -> y_next`.
+fn integrate_fixed(
+    input: &Input<'_>,
+    rng: &mut Rng,
+    step: impl Fn(f64, &DVector<f64>, f64, &DVector<f64>) -> DVector<f64>,
+) -> Result<Output, Error> {
+    validate_span(input.t_span, input.h)?;
+
+    let dim = input.y0.len();
+    let mut t = input.t_span[0];
+    let mut y = input.y0.clone();
+    let mut w = DVector::zeros(dim);
+    let mut num_steps = 0;
+    while t < input.t_span[1] {
+        let h = input.h.min(input.t_span[1] - t);
+        let dw = wiener_increment(rng, h, dim);
+        y = step(t, &y, h, &dw);
+        w += dw;
+        t += h;
+        num_steps += 1;
+    }
+
+    Ok(Output {
+        y,
+        w,
+        num_steps,
+        num_rejected: 0,
+    })
+}
+
+/// Strong order 0.5, weak order 1.
+pub fn euler_maruyama(input: &Input<'_>, rng: &mut Rng) -> Result<Output, Error> {
+    integrate_fixed(input, rng, |t, y, h, dw| {
+        y + h * (input.drift)(t, y) + (input.diffusion)(t, y).component_mul(dw)
+    })
+}
+
+// Derivative free Milstein scheme of Platen, strong order 1.
+fn milstein_step(
+    input: &Input<'_>,
+    t: f64,
+    y: &DVector<f64>,
+    h: f64,
+    dw: &DVector<f64>,
+) -> (DVector<f64>, DVector<f64>, DVector<f64>) {
+    let drift = (input.drift)(t, y);
+    let diffusion = (input.diffusion)(t, y);
+    let support = y + h * &drift + h.sqrt() * &diffusion;
+    let correction = ((input.diffusion)(t, &support) - &diffusion)
+        .component_mul(&dw.map(|dw| dw * dw - h))
+        / (2.0 * h.sqrt());
+    let y_next = y + h * &drift + diffusion.component_mul(dw) + &correction;
+    (y_next, drift, correction)
+}
+
+/// Strong order 1 for diagonal noise, weak order 1.
+pub fn milstein(input: &Input<'_>, rng: &mut Rng) -> Result<Output, Error> {
+    integrate_fixed(input, rng, |t, y, h, dw| {
+        milstein_step(input, t, y, h, dw).0
+    })
+}
+
+// The error estimate is O(h), so the ratio scales h linearly.
+const MIN_ERROR_RATIO: f64 = 1e-1; // 1/10, 10x decrease in h.
+const MAX_ERROR_RATIO: f64 = 2.0; // 2x increase in h.
+
+/// Adaptive derivative free Milstein scheme of Platen, not a stochastic Runge-Kutta method of
+/// higher order. The local error estimate is the diffusion correction, the difference from an
+/// Euler-Maruyama step, plus a Heun drift correction. The diffusion correction dominates at O(h),
+/// so the estimate is that of the strong order 0.5 method and the step is controlled accordingly,
+/// with the strong order 1 Milstein solution propagated. Rejected steps are halved and the Wiener
+/// increment is split with a Brownian bridge, with the unused part of the path kept for later
+/// steps, so the sampled path does not depend on the step sizes taken.
+pub fn milstein_adaptive(
+    input: &Input<'_>,
+    config: &Config,
+    rng: &mut Rng,
+) -> Result<Output, Error> {
+    validate_span(input.t_span, input.h)?;
+
+    let dim = input.y0.len();
+    let mut t = input.t_span[0];
+    let mut y = input.y0.clone();
+    let mut w = DVector::zeros(dim);
+    let mut h = input.h;
+    let mut num_steps = 0;
+    let mut num_rejected = 0;
+    let mut num_failures = 0;
+
+    // Already sampled future increments (dt, dW), next one last.
+    let mut path: Vec<(f64, DVector<f64>)> = Vec::new();
+
+    while t < input.t_span[1] {
+        h = h.min(input.t_span[1] - t);
+        let (h_step, dw) = match path.pop() {
+            Some((dt, dw)) if h < dt => {
+                let (first, second) = bridge(rng, dt, &dw, h);
+                path.push((dt - h, second));
+                (h, first)
+            }
+            Some(increment) => increment,
+            None => (h, wiener_increment(rng, h, dim)),
+        };
+        // The step has underflowed, typically approaching a singularity of the solution.
+        if t + h_step <= t {
+            return Err(Error::Convergence {
+                t,
+                h: h_step,
+                element: 0,
+            });
+        }
+
+        let (y_next, drift, correction) = milstein_step(input, t, &y, h_step, &dw);
+        let drift_error = 0.5 * h_step * ((input.drift)(t + h_step, &y_next) - drift);
+        let error = drift_error.abs() + correction.abs();
+        let allowed_error = (config.rel_tol * y_next.abs()).map(|x| x.max(config.abs_tol));
+        let (error_ratio, limiting_element) =
+            error_ratio(&allowed_error, &error, MIN_ERROR_RATIO, MAX_ERROR_RATIO);
+
+        // Discard step if error is too high.
+        if error_ratio < 1.0 {
+            num_rejected += 1;
+            num_failures += 1;
+            if num_failures > 10 {
+                return Err(Error::Convergence {
+                    t,
+                    h: h_step,
+                    element: limiting_element,
+                });
+            }
+
+            let half = 0.5 * h_step;
+            let (first, second) = bridge(rng, h_step, &dw, half);
+            path.push((half, second));
+            path.push((half, first));
+            h = half;
+            continue;
+        }
+        num_failures = 0;
+        num_steps += 1;
+
+        t += h_step;
+        y = y_next;
+        w += dw;
+        h = 0.9 * h_step * error_ratio;
+    }
+
+    Ok(Output {
+        y,
+        w,
+        num_steps,
+        num_rejected,
+    })
+}
+
+// Splits an increment dw over dt into increments over [0, h] and [h, dt].
+fn bridge(rng: &mut Rng, dt: f64, dw: &DVector<f64>, h: f64) -> (DVector<f64>, DVector<f64>) {
+    let std_dev = (h * (dt - h) / dt).sqrt();
+    let first = dw.map(|dw| h / dt * dw + std_dev * rng.normal());
+    let second = dw - &first;
+    (first, second)
+}
@@ -0,0 +1,104 @@
+use std::fmt;
+
+use nalgebra::{DMatrix, DVector};
+
+use super::dopri5::{self, Config};
+use super::jacobian::Jacobian;
+use super::{Error, ParamDerivativeFunc};
+
+pub struct Input<'a> {
+    pub t_span: [f64; 2],
+    pub y0: &'a DVector<f64>,
+    pub p: &'a DVector<f64>,
+    pub h0: f64,
+    pub f: &'a ParamDerivativeFunc<'a>,
+    pub jacobian_y: Jacobian<'a>,
+    pub jacobian_p: Jacobian<'a>,
+    /// Include the sensitivities in step size control, not just the state.
+    pub error_control: bool,
+}
+
+impl fmt::Debug for Input<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Input")
+            .field("t_span", &self.t_span)
+            .field("y0", self.y0)
+            .field("p", self.p)
+            .field("h0", &self.h0)
+            .field("f", &"ParamDerivativeFunc")
+            .field("jacobian_y", &self.jacobian_y)
+            .field("jacobian_p", &self.jacobian_p)
+            .field("error_control", &self.error_control)
+            .finish()
+    }
+}
+
+#[derive(Debug)]
+pub struct Output {
+    pub y: DVector<f64>,
+    /// dy(t)/dy0, n x n.
+    pub dy_dy0: DMatrix<f64>,
+    /// dy(t)/dp, n x `num_params`.
+    pub dy_dp: DMatrix<f64>,
+    pub h: f64,
+    pub num_calls: usize,
+    pub num_steps: usize,
+    pub num_rejected: usize,
+}
+
+/// Integrates the variational equations alongside the state. The augmented state is
+/// `[y, S]` with `S = [dy/dy0 | dy/dp]` stored column-major and `dS/dt = J_y S + [0 | J_p]`.
+pub fn integrate(input: &Input<'_>, config: &Config) -> Result<Output, Error> {
+    let n = input.y0.len();
+    let num_params = input.p.len();
+    let p = input.p;
+
+    let augmented = |t: f64, z: &DVector<f64>| {
+        let y = z.rows(0, n).into_owned();
+        let y_dot = (input.f)(t, &y, p);
+
+        let jacobian_y = input.jacobian_y.wrt_y(input.f, t, &y, p, &y_dot);
+        let jacobian_p = input.jacobian_p.wrt_p(input.f, t, &y, p, &y_dot);
+
+        let s = DMatrix::from_column_slice(n, n + num_params, &z.as_slice()[n..]);
+        let mut s_dot = jacobian_y * s;
+        let mut s_dot_p = s_dot.columns_mut(n, num_params);
+        s_dot_p += jacobian_p;
+
+        let mut z_dot = DVector::zeros(z.len());
+        z_dot.rows_mut(0, n).copy_from(&y_dot);
+        z_dot.as_mut_slice()[n..].copy_from_slice(s_dot.as_slice());
+        z_dot
+    };
+
+    let mut z0 = DVector::zeros(n + n * (n + num_params));
+    z0.rows_mut(0, n).copy_from(input.y0);
+    for i in 0..n {
+        z0[n + i * n + i] = 1.0;
+    }
+
+    let dopri5_input = dopri5::Input {
+        t_span: input.t_span,
+        y0: &z0,
+        h0: input.h0,
+        f: &augmented,
+    };
+    let num_controlled = if input.error_control { z0.len() } else { n };
+
+    let mut state = dopri5::State::new(&dopri5_input)?;
+    while !state.is_finished(&dopri5_input) {
+        state.step_controlled(&dopri5_input, config, num_controlled)?;
+    }
+
+    let z = &state.y;
+    let s = DMatrix::from_column_slice(n, n + num_params, &z.as_slice()[n..]);
+    Ok(Output {
+        y: z.rows(0, n).into_owned(),
+        dy_dy0: s.columns(0, n).into_owned(),
+        dy_dp: s.columns(n, num_params).into_owned(),
+        h: state.h,
+        num_calls: state.num_calls,
+        num_steps: state.num_steps,
+        num_rejected: state.num_rejected,
+    })
+}
@@ -0,0 +1,149 @@
+use std::fmt;
+
+use nalgebra::{DMatrix, DVector};
+
+use super::dopri5::Config;
+use super::extrapolation::{self, Scheme};
+use super::jacobian::Jacobian;
+use super::{validate_span, DerivativeFunc, Error};
+
+pub struct Input<'a> {
+    pub t_span: [f64; 2],
+    pub y0: &'a DVector<f64>,
+    pub h0: f64,
+    pub f: &'a DerivativeFunc<'a>,
+    pub jacobian: Jacobian<'a>,
+}
+
+impl fmt::Debug for Input<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Input")
+            .field("t_span", &self.t_span)
+            .field("y0", self.y0)
+            .field("h0", &self.h0)
+            .field("f", &"DerivativeFunc")
+            .field("jacobian", &self.jacobian)
+            .finish()
+    }
+}
+
+#[derive(Debug)]
+pub struct Output {
+    pub y: DVector<f64>,
+    pub h: f64,
+    /// Order of the extrapolated solution chosen for the next step.
+    pub order: usize,
+    pub num_calls: usize,
+    pub num_jacobians: usize,
+    pub num_steps: usize,
+    pub num_rejected: usize,
+}
+
+struct LinearlyImplicitEuler<'a, 'b> {
+    input: &'a Input<'b>,
+    config: &'a Config,
+    // Linearization at the start of the step, kept over rejected attempts.
+    t0: f64,
+    f0: DVector<f64>,
+    jacobian: DMatrix<f64>,
+    num_calls: usize,
+    num_jacobians: usize,
+}
+
+impl Scheme for LinearlyImplicitEuler<'_, '_> {
+    const SEQUENCE: &'static [usize] = &[1, 2, 3, 4, 5, 6, 7, 8];
+    const GAMMA: i32 = 1;
+
+    // Derivative evaluations and one decomposition.
+    #[allow(clippy::cast_precision_loss)]
+    fn work(n: usize) -> f64 {
+        n as f64 + 1.0
+    }
+
+    fn begin(&mut self, t: f64, y: &DVector<f64>) {
+        if self.num_jacobians > 0 && self.t0.to_bits() == t.to_bits() {
+            return;
+        }
+        self.t0 = t;
+        self.f0 = (self.input.f)(t, y);
+        self.jacobian = self.input.jacobian.evaluate(self.input.f, t, y);
+        self.num_calls += 1;
+        self.num_jacobians += 1;
+    }
+
+    // (I - h J) (z_{m+1} - z_m) = h f(t_m, z_m) with J fixed at the start of the step.
+    #[allow(clippy::cast_precision_loss, clippy::many_single_char_names)]
+    fn row(&mut self, t: f64, y: &DVector<f64>, h: f64, n: usize) -> Option<DVector<f64>> {
+        let h = h / n as f64;
+        let dim = y.len();
+        let lu = (DMatrix::identity(dim, dim) - h * &self.jacobian).lu();
+        let scale = y.map(|y| self.config.abs_tol + self.config.rel_tol * y.abs());
+
+        let mut z = y.clone();
+        let mut first_increment = 0.0;
+        for m in 0..n {
+            let f = if m == 0 {
+                self.f0.clone()
+            } else {
+                self.num_calls += 1;
+                (self.input.f)(t + m as f64 * h, &z)
+            };
+            let increment = lu.solve(&(h * f))?;
+            // A growing Newton-like increment means h is far outside the region where the
+            // frozen Jacobian is adequate.
+            let size = increment.component_div(&scale).amax();
+            if m == 0 {
+                first_increment = size;
+            } else if m == 1 && size > 2.0 * first_increment {
+                return None;
+            }
+            z += increment;
+        }
+        z.iter().all(|z| z.is_finite()).then_some(z)
+    }
+}
+
+pub fn integrate(input: &Input<'_>, config: &Config) -> Result<Output, Error> {
+    integrate_with_observer(input, config, &mut |_, _| {})
+}
+
+/// Extrapolation of the linearly implicit Euler method after SEULEX (Hairer & Wanner), for stiff
+/// problems at tight tolerances. The harmonic sequence keeps the first rows cheap, and the error
+/// expansion in powers of h gives orders up to 8.
+///
+/// After y0, the extrapolated state at the end of each accepted step is passed to `observer`.
+pub fn integrate_with_observer(
+    input: &Input<'_>,
+    config: &Config,
+    observer: &mut dyn FnMut(f64, &DVector<f64>),
+) -> Result<Output, Error> {
+    validate_span(input.t_span, input.h0)?;
+
+    let mut scheme = LinearlyImplicitEuler {
+        input,
+        config,
+        t0: input.t_span[0],
+        f0: DVector::zeros(0),
+        jacobian: DMatrix::zeros(0, 0),
+        num_calls: 0,
+        num_jacobians: 0,
+    };
+    let output = extrapolation::integrate(
+        &mut scheme,
+        input.t_span,
+        input.y0,
+        input.h0,
+        config,
+        observer,
+    )?;
+
+    Ok(Output {
+        y: output.y,
+        h: output.h,
+        order: output.column + 1,
+        num_calls: scheme.num_calls,
+        num_jacobians: scheme.num_jacobians,
+        num_steps: output.num_steps,
+        num_rejected: output.num_rejected,
+    })
+}
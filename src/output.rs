@@ -0,0 +1,166 @@
+use std::fmt::{self, Write as _};
+use std::io;
+
+use nalgebra::DVector;
+
+use crate::contig::{Element, Layout};
+
+#[cfg(feature = "arrow")]
+pub mod arrow;
+pub mod csv;
+pub mod jsonl;
+#[cfg(feature = "mcap")]
+pub mod mcap;
+
+#[derive(Debug)]
+pub enum Error {
+    Io(io::Error),
+    #[cfg(feature = "arrow")]
+    Arrow(arrow_schema::ArrowError),
+    #[cfg(feature = "arrow")]
+    Parquet(parquet::errors::ParquetError),
+    #[cfg(feature = "mcap")]
+    Mcap(::mcap::McapError),
+    /// A message is stamped before the start time of the log.
+    #[cfg(feature = "mcap")]
+    BeforeStart {
+        t: f64,
+        t0: f64,
+    },
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Io(err) => write!(f, "{err}"),
+            #[cfg(feature = "arrow")]
+            Error::Arrow(err) => write!(f, "{err}"),
+            #[cfg(feature = "arrow")]
+            Error::Parquet(err) => write!(f, "{err}"),
+            #[cfg(feature = "mcap")]
+            Error::Mcap(err) => write!(f, "{err}"),
+            #[cfg(feature = "mcap")]
+            Error::BeforeStart { t, t0 } => {
+                write!(f, "time {t} s is before the start of the log at {t0} s")
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Error {
+        Error::Io(err)
+    }
+}
+
+/// A sink for (t, y) rows. Writers stream each row as it arrives, buffering at most one batch.
+pub trait Writer {
+    fn write(&mut self, t: f64, y: &DVector<f64>) -> Result<(), Error>;
+    fn finish(&mut self) -> Result<(), Error>;
+}
+
+/// Column descriptions of each state element, from the layout when available.
+#[must_use]
+pub fn elements(layout: Option<&Layout>, len: usize) -> Vec<Element> {
+    match layout {
+        Some(layout) => layout.elements(),
+        None => (0..len)
+            .map(|i| Element {
+                name: format!("y[{i}]"),
+                unit: None,
+                description: None,
+            })
+            .collect(),
+    }
+}
+
+/// Adapts a writer to an integration observer. The observer cannot fail, so the first error is held
+/// and returned from `finish`, and later rows are dropped.
+pub struct Stream<W> {
+    writer: W,
+    error: Option<Error>,
+}
+
+impl<W: Writer> Stream<W> {
+    pub fn new(writer: W) -> Self {
+        Self {
+            writer,
+            error: None,
+        }
+    }
+
+    pub fn observe(&mut self, t: f64, y: &DVector<f64>) {
+        if self.error.is_none() {
+            self.error = self.writer.write(t, y).err();
+        }
+    }
+
+    pub fn get_mut(&mut self) -> &mut W {
+        &mut self.writer
+    }
+
+    pub fn finish(mut self) -> Result<W, Error> {
+        if let Some(err) = self.error {
+            return Err(err);
+        }
+        self.writer.finish()?;
+        Ok(self.writer)
+    }
+}
+
+// JSON has no representation of non-finite numbers.
+pub(crate) fn json_number(x: f64) -> String {
+    if x.is_finite() {
+        format!("{x:?}")
+    } else {
+        String::from("null")
+    }
+}
+
+// Quotes, backslashes and control characters, which JSON strings cannot hold as they are.
+pub(crate) fn json_escape(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            // Writing to a String cannot fail.
+            c if c < ' ' => {
+                let _ = write!(escaped, "\\u{:04x}", u32::from(c));
+            }
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// An in memory trajectory, for runs short enough to keep every step.
+#[derive(Debug, Default, Clone)]
+pub struct Trajectory {
+    pub t: Vec<f64>,
+    pub y: Vec<DVector<f64>>,
+}
+
+impl Trajectory {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn observe(&mut self, t: f64, y: &DVector<f64>) {
+        self.t.push(t);
+        self.y.push(y.clone());
+    }
+
+    pub fn write(&self, writer: &mut dyn Writer) -> Result<(), Error> {
+        for (t, y) in self.t.iter().zip(&self.y) {
+            writer.write(*t, y)?;
+        }
+        writer.finish()
+    }
+}
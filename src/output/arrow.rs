@@ -0,0 +1,205 @@
+use std::collections::HashMap;
+use std::io::Write;
+use std::mem;
+use std::sync::Arc;
+
+use arrow_array::{ArrayRef, Float64Array, RecordBatch};
+use arrow_schema::{ArrowError, DataType, Field, Schema, SchemaRef};
+use nalgebra::DVector;
+
+use super::{Error, Writer};
+use crate::contig::Element;
+
+pub const DEFAULT_BATCH_SIZE: usize = 4096;
+
+impl From<ArrowError> for Error {
+    fn from(err: ArrowError) -> Error {
+        Error::Arrow(err)
+    }
+}
+
+impl From<parquet::errors::ParquetError> for Error {
+    fn from(err: parquet::errors::ParquetError) -> Error {
+        Error::Parquet(err)
+    }
+}
+
+/// One Float64 column per element, with units and descriptions kept as field metadata.
+#[must_use]
+pub fn schema(elements: &[Element]) -> SchemaRef {
+    let column = |name: &str, unit: Option<&str>, description: Option<&str>| {
+        let metadata: HashMap<_, _> = [("unit", unit), ("description", description)]
+            .into_iter()
+            .filter_map(|(key, value)| Some((key.to_string(), value?.to_string())))
+            .collect();
+        Field::new(name, DataType::Float64, false).with_metadata(metadata)
+    };
+
+    let fields: Vec<_> = std::iter::once(column("t", Some("s"), Some("Time")))
+        .chain(
+            elements
+                .iter()
+                .map(|e| column(&e.name, e.unit, e.description)),
+        )
+        .collect();
+    Arc::new(Schema::new(fields))
+}
+
+// Rows buffered column-wise until a record batch is full.
+struct Batch {
+    schema: SchemaRef,
+    columns: Vec<Vec<f64>>,
+    capacity: usize,
+}
+
+impl Batch {
+    fn new(schema: SchemaRef, batch_size: usize) -> Self {
+        let columns = vec![Vec::with_capacity(batch_size); schema.fields().len()];
+        Self {
+            schema,
+            columns,
+            capacity: batch_size,
+        }
+    }
+
+    fn push(&mut self, t: f64, y: &DVector<f64>) {
+        self.columns[0].push(t);
+        for (column, x) in self.columns[1..].iter_mut().zip(y) {
+            column.push(*x);
+        }
+    }
+
+    fn is_full(&self) -> bool {
+        self.columns[0].len() >= self.capacity
+    }
+
+    fn is_empty(&self) -> bool {
+        self.columns[0].is_empty()
+    }
+
+    fn take(&mut self) -> Result<RecordBatch, ArrowError> {
+        let columns = self
+            .columns
+            .iter_mut()
+            .map(|column| {
+                let column = mem::replace(column, Vec::with_capacity(self.capacity));
+                Arc::new(Float64Array::from(column)) as ArrayRef
+            })
+            .collect();
+        RecordBatch::try_new(self.schema.clone(), columns)
+    }
+}
+
+/// Arrow IPC file format, readable with `pyarrow.ipc.open_file` or `polars.read_ipc`.
+pub struct IpcWriter<W: Write> {
+    writer: arrow_ipc::writer::FileWriter<W>,
+    batch: Batch,
+    finished: bool,
+}
+
+impl<W: Write> IpcWriter<W> {
+    pub fn new(inner: W, elements: &[Element], batch_size: usize) -> Result<Self, Error> {
+        let schema = schema(elements);
+        Ok(Self {
+            writer: arrow_ipc::writer::FileWriter::try_new(inner, &schema)?,
+            batch: Batch::new(schema, batch_size),
+            finished: false,
+        })
+    }
+
+    /// Finishes the file first if `finish` has not been called.
+    pub fn into_inner(mut self) -> Result<W, Error> {
+        self.finish()?;
+        Ok(self.writer.into_inner()?)
+    }
+}
+
+impl<W: Write> Writer for IpcWriter<W> {
+    fn write(&mut self, t: f64, y: &DVector<f64>) -> Result<(), Error> {
+        self.batch.push(t, y);
+        if self.batch.is_full() {
+            self.writer.write(&self.batch.take()?)?;
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<(), Error> {
+        if self.finished {
+            return Ok(());
+        }
+        if !self.batch.is_empty() {
+            self.writer.write(&self.batch.take()?)?;
+        }
+        self.writer.finish()?;
+        self.finished = true;
+        Ok(())
+    }
+}
+
+// The Arrow writer can only give back its inner writer by finishing the file, so finishing takes
+// it apart and keeps the inner writer for `into_inner`.
+enum ParquetState<W: Write + Send> {
+    Open(Box<parquet::arrow::ArrowWriter<W>>),
+    Finished(W),
+    // Finishing failed part way and the inner writer is lost.
+    Failed,
+}
+
+pub struct ParquetWriter<W: Write + Send> {
+    state: ParquetState<W>,
+    batch: Batch,
+}
+
+impl<W: Write + Send> ParquetWriter<W> {
+    pub fn new(inner: W, elements: &[Element], batch_size: usize) -> Result<Self, Error> {
+        let schema = schema(elements);
+        let writer = parquet::arrow::ArrowWriter::try_new(inner, schema.clone(), None)?;
+        Ok(Self {
+            state: ParquetState::Open(Box::new(writer)),
+            batch: Batch::new(schema, batch_size),
+        })
+    }
+
+    /// Finishes the file first if `finish` has not been called.
+    pub fn into_inner(mut self) -> Result<W, Error> {
+        self.finish()?;
+        match self.state {
+            ParquetState::Finished(inner) => Ok(inner),
+            ParquetState::Open(_) | ParquetState::Failed => Err(closed()),
+        }
+    }
+}
+
+fn closed() -> Error {
+    parquet::errors::ParquetError::General(String::from("parquet writer is closed")).into()
+}
+
+impl<W: Write + Send> Writer for ParquetWriter<W> {
+    fn write(&mut self, t: f64, y: &DVector<f64>) -> Result<(), Error> {
+        let ParquetState::Open(writer) = &mut self.state else {
+            return Err(closed());
+        };
+        self.batch.push(t, y);
+        if self.batch.is_full() {
+            writer.write(&self.batch.take()?)?;
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<(), Error> {
+        match mem::replace(&mut self.state, ParquetState::Failed) {
+            ParquetState::Open(mut writer) => {
+                if !self.batch.is_empty() {
+                    writer.write(&self.batch.take()?)?;
+                }
+                self.state = ParquetState::Finished(writer.into_inner()?);
+                Ok(())
+            }
+            ParquetState::Finished(inner) => {
+                self.state = ParquetState::Finished(inner);
+                Ok(())
+            }
+            ParquetState::Failed => Err(closed()),
+        }
+    }
+}
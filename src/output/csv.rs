@@ -0,0 +1,50 @@
+use std::io::Write;
+
+use nalgebra::DVector;
+
+use super::{Error, Writer};
+use crate::contig::Element;
+
+pub struct CsvWriter<W: Write> {
+    inner: W,
+}
+
+impl<W: Write> CsvWriter<W> {
+    /// Writes the header immediately, e.g. `t [s],vehicle.velocity.x [m/s]`.
+    pub fn new(mut inner: W, elements: &[Element]) -> Result<Self, Error> {
+        write!(inner, "t [s]")?;
+        for element in elements {
+            write!(inner, ",{}", escape(&element.to_string()))?;
+        }
+        writeln!(inner)?;
+        Ok(Self { inner })
+    }
+
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+}
+
+impl<W: Write> Writer for CsvWriter<W> {
+    fn write(&mut self, t: f64, y: &DVector<f64>) -> Result<(), Error> {
+        write!(self.inner, "{t}")?;
+        for x in y {
+            write!(self.inner, ",{x}")?;
+        }
+        writeln!(self.inner)?;
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<(), Error> {
+        self.inner.flush()?;
+        Ok(())
+    }
+}
+
+fn escape(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
@@ -0,0 +1,43 @@
+use std::io::Write;
+
+use nalgebra::DVector;
+
+use super::{json_escape, json_number, Error, Writer};
+use crate::contig::Element;
+
+/// One JSON object per row, keyed by the same labels as the CSV header.
+pub struct JsonLinesWriter<W: Write> {
+    inner: W,
+    keys: Vec<String>,
+}
+
+impl<W: Write> JsonLinesWriter<W> {
+    #[must_use]
+    pub fn new(inner: W, elements: &[Element]) -> Self {
+        let keys = elements
+            .iter()
+            .map(|element| json_escape(&element.to_string()))
+            .collect();
+        Self { inner, keys }
+    }
+
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+}
+
+impl<W: Write> Writer for JsonLinesWriter<W> {
+    fn write(&mut self, t: f64, y: &DVector<f64>) -> Result<(), Error> {
+        write!(self.inner, "{{\"t [s]\":{}", json_number(t))?;
+        for (key, x) in self.keys.iter().zip(y) {
+            write!(self.inner, ",\"{key}\":{}", json_number(*x))?;
+        }
+        writeln!(self.inner, "}}")?;
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<(), Error> {
+        self.inner.flush()?;
+        Ok(())
+    }
+}
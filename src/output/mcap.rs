@@ -0,0 +1,269 @@
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::io::{Seek, Write};
+
+use nalgebra::DVector;
+
+use super::{json_escape, json_number, Error, Writer};
+use crate::contig::Element;
+
+pub const STATE_TOPIC: &str = "/state";
+
+impl From<::mcap::McapError> for Error {
+    fn from(err: ::mcap::McapError) -> Error {
+        Error::Mcap(err)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Channel(u16);
+
+// Element names nest into JSON objects and arrays, so `vehicle.velocity.x` is plotted in Foxglove
+// as `/state.y.vehicle.velocity.x` and `angles[1]` as `/state.y.angles[1]`.
+#[derive(Debug)]
+enum Node {
+    Leaf(usize),
+    Object(Vec<(String, Node)>),
+    Array(Vec<Node>),
+}
+
+enum Segment<'a> {
+    Key(&'a str),
+    Index(usize),
+}
+
+fn segments(name: &str) -> Vec<Segment<'_>> {
+    let mut segments = Vec::new();
+    for part in name.split('.') {
+        let mut indices = part.split('[');
+        segments.push(Segment::Key(indices.next().unwrap_or_default()));
+        for index in indices {
+            match index.trim_end_matches(']').parse() {
+                Ok(index) => segments.push(Segment::Index(index)),
+                Err(_) => segments.push(Segment::Key(index)),
+            }
+        }
+    }
+    segments
+}
+
+impl Node {
+    fn new(segment: Option<&Segment<'_>>, element: usize) -> Node {
+        match segment {
+            None => Node::Leaf(element),
+            Some(Segment::Key(_)) => Node::Object(Vec::new()),
+            Some(Segment::Index(_)) => Node::Array(Vec::new()),
+        }
+    }
+
+    // Names that do not nest consistently, such as `a` alongside `a.b` or `v[1]` without `v[0]`,
+    // would give duplicate keys or sparse arrays, so then every element is kept flat under its full
+    // name instead.
+    fn from_elements(elements: &[Element]) -> Node {
+        let mut root = Node::Object(Vec::new());
+        let nested = elements
+            .iter()
+            .enumerate()
+            .all(|(i, element)| root.insert(&segments(&element.name), i));
+        if nested {
+            return root;
+        }
+        Node::Object(
+            elements
+                .iter()
+                .enumerate()
+                .map(|(i, element)| (element.name.clone(), Node::Leaf(i)))
+                .collect(),
+        )
+    }
+
+    fn insert(&mut self, path: &[Segment<'_>], element: usize) -> bool {
+        let Some((first, rest)) = path.split_first() else {
+            return matches!(self, Node::Leaf(_));
+        };
+        match (self, first) {
+            (Node::Object(children), Segment::Key(key)) => {
+                if let Some((_, child)) = children.iter_mut().find(|(k, _)| k == key) {
+                    child.insert(rest, element)
+                } else {
+                    let mut child = Node::new(rest.first(), element);
+                    let inserted = child.insert(rest, element);
+                    children.push(((*key).to_string(), child));
+                    inserted
+                }
+            }
+            (Node::Array(children), Segment::Index(index)) if *index == children.len() => {
+                let mut child = Node::new(rest.first(), element);
+                let inserted = child.insert(rest, element);
+                children.push(child);
+                inserted
+            }
+            (Node::Array(children), Segment::Index(index)) if *index + 1 == children.len() => {
+                children[*index].insert(rest, element)
+            }
+            _ => false,
+        }
+    }
+
+    fn write_value(&self, y: &DVector<f64>, out: &mut String) {
+        match self {
+            Node::Leaf(i) => out.push_str(&json_number(y[*i])),
+            Node::Object(children) => {
+                out.push('{');
+                for (i, (key, child)) in children.iter().enumerate() {
+                    if i > 0 {
+                        out.push(',');
+                    }
+                    let _ = write!(out, "\"{}\":", json_escape(key));
+                    child.write_value(y, out);
+                }
+                out.push('}');
+            }
+            Node::Array(children) => {
+                out.push('[');
+                for (i, child) in children.iter().enumerate() {
+                    if i > 0 {
+                        out.push(',');
+                    }
+                    child.write_value(y, out);
+                }
+                out.push(']');
+            }
+        }
+    }
+
+    fn write_schema(&self, elements: &[Element], out: &mut String) {
+        match self {
+            Node::Leaf(i) => {
+                let element = &elements[*i];
+                let description = match (element.unit, element.description) {
+                    (Some(unit), Some(description)) => format!("{description} [{unit}]"),
+                    (Some(unit), None) => format!("[{unit}]"),
+                    (None, Some(description)) => description.to_string(),
+                    (None, None) => String::new(),
+                };
+                let _ = write!(
+                    out,
+                    "{{\"type\":\"number\",\"description\":\"{}\"}}",
+                    json_escape(&description)
+                );
+            }
+            Node::Object(children) => {
+                out.push_str("{\"type\":\"object\",\"properties\":{");
+                for (i, (key, child)) in children.iter().enumerate() {
+                    if i > 0 {
+                        out.push(',');
+                    }
+                    let _ = write!(out, "\"{}\":", json_escape(key));
+                    child.write_schema(elements, out);
+                }
+                out.push_str("}}");
+            }
+            Node::Array(children) => {
+                out.push_str("{\"type\":\"array\",\"prefixItems\":[");
+                for (i, child) in children.iter().enumerate() {
+                    if i > 0 {
+                        out.push(',');
+                    }
+                    child.write_schema(elements, out);
+                }
+                out.push_str("]}");
+            }
+        }
+    }
+}
+
+// Nanoseconds of simulation time since the start of the log, as MCAP timestamps are unsigned.
+#[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+fn timestamp(t: f64, t0: f64) -> Result<u64, Error> {
+    if t < t0 {
+        return Err(Error::BeforeStart { t, t0 });
+    }
+    Ok(((t - t0) * 1e9).round() as u64)
+}
+
+/// Writes integration output to the `/state` topic and any user channels to an MCAP file with JSON
+/// messages and a JSON schema per channel, stamped with simulation time relative to `t0`, typically
+/// `t_span[0]`. The messages themselves carry the absolute time.
+pub struct McapLogger<W: Write + Seek> {
+    writer: ::mcap::Writer<W>,
+    t0: f64,
+    state: Node,
+    state_channel: Channel,
+    sequences: BTreeMap<u16, u32>,
+}
+
+impl<W: Write + Seek> McapLogger<W> {
+    pub fn new(inner: W, elements: &[Element], t0: f64) -> Result<Self, Error> {
+        let mut writer = ::mcap::WriteOptions::new()
+            .library("finfoot")
+            .create(inner)?;
+
+        let state = Node::from_elements(elements);
+        let mut schema = String::from(
+            "{\"type\":\"object\",\"properties\":{\"t\":{\"type\":\"number\",\"description\":\"[s]\"},\"y\":",
+        );
+        state.write_schema(elements, &mut schema);
+        schema.push_str("}}");
+
+        let schema_id = writer.add_schema("finfoot.State", "jsonschema", schema.as_bytes())?;
+        let channel_id = writer.add_channel(schema_id, STATE_TOPIC, "json", &BTreeMap::new())?;
+
+        Ok(Self {
+            writer,
+            t0,
+            state,
+            state_channel: Channel(channel_id),
+            sequences: BTreeMap::new(),
+        })
+    }
+
+    pub fn add_channel(
+        &mut self,
+        topic: &str,
+        schema_name: &str,
+        json_schema: &str,
+    ) -> Result<Channel, Error> {
+        let schema_id =
+            self.writer
+                .add_schema(schema_name, "jsonschema", json_schema.as_bytes())?;
+        let channel_id = self
+            .writer
+            .add_channel(schema_id, topic, "json", &BTreeMap::new())?;
+        Ok(Channel(channel_id))
+    }
+
+    /// Logs a JSON message on a channel at simulation time t.
+    pub fn log(&mut self, channel: Channel, t: f64, json: &str) -> Result<(), Error> {
+        let time = timestamp(t, self.t0)?;
+        let sequence = self.sequences.entry(channel.0).or_insert(0);
+        let header = ::mcap::records::MessageHeader {
+            channel_id: channel.0,
+            sequence: *sequence,
+            log_time: time,
+            publish_time: time,
+        };
+        *sequence += 1;
+        self.writer
+            .write_to_known_channel(&header, json.as_bytes())?;
+        Ok(())
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer.into_inner()
+    }
+}
+
+impl<W: Write + Seek> Writer for McapLogger<W> {
+    fn write(&mut self, t: f64, y: &DVector<f64>) -> Result<(), Error> {
+        let mut json = format!("{{\"t\":{},\"y\":", json_number(t));
+        self.state.write_value(y, &mut json);
+        json.push('}');
+        self.log(self.state_channel, t, &json)
+    }
+
+    fn finish(&mut self) -> Result<(), Error> {
+        self.writer.finish()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,187 @@
+use std::marker::PhantomData;
+
+use typenum::operator_aliases::{Diff, Sub1, Sum};
+use typenum::{Integer, UInt, UTerm, B1, P1};
+use uom::num_traits::Num;
+use uom::si::{Dimension, Quantity, Units, ISQ};
+use uom::Conversion;
+
+pub trait DivideByTime {
+    type Type;
+}
+
+impl<D, U, V> DivideByTime for Quantity<D, U, V>
+where
+    D: Dimension + ?Sized,
+    U: Units<V> + ?Sized,
+    V: Num + Conversion<V>,
+    D::T: std::ops::Sub<P1>,
+    Diff<D::T, P1>: Integer,
+{
+    type Type = Quantity<ISQ<D::L, D::M, Diff<D::T, P1>, D::I, D::Th, D::N, D::J, D::Kind>, U, V>;
+}
+
+pub type TimeDerivative<T> = <T as DivideByTime>::Type;
+
+pub trait MultiplyByTime {
+    type Type;
+}
+
+impl<D, U, V> MultiplyByTime for Quantity<D, U, V>
+where
+    D: Dimension + ?Sized,
+    U: Units<V> + ?Sized,
+    V: Num + Conversion<V>,
+    D::T: std::ops::Add<P1>,
+    Sum<D::T, P1>: Integer,
+{
+    type Type = Quantity<ISQ<D::L, D::M, Sum<D::T, P1>, D::I, D::Th, D::N, D::J, D::Kind>, U, V>;
+}
+
+pub type TimeIntegral<T> = <T as MultiplyByTime>::Type;
+
+/// N is a typenum unsigned integer, e.g. `NthTimeDerivative<Length, U3>` is `Jerk`.
+pub trait DivideByTimeN<N> {
+    type Type;
+}
+
+impl<T> DivideByTimeN<UTerm> for T {
+    type Type = T;
+}
+
+impl<T, U, B> DivideByTimeN<UInt<U, B>> for T
+where
+    T: DivideByTime,
+    UInt<U, B>: std::ops::Sub<B1>,
+    TimeDerivative<T>: DivideByTimeN<Sub1<UInt<U, B>>>,
+{
+    type Type = <TimeDerivative<T> as DivideByTimeN<Sub1<UInt<U, B>>>>::Type;
+}
+
+pub type NthTimeDerivative<T, N> = <T as DivideByTimeN<N>>::Type;
+
+pub trait MultiplyByTimeN<N> {
+    type Type;
+}
+
+impl<T> MultiplyByTimeN<UTerm> for T {
+    type Type = T;
+}
+
+impl<T, U, B> MultiplyByTimeN<UInt<U, B>> for T
+where
+    T: MultiplyByTime,
+    UInt<U, B>: std::ops::Sub<B1>,
+    TimeIntegral<T>: MultiplyByTimeN<Sub1<UInt<U, B>>>,
+{
+    type Type = <TimeIntegral<T> as MultiplyByTimeN<Sub1<UInt<U, B>>>>::Type;
+}
+
+pub type NthTimeIntegral<T, N> = <T as MultiplyByTimeN<N>>::Type;
+
+/// A fixed size group of f64 values that can be packed into and unpacked from a flat slice.
+/// Quantities are stored in base units.
+pub trait StateElement: Sized {
+    const LEN: usize;
+
+    fn pack(&self, out: &mut [f64]);
+    fn unpack(values: &[f64]) -> Self;
+}
+
+/// A state whose time derivative is known at the type level.
+pub trait UnitState: StateElement {
+    type Derivative: StateElement;
+}
+
+impl<D, U> StateElement for Quantity<D, U, f64>
+where
+    D: Dimension + ?Sized,
+    U: Units<f64> + ?Sized,
+{
+    const LEN: usize = 1;
+
+    fn pack(&self, out: &mut [f64]) {
+        out[0] = self.value;
+    }
+
+    fn unpack(values: &[f64]) -> Self {
+        Quantity {
+            dimension: PhantomData,
+            units: PhantomData,
+            value: values[0],
+        }
+    }
+}
+
+impl<D, U> UnitState for Quantity<D, U, f64>
+where
+    D: Dimension + ?Sized,
+    U: Units<f64> + ?Sized,
+    Self: DivideByTime,
+    TimeDerivative<Self>: StateElement,
+{
+    type Derivative = TimeDerivative<Self>;
+}
+
+impl<T: StateElement, const N: usize> StateElement for [T; N] {
+    const LEN: usize = N * T::LEN;
+
+    fn pack(&self, out: &mut [f64]) {
+        for (element, out) in self.iter().zip(out.chunks_exact_mut(T::LEN)) {
+            element.pack(out);
+        }
+    }
+
+    fn unpack(values: &[f64]) -> Self {
+        std::array::from_fn(|i| T::unpack(&values[i * T::LEN..(i + 1) * T::LEN]))
+    }
+}
+
+impl<T: UnitState, const N: usize> UnitState for [T; N] {
+    type Derivative = [T::Derivative; N];
+}
+
+macro_rules! impl_tuple_state {
+    ($($name:ident),+) => {
+        impl<$($name: StateElement),+> StateElement for ($($name,)+) {
+            const LEN: usize = 0 $(+ $name::LEN)+;
+
+            #[allow(non_snake_case)]
+            fn pack(&self, out: &mut [f64]) {
+                let ($($name,)+) = self;
+                let mut offset = 0;
+                $(
+                    $name.pack(&mut out[offset..offset + $name::LEN]);
+                    offset += $name::LEN;
+                )+
+                debug_assert_eq!(offset, Self::LEN);
+            }
+
+            fn unpack(values: &[f64]) -> Self {
+                let mut offset = 0;
+                let state = (
+                    $({
+                        let element = $name::unpack(&values[offset..offset + $name::LEN]);
+                        offset += $name::LEN;
+                        element
+                    },)+
+                );
+                debug_assert_eq!(offset, Self::LEN);
+                state
+            }
+        }
+
+        impl<$($name: UnitState),+> UnitState for ($($name,)+) {
+            type Derivative = ($($name::Derivative,)+);
+        }
+    };
+}
+
+impl_tuple_state!(A);
+impl_tuple_state!(A, B);
+impl_tuple_state!(A, B, C);
+impl_tuple_state!(A, B, C, D);
+impl_tuple_state!(A, B, C, D, E);
+impl_tuple_state!(A, B, C, D, E, F);
+impl_tuple_state!(A, B, C, D, E, F, G);
+impl_tuple_state!(A, B, C, D, E, F, G, H);
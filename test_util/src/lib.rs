@@ -8,7 +8,7 @@ pub struct OdeProblem {
     pub name: String,
     pub t_span: [f64; 2],
     pub y0: DVector<f64>,
-    pub f: Box<DerivativeFunc>,
+    pub f: Box<DerivativeFunc<'static>>,
     pub yf: DVector<f64>,
     pub tolerance: DVector<f64>,
 }
@@ -17,6 +17,8 @@ fn calc_tolerance(y: &DVector<f64>, rel_tol: f64, abs_tol: f64) -> DVector<f64>
     (rel_tol * y).abs().map(|x| x.max(abs_tol.abs()))
 }
 
+#[must_use]
+#[allow(clippy::too_many_lines, clippy::cast_precision_loss)]
 pub fn all_problems() -> HashMap<String, OdeProblem> {
     let mut problems = HashMap::new();
 
@@ -25,7 +27,7 @@ pub fn all_problems() -> HashMap<String, OdeProblem> {
     let t_span = [0.0, 1.0];
     let tau = 1.0;
     let y0 = dvector![1.0];
-    let f: Box<DerivativeFunc> = Box::new(move |_, y| -tau * y);
+    let f: Box<DerivativeFunc<'static>> = Box::new(move |_, y| -tau * y);
     let yf = dvector![f64::exp(-tau * t_span[1])];
     let tolerance = calc_tolerance(&yf, 1e-4, 1e-6);
 
@@ -46,7 +48,7 @@ pub fn all_problems() -> HashMap<String, OdeProblem> {
     let t_span = [0.0, 1.0];
     let omega = 2.0 * std::f64::consts::PI;
     let y0 = dvector![1.0, 0.0];
-    let f: Box<DerivativeFunc> = Box::new(move |_, y| {
+    let f: Box<DerivativeFunc<'static>> = Box::new(move |_, y| {
         let x = y[0];
         let dx = y[1];
         dvector![dx, -omega.powi(2) * x]
@@ -71,7 +73,7 @@ pub fn all_problems() -> HashMap<String, OdeProblem> {
     let t_span = [0.0, 15.0];
     let mu = 5.0;
     let y0 = dvector![1.0, 0.0];
-    let f: Box<DerivativeFunc> = Box::new(move |_, y| {
+    let f: Box<DerivativeFunc<'static>> = Box::new(move |_, y| {
         let x = y[0];
         let dx = y[1];
         dvector![dx, mu * (1.0 - x.powi(2)) * dx - x]
@@ -98,7 +100,7 @@ pub fn all_problems() -> HashMap<String, OdeProblem> {
     let rho = 28.0;
     let beta = 8.0 / 3.0;
     let y0 = dvector![1.0, 1.0, 1.0];
-    let f: Box<DerivativeFunc> = Box::new(move |_, y| {
+    let f: Box<DerivativeFunc<'static>> = Box::new(move |_, y| {
         let (x, y, z) = (y[0], y[1], y[2]);
         dvector![sigma * (y - x), x * (rho - z) - y, x * y - beta * z]
     });
@@ -121,7 +123,7 @@ pub fn all_problems() -> HashMap<String, OdeProblem> {
     let name = String::from("robertson_equations");
     let t_span = [0.0, 30.0];
     let y0 = dvector![1.0, 0.0, 0.0];
-    let f: Box<DerivativeFunc> = Box::new(move |_, y| {
+    let f: Box<DerivativeFunc<'static>> = Box::new(move |_, y| {
         let (y1, y2, y3) = (y[0], y[1], y[2]);
         dvector![
             -0.04 * y1 + 1e4 * y2 * y3,
@@ -156,7 +158,7 @@ pub fn all_problems() -> HashMap<String, OdeProblem> {
         y0[n + i] = (i as f64).cos(); // Initial velocities
     }
 
-    let f: Box<DerivativeFunc> = Box::new(move |_, y| {
+    let f: Box<DerivativeFunc<'static>> = Box::new(move |_, y| {
         let mut dydt = DVector::zeros(2 * n);
         for i in 0..n {
             dydt[i] = y[n + i]; // Velocity
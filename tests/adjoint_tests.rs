@@ -0,0 +1,162 @@
+use nalgebra::{dmatrix, dvector, DVector};
+use speculoos::prelude::*;
+
+use finfoot::ode::adjoint::{self, Cost, Gradient};
+use finfoot::ode::dopri5;
+use finfoot::ode::jacobian::{Difference, Jacobian};
+use finfoot::ode::sensitivity;
+
+const CONFIG: dopri5::Config = dopri5::Config {
+    rel_tol: 1e-10,
+    abs_tol: 1e-12,
+};
+
+const FINITE_DIFFERENCE: Jacobian<'static> = Jacobian::FiniteDifference {
+    difference: Difference::Forward,
+    sparsity: None,
+};
+
+const T_SPAN: [f64; 2] = [0.0, 5.0];
+
+fn van_der_pol(_: f64, y: &DVector<f64>, p: &DVector<f64>) -> DVector<f64> {
+    dvector![y[1], p[0] * (1.0 - y[0].powi(2)) * y[1] - y[0]]
+}
+
+fn assert_vector_close(a: &DVector<f64>, b: &DVector<f64>, rel_tol: f64, name: &str) {
+    assert_that!(a.len()).is_equal_to(b.len());
+    let scale = b.amax().max(1.0);
+    for i in 0..a.len() {
+        assert_that!(a[i])
+            .named(&format!("{name} element {i}"))
+            .is_close_to(b[i], rel_tol * scale);
+    }
+}
+
+fn terminal(_: f64, y: &DVector<f64>, _: &DVector<f64>) -> f64 {
+    0.5 * y.norm_squared()
+}
+
+fn running(_: f64, y: &DVector<f64>, p: &DVector<f64>) -> f64 {
+    y[0].powi(2) + 0.1 * p[0] * y[1].powi(2)
+}
+
+fn integrate(y0: &DVector<f64>, p: &DVector<f64>) -> adjoint::Output {
+    adjoint::integrate(
+        &adjoint::Input {
+            t_span: T_SPAN,
+            y0,
+            p,
+            h0: 0.05,
+            f: &van_der_pol,
+            jacobian_y: FINITE_DIFFERENCE,
+            jacobian_p: FINITE_DIFFERENCE,
+            terminal: Some(Cost {
+                value: &terminal,
+                gradient_y: Gradient::Analytic(&|_, y, _| y.clone()),
+                gradient_p: Gradient::Analytic(&|_, _, p| DVector::zeros(p.len())),
+            }),
+            running: Some(Cost {
+                value: &running,
+                gradient_y: Gradient::FiniteDifference,
+                gradient_p: Gradient::FiniteDifference,
+            }),
+        },
+        &CONFIG,
+    )
+    .unwrap()
+}
+
+// Central differences of the cost through the whole forward integration.
+fn cost_difference(x: &DVector<f64>, cost: impl Fn(&DVector<f64>) -> f64) -> DVector<f64> {
+    const DELTA: f64 = 1e-5;
+    DVector::from_fn(x.len(), |j, _| {
+        let mut plus = x.clone();
+        let mut minus = x.clone();
+        plus[j] += DELTA;
+        minus[j] -= DELTA;
+        (cost(&plus) - cost(&minus)) / (2.0 * DELTA)
+    })
+}
+
+#[test]
+fn test_gradient_matches_finite_differences() {
+    let y0 = dvector![1.0, 0.0];
+    let p = dvector![2.0];
+    let output = integrate(&y0, &p);
+
+    assert_that!(output.cost).is_greater_than(terminal(T_SPAN[1], &output.y, &p));
+
+    let expected_y0 = cost_difference(&y0, |y0| integrate(y0, &p).cost);
+    let expected_p = cost_difference(&p, |p| integrate(&y0, p).cost);
+    assert_vector_close(&output.gradient_y0, &expected_y0, 1e-5, "dJ/dy0");
+    assert_vector_close(&output.gradient_p, &expected_p, 1e-5, "dJ/dp");
+}
+
+#[test]
+fn test_matches_forward_sensitivity() {
+    let y0 = dvector![1.0, 0.0];
+    let p = dvector![2.0];
+    // Analytic Jacobians of a parameterized system take z = [y; p].
+    let jacobian_y = |_, z: &DVector<f64>| {
+        dmatrix![
+            0.0, 1.0;
+            -2.0 * z[2] * z[0] * z[1] - 1.0, z[2] * (1.0 - z[0].powi(2));
+        ]
+    };
+    let jacobian_p = |_, z: &DVector<f64>| {
+        dmatrix![
+            0.0;
+            (1.0 - z[0].powi(2)) * z[1];
+        ]
+    };
+
+    let output = adjoint::integrate(
+        &adjoint::Input {
+            t_span: T_SPAN,
+            y0: &y0,
+            p: &p,
+            h0: 0.05,
+            f: &van_der_pol,
+            jacobian_y: Jacobian::Analytic(&jacobian_y),
+            jacobian_p: Jacobian::Analytic(&jacobian_p),
+            terminal: Some(Cost {
+                value: &terminal,
+                gradient_y: Gradient::FiniteDifference,
+                gradient_p: Gradient::FiniteDifference,
+            }),
+            running: None,
+        },
+        &CONFIG,
+    )
+    .unwrap();
+
+    let forward = sensitivity::integrate(
+        &sensitivity::Input {
+            t_span: T_SPAN,
+            y0: &y0,
+            p: &p,
+            h0: 0.05,
+            f: &van_der_pol,
+            jacobian_y: Jacobian::Analytic(&jacobian_y),
+            jacobian_p: Jacobian::Analytic(&jacobian_p),
+            error_control: true,
+        },
+        &CONFIG,
+    )
+    .unwrap();
+
+    // For J = |y(t1)|^2 / 2, dJ/dx = (dy/dx)^T y(t1).
+    assert_that!(output.cost).is_close_to(terminal(T_SPAN[1], &forward.y, &p), 1e-7);
+    assert_vector_close(
+        &output.gradient_y0,
+        &forward.dy_dy0.tr_mul(&forward.y),
+        1e-6,
+        "dJ/dy0",
+    );
+    assert_vector_close(
+        &output.gradient_p,
+        &forward.dy_dp.tr_mul(&forward.y),
+        1e-6,
+        "dJ/dp",
+    );
+}
@@ -0,0 +1,131 @@
+use nalgebra::dvector;
+use speculoos::prelude::*;
+
+use finfoot::ode::{batch, dopri5, Error};
+use test_util::all_problems;
+
+const CONFIG: dopri5::Config = dopri5::Config {
+    rel_tol: 1e-8,
+    abs_tol: 1e-10,
+};
+
+const MU: [f64; 8] = [0.5, 1.0, 2.0, 3.0, 5.0, 8.0, 10.0, 0.0];
+
+fn van_der_pol(t: &[f64; 8], y: &[[f64; 8]], dydt: &mut [[f64; 8]]) {
+    for l in 0..t.len() {
+        let x = y[0][l];
+        let dx = y[1][l];
+        dydt[0][l] = dx;
+        dydt[1][l] = MU[l] * (1.0 - x * x) * dx - x;
+    }
+}
+
+#[test]
+fn test_lanes_match_individual_integration() {
+    let y0 = [[1.0; 8], [0.0; 8]];
+    let input = batch::Input {
+        t_span: [0.0, 15.0],
+        y0: &y0,
+        h0: 0.15,
+        f: &van_der_pol,
+    };
+    let output = batch::integrate(&input, &CONFIG).unwrap();
+
+    for (l, mu) in MU.into_iter().enumerate() {
+        let f = move |_, y: &nalgebra::DVector<f64>| {
+            dvector![y[1], mu * (1.0 - y[0].powi(2)) * y[1] - y[0]]
+        };
+        let expected = dopri5::integrate(
+            &dopri5::Input {
+                t_span: [0.0, 15.0],
+                y0: &dvector![1.0, 0.0],
+                h0: 0.15,
+                f: &f,
+            },
+            &CONFIG,
+        )
+        .unwrap();
+
+        let name = format!("lane {l}");
+        assert_that!(output.y[0][l])
+            .named(&name)
+            .is_close_to(expected.y[0], 1e-6);
+        assert_that!(output.y[1][l])
+            .named(&name)
+            .is_close_to(expected.y[1], 1e-6);
+        assert_that!(output.num_steps[l])
+            .named(&name)
+            .is_equal_to(expected.num_steps);
+    }
+
+    // Stiffer lanes take more steps than the harmonic one.
+    assert_that!(output.num_steps[6]).is_greater_than(output.num_steps[7]);
+}
+
+#[test]
+fn test_matches_reference_solution() {
+    let problem = &all_problems()["van_der_pol_oscillator"];
+    let y0 = [[1.0; 8], [0.0; 8]];
+    let output = batch::integrate(
+        &batch::Input {
+            t_span: problem.t_span,
+            y0: &y0,
+            h0: 0.15,
+            f: &van_der_pol,
+        },
+        &CONFIG,
+    )
+    .unwrap();
+
+    // Lane 4 has the reference problem's mu = 5.
+    assert_that!(output.y[0][4]).is_close_to(problem.yf[0], problem.tolerance[0]);
+    assert_that!(output.y[1][4]).is_close_to(problem.yf[1], problem.tolerance[1]);
+}
+
+#[test]
+fn test_invalid_input() {
+    let y0 = [[1.0; 8], [0.0; 8]];
+    let input = batch::Input {
+        t_span: [0.0, 1.0],
+        y0: &y0,
+        h0: 0.0,
+        f: &van_der_pol,
+    };
+    assert_that!(batch::integrate(&input, &CONFIG)).is_err();
+}
+
+// Element 1 of lane 2 blows up at t = 1 while everything else decays, and the lane keeps its own
+// error naming that element.
+#[test]
+fn test_failed_lane() {
+    let f = |_: &[f64; 4], y: &[[f64; 4]], dydt: &mut [[f64; 4]]| {
+        for l in 0..4 {
+            dydt[0][l] = -y[0][l];
+            dydt[1][l] = if l == 2 { y[1][l] * y[1][l] } else { -y[1][l] };
+        }
+    };
+    let input = batch::Input {
+        t_span: [0.0, 2.0],
+        y0: &[[1.0; 4], [1.0; 4]],
+        h0: 0.01,
+        f: &f,
+    };
+    let output = batch::integrate(&input, &CONFIG).unwrap();
+
+    for l in [0, 1, 3] {
+        let name = format!("lane {l}");
+        assert_that!(output.errors[l].is_none())
+            .named(&name)
+            .is_true();
+        assert_that!(output.t[l]).named(&name).is_equal_to(2.0);
+        assert_that!(output.y[1][l])
+            .named(&name)
+            .is_close_to((-2.0_f64).exp(), 1e-8);
+    }
+    assert_that!(matches!(
+        output.errors[2],
+        Some(Error::Convergence { element: 1, .. })
+    ))
+    .is_true();
+    assert_that!(output.t[2]).is_close_to(1.0, 1e-6);
+}
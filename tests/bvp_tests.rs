@@ -0,0 +1,350 @@
+use nalgebra::{dvector, DVector};
+use speculoos::prelude::*;
+
+use finfoot::ode::bvp::{collocation, shooting};
+use finfoot::ode::dopri5::Config;
+use finfoot::ode::jacobian::{Difference, Dual, Jacobian, Real};
+use finfoot::ode::Error;
+
+const CONFIG: Config = Config {
+    rel_tol: 1e-10,
+    abs_tol: 1e-12,
+};
+
+const FINITE_DIFFERENCE: Jacobian<'static> = Jacobian::FiniteDifference {
+    difference: Difference::Forward,
+    sparsity: None,
+};
+
+// Bratu's problem y'' + exp(y) = 0, y(0) = y(1) = 0, whose lower solution is
+// y = -2 ln(cosh((t - 1/2) theta / 2) / cosh(theta / 4)) with theta = sqrt(2) cosh(theta / 4).
+fn bratu(_: f64, y: &DVector<f64>, _: &DVector<f64>) -> DVector<f64> {
+    dvector![y[1], -y[0].exp()]
+}
+
+fn dirichlet(ya: &DVector<f64>, yb: &DVector<f64>, _: &DVector<f64>) -> DVector<f64> {
+    dvector![ya[0], yb[0]]
+}
+
+fn bratu_solution(t: f64) -> f64 {
+    let mut theta: f64 = 1.0;
+    for _ in 0..50 {
+        let g = theta - 2f64.sqrt() * (theta / 4.0).cosh();
+        let dg = 1.0 - 2f64.sqrt() * (theta / 4.0).sinh() / 4.0;
+        theta -= g / dg;
+    }
+    -2.0 * (((t - 0.5) * theta / 2.0).cosh() / (theta / 4.0).cosh()).ln()
+}
+
+#[test]
+fn test_bratu() {
+    let guess = |_| dvector![0.0, 0.0];
+    let no_params = DVector::zeros(0);
+
+    for num_segments in [1, 4] {
+        let output = shooting::solve(
+            &shooting::Input {
+                t_span: [0.0, 1.0],
+                guess: &guess,
+                p: &no_params,
+                num_segments,
+                h0: 0.1,
+                f: &bratu,
+                jacobian_y: FINITE_DIFFERENCE,
+                jacobian_p: FINITE_DIFFERENCE,
+                boundary: &dirichlet,
+                tolerance: 1e-10,
+                max_iterations: 20,
+            },
+            &CONFIG,
+        )
+        .unwrap();
+        assert_that!(output.t.len()).is_equal_to(num_segments + 1);
+        for (&t, y) in output.t.iter().zip(&output.y) {
+            assert_that!(y[0])
+                .named(&format!("{num_segments} segments, y({t})"))
+                .is_close_to(bratu_solution(t), 1e-8);
+        }
+    }
+
+    let mesh: Vec<f64> = (0..=5).map(|i| f64::from(i) / 5.0).collect();
+    let output = collocation::solve(&collocation::Input {
+        t: &mesh,
+        guess: &guess,
+        p: &no_params,
+        f: &bratu,
+        jacobian_y: FINITE_DIFFERENCE,
+        jacobian_p: FINITE_DIFFERENCE,
+        boundary: &dirichlet,
+        tolerance: 1e-6,
+        max_nodes: 1000,
+        max_iterations: 20,
+    })
+    .unwrap();
+    assert_that!(output.node_limit_reached).is_false();
+    assert_that!(output.max_residual).is_less_than_or_equal_to(1e-6);
+    assert_that!(output.t.len()).is_greater_than(mesh.len());
+    for t in [0.0, 0.1, 0.37, 0.5, 0.92, 1.0] {
+        let y = output.evaluate(t);
+        assert_that!(y[0])
+            .named(&format!("y({t})"))
+            .is_close_to(bratu_solution(t), 1e-7);
+    }
+}
+
+fn bratu_stacked<T: Real>(_: T, z: &DVector<T>) -> DVector<T> {
+    dvector![z[1], -z[0].exp()]
+}
+
+// Automatic differentiation with respect to the parameters of a problem without any.
+#[test]
+fn test_dual_without_params() {
+    let guess = |_| dvector![0.0, 0.0];
+    let no_params = DVector::zeros(0);
+    let jacobian = Jacobian::Dual(&bratu_stacked::<Dual>);
+
+    let output = shooting::solve(
+        &shooting::Input {
+            t_span: [0.0, 1.0],
+            guess: &guess,
+            p: &no_params,
+            num_segments: 2,
+            h0: 0.1,
+            f: &bratu,
+            jacobian_y: jacobian.clone(),
+            jacobian_p: jacobian.clone(),
+            boundary: &dirichlet,
+            tolerance: 1e-10,
+            max_iterations: 20,
+        },
+        &CONFIG,
+    )
+    .unwrap();
+    assert_that!(output.y[1][0]).is_close_to(bratu_solution(0.5), 1e-8);
+
+    let mesh: Vec<f64> = (0..=5).map(|i| f64::from(i) / 5.0).collect();
+    let output = collocation::solve(&collocation::Input {
+        t: &mesh,
+        guess: &guess,
+        p: &no_params,
+        f: &bratu,
+        jacobian_y: jacobian.clone(),
+        jacobian_p: jacobian,
+        boundary: &dirichlet,
+        tolerance: 1e-6,
+        max_nodes: 1000,
+        max_iterations: 20,
+    })
+    .unwrap();
+    let y = output.evaluate(0.5);
+    assert_that!(y[0]).is_close_to(bratu_solution(0.5), 1e-7);
+}
+
+// Van der Pol limit cycle with time scaled by the unknown period T, so the interval is [0, 1].
+// The phase is fixed by starting at a maximum of x.
+#[test]
+fn test_periodic_orbit() {
+    const MU: f64 = 1.0;
+    const PERIOD: f64 = 6.663_286_859_323_13;
+
+    let f = |_, y: &DVector<f64>, p: &DVector<f64>| {
+        p[0] * dvector![y[1], MU * (1.0 - y[0].powi(2)) * y[1] - y[0]]
+    };
+    let periodic = |ya: &DVector<f64>, yb: &DVector<f64>, _: &DVector<f64>| {
+        dvector![ya[0] - yb[0], ya[1] - yb[1], ya[1]]
+    };
+    let guess = |s: f64| {
+        let phase = 2.0 * std::f64::consts::PI * s;
+        dvector![2.0 * phase.cos(), -2.0 * phase.sin()]
+    };
+    let p = dvector![2.0 * std::f64::consts::PI];
+
+    let output = shooting::solve(
+        &shooting::Input {
+            t_span: [0.0, 1.0],
+            guess: &guess,
+            p: &p,
+            num_segments: 1,
+            h0: 0.01,
+            f: &f,
+            jacobian_y: FINITE_DIFFERENCE,
+            jacobian_p: FINITE_DIFFERENCE,
+            boundary: &periodic,
+            tolerance: 1e-10,
+            max_iterations: 30,
+        },
+        &CONFIG,
+    )
+    .unwrap();
+    assert_that!(output.p[0]).is_close_to(PERIOD, 1e-8);
+    assert_that!(output.y[0][0]).is_close_to(2.008_620, 1e-5);
+
+    let mesh: Vec<f64> = (0..=20).map(|i| f64::from(i) / 20.0).collect();
+    let output = collocation::solve(&collocation::Input {
+        t: &mesh,
+        guess: &guess,
+        p: &p,
+        f: &f,
+        jacobian_y: FINITE_DIFFERENCE,
+        jacobian_p: FINITE_DIFFERENCE,
+        boundary: &periodic,
+        tolerance: 1e-6,
+        max_nodes: 1000,
+        max_iterations: 30,
+    })
+    .unwrap();
+    assert_that!(output.node_limit_reached).is_false();
+    assert_that!(output.p[0]).is_close_to(PERIOD, 1e-5);
+}
+
+// Troesch's problem y'' = lambda sinh(lambda y), y(0) = 0, y(1) = 1. Single shooting from a poor
+// slope runs into the singularity of the initial value problem before t = 1.
+#[test]
+fn test_troesch() {
+    const LAMBDA: f64 = 5.0;
+
+    let f = |_, y: &DVector<f64>, _: &DVector<f64>| dvector![y[1], LAMBDA * (LAMBDA * y[0]).sinh()];
+    let jacobian = |_, y: &DVector<f64>| {
+        nalgebra::dmatrix![
+            0.0, 1.0;
+            LAMBDA.powi(2) * (LAMBDA * y[0]).cosh(), 0.0;
+        ]
+    };
+    let boundary =
+        |ya: &DVector<f64>, yb: &DVector<f64>, _: &DVector<f64>| dvector![ya[0], yb[0] - 1.0];
+    let guess = |t: f64| dvector![t, 1.0];
+    let no_params = DVector::zeros(0);
+
+    let input = |num_segments| shooting::Input {
+        t_span: [0.0, 1.0],
+        guess: &guess,
+        p: &no_params,
+        num_segments,
+        h0: 0.01,
+        f: &f,
+        jacobian_y: Jacobian::Analytic(&jacobian),
+        jacobian_p: FINITE_DIFFERENCE,
+        boundary: &boundary,
+        tolerance: 1e-10,
+        max_iterations: 50,
+    };
+    let single = shooting::solve(&input(1), &CONFIG);
+    assert_that!(single).is_err();
+
+    let multiple = shooting::solve(&input(20), &CONFIG).unwrap();
+    let slope = multiple.y[0][1];
+
+    let mesh: Vec<f64> = (0..=10).map(|i| f64::from(i) / 10.0).collect();
+    let output = collocation::solve(&collocation::Input {
+        t: &mesh,
+        guess: &guess,
+        p: &no_params,
+        f: &f,
+        jacobian_y: Jacobian::Analytic(&jacobian),
+        jacobian_p: FINITE_DIFFERENCE,
+        boundary: &boundary,
+        tolerance: 1e-8,
+        max_nodes: 5000,
+        max_iterations: 50,
+    })
+    .unwrap();
+    assert_that!(output.node_limit_reached).is_false();
+    assert_that!(output.y[0][1]).is_close_to(slope, 1e-6);
+    assert_that!(slope).is_close_to(0.045_750_46, 1e-6);
+
+    // The boundary layer at t = 1 gets the refinement.
+    let last_interval = output.t[output.t.len() - 1] - output.t[output.t.len() - 2];
+    assert_that!(last_interval).is_less_than(output.t[1] - output.t[0]);
+}
+
+// y'' = lambda^2 y, y(0) = y(1) = 1, with solution cosh(lambda (t - 1/2)) / cosh(lambda / 2). The
+// growing mode amplifies errors in the initial slope by about exp(lambda), which defeats single
+// shooting.
+#[test]
+fn test_growing_mode() {
+    const LAMBDA: f64 = 60.0;
+
+    let f = |_, y: &DVector<f64>, _: &DVector<f64>| dvector![y[1], LAMBDA.powi(2) * y[0]];
+    let jacobian = |_, _: &DVector<f64>| {
+        nalgebra::dmatrix![
+            0.0, 1.0;
+            LAMBDA.powi(2), 0.0;
+        ]
+    };
+    let boundary =
+        |ya: &DVector<f64>, yb: &DVector<f64>, _: &DVector<f64>| dvector![ya[0] - 1.0, yb[0] - 1.0];
+    let guess = |_| dvector![1.0, 0.0];
+    let no_params = DVector::zeros(0);
+    let solution = |t: f64| (LAMBDA * (t - 0.5)).cosh() / (LAMBDA / 2.0).cosh();
+
+    let single = shooting::solve(
+        &shooting::Input {
+            t_span: [0.0, 1.0],
+            guess: &guess,
+            p: &no_params,
+            num_segments: 1,
+            h0: 0.01,
+            f: &f,
+            jacobian_y: Jacobian::Analytic(&jacobian),
+            jacobian_p: FINITE_DIFFERENCE,
+            boundary: &boundary,
+            tolerance: 1e-10,
+            max_iterations: 50,
+        },
+        &CONFIG,
+    );
+    assert_that!(single).is_err();
+
+    let mesh: Vec<f64> = (0..=10).map(|i| f64::from(i) / 10.0).collect();
+    let output = collocation::solve(&collocation::Input {
+        t: &mesh,
+        guess: &guess,
+        p: &no_params,
+        f: &f,
+        jacobian_y: Jacobian::Analytic(&jacobian),
+        jacobian_p: FINITE_DIFFERENCE,
+        boundary: &boundary,
+        tolerance: 1e-6,
+        max_nodes: 5000,
+        max_iterations: 20,
+    })
+    .unwrap();
+    assert_that!(output.node_limit_reached).is_false();
+    for t in [0.0, 0.02, 0.1, 0.5, 0.97, 1.0] {
+        let y = output.evaluate(t);
+        assert_that!(y[0])
+            .named(&format!("y({t})"))
+            .is_close_to(solution(t), 1e-6);
+    }
+}
+
+#[test]
+fn test_invalid_mesh() {
+    let guess = |_: f64| dvector![0.0, 0.0];
+    let no_params = DVector::zeros(0);
+    for (mesh, expected) in [
+        (vec![], "TimeSpan"),
+        (vec![0.0], "TimeSpan"),
+        (vec![0.0, 0.5, 0.5, 1.0], "StepSize"),
+        (vec![1.0, 0.0], "TimeSpan"),
+    ] {
+        let result = collocation::solve(&collocation::Input {
+            t: &mesh,
+            guess: &guess,
+            p: &no_params,
+            f: &bratu,
+            jacobian_y: FINITE_DIFFERENCE,
+            jacobian_p: FINITE_DIFFERENCE,
+            boundary: &dirichlet,
+            tolerance: 1e-6,
+            max_nodes: 1000,
+            max_iterations: 20,
+        });
+        let Err(Error::Input(error)) = result else {
+            panic!("{mesh:?}: expected an input error, got {result:?}");
+        };
+        assert_that!(format!("{error:?}"))
+            .named(&format!("{mesh:?}"))
+            .is_equal_to(expected.to_string());
+    }
+}
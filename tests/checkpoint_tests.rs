@@ -0,0 +1,57 @@
+#![cfg(feature = "serde")]
+
+use speculoos::prelude::*;
+
+use finfoot::ode::dopri5;
+use test_util::all_problems;
+
+const CONFIG: dopri5::Config = dopri5::Config {
+    rel_tol: 1e-6,
+    abs_tol: 1e-8,
+};
+
+#[test]
+fn test_resume_is_bit_identical() {
+    let problems = all_problems();
+    let problem = &problems["van_der_pol_oscillator"];
+    let input = dopri5::Input {
+        t_span: problem.t_span,
+        y0: &problem.y0,
+        h0: (problem.t_span[1] - problem.t_span[0]) / 100.0,
+        f: &problem.f,
+    };
+
+    let mut uninterrupted = Vec::new();
+    let expected = dopri5::integrate_with_observer(&input, &CONFIG, &mut |t, y| {
+        uninterrupted.push((t, y.clone()));
+    })
+    .unwrap();
+
+    // Interrupt part way through, checkpoint to disk and resume in a fresh state.
+    let mut state = dopri5::State::new(&input).unwrap();
+    let mut resumed = vec![(state.t, state.y.clone())];
+    for _ in 0..uninterrupted.len() / 2 {
+        state.step(&input, &CONFIG).unwrap();
+        resumed.push((state.t, state.y.clone()));
+    }
+
+    let path = std::env::temp_dir().join(format!("finfoot_checkpoint_{}.json", std::process::id()));
+    state.save(&path).unwrap();
+    let loaded = dopri5::State::load(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert_that!(loaded).is_equal_to(&state);
+    assert_that!(loaded.k1).is_some();
+
+    let output = dopri5::integrate_from(loaded, &input, &CONFIG, &mut |t, y| {
+        resumed.push((t, y.clone()));
+    })
+    .unwrap();
+
+    assert_that!(resumed).is_equal_to(&uninterrupted);
+    assert_that!(output.y).is_equal_to(&expected.y);
+    assert_that!(output.h.to_bits()).is_equal_to(expected.h.to_bits());
+    assert_that!(output.num_calls).is_equal_to(expected.num_calls);
+    assert_that!(output.num_steps).is_equal_to(expected.num_steps);
+    assert_that!(output.num_rejected).is_equal_to(expected.num_rejected);
+    assert_that!(output.num_limited).is_equal_to(&expected.num_limited);
+}
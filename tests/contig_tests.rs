@@ -0,0 +1,267 @@
+use nalgebra::DVector;
+use speculoos::prelude::*;
+
+use uom::si::length::meter;
+use uom::si::mass::kilogram;
+use uom::si::velocity::meter_per_second;
+
+use finfoot::contig::StateVector;
+use finfoot::ode::{dopri5, Error};
+
+#[derive(StateVector)]
+struct Projectile {
+    position: [f64; 2],
+    velocity: [f64; 2],
+    masses: Vec<f64>,
+}
+
+#[test]
+fn test_integrate_views() {
+    const CONFIG: dopri5::Config = dopri5::Config {
+        rel_tol: 1e-8,
+        abs_tol: 1e-10,
+    };
+    const GRAVITY: f64 = -9.81;
+
+    let y0 = Projectile {
+        position: [0.0, 0.0],
+        velocity: [3.0, 4.0],
+        masses: vec![1.0, 2.0],
+    };
+    let layout = y0.layout();
+
+    let f = |_: f64, y: &DVector<f64>| {
+        let s = Projectile::view(&layout, y.as_slice());
+        let mut dydt = DVector::zeros(layout.len());
+        let d = Projectile::view_mut(&layout, dydt.as_mut_slice());
+        *d.position = *s.velocity;
+        d.velocity[1] = GRAVITY;
+        for i in 0..s.masses.len() {
+            d.masses[i] = -0.1 * s.masses[i];
+        }
+        dydt
+    };
+
+    let input = dopri5::Input {
+        t_span: [0.0, 1.0],
+        y0: &y0.to_vector(),
+        h0: 0.01,
+        f: &f,
+    };
+    let output = dopri5::integrate(&input, &CONFIG).unwrap();
+    let yf = Projectile::read(&layout, output.y.as_slice());
+
+    assert_that!(yf.position[0]).is_close_to(3.0, 1e-8);
+    assert_that!(yf.position[1]).is_close_to(4.0 + 0.5 * GRAVITY, 1e-8);
+    assert_that!(yf.velocity[1]).is_close_to(4.0 + GRAVITY, 1e-8);
+    assert_that!(yf.masses[1]).is_close_to(2.0 * f64::exp(-0.1), 1e-8);
+}
+
+#[derive(StateVector)]
+struct RigidBody {
+    #[state(unit = meter, names = ["x", "y", "z"])]
+    position: [f64; 3],
+    #[state(unit = meter_per_second, names = ["x", "y", "z"])]
+    #[state(description = "Inertial velocity")]
+    velocity: [f64; 3],
+}
+
+#[derive(StateVector)]
+struct Tank {
+    #[state(unit = kilogram, description = "Propellant mass")]
+    mass: f64,
+}
+
+#[derive(StateVector)]
+struct Actuators {
+    angles: Vec<f64>,
+}
+
+#[derive(StateVector)]
+struct Vehicle {
+    body: RigidBody,
+    tank: Tank,
+    actuators: Actuators,
+}
+
+const MASS_FLOW: f64 = 2.0;
+const EXHAUST_VELOCITY: f64 = 3000.0;
+const TIME_CONSTANT: f64 = 0.5;
+
+fn body_derivative(s: &RigidBodyView<'_>, d: &mut RigidBodyViewMut<'_>, mass: f64) {
+    *d.position = *s.velocity;
+    *d.velocity = [0.0, 0.0, MASS_FLOW * EXHAUST_VELOCITY / mass];
+}
+
+fn tank_derivative(d: &mut TankViewMut<'_>) {
+    *d.mass = -MASS_FLOW;
+}
+
+fn actuators_derivative(s: &ActuatorsView<'_>, d: &mut ActuatorsViewMut<'_>, commands: &[f64]) {
+    for ((d_angle, angle), command) in d.angles.iter_mut().zip(s.angles).zip(commands) {
+        *d_angle = (command - angle) / TIME_CONSTANT;
+    }
+}
+
+#[test]
+fn test_nested_states() {
+    const CONFIG: dopri5::Config = dopri5::Config {
+        rel_tol: 1e-10,
+        abs_tol: 1e-10,
+    };
+    const DRY_MASS: f64 = 100.0;
+    const PROPELLANT_MASS: f64 = 50.0;
+
+    let y0 = Vehicle {
+        body: RigidBody {
+            position: [0.0; 3],
+            velocity: [1.0, 0.0, 0.0],
+        },
+        tank: Tank {
+            mass: PROPELLANT_MASS,
+        },
+        actuators: Actuators {
+            angles: vec![0.0; 4],
+        },
+    };
+    let commands = [0.1, -0.1, 0.2, -0.2];
+
+    let layout = y0.layout();
+    let f = finfoot::contig::derivative_func::<Vehicle, _>(
+        layout.clone(),
+        |_, s: VehicleView<'_>, mut d: VehicleViewMut<'_>| {
+            body_derivative(&s.body, &mut d.body, DRY_MASS + *s.tank.mass);
+            tank_derivative(&mut d.tank);
+            actuators_derivative(&s.actuators, &mut d.actuators, &commands);
+        },
+    );
+
+    let input = dopri5::Input {
+        t_span: [0.0, 10.0],
+        y0: &y0.to_vector(),
+        h0: 0.1,
+        f: &f,
+    };
+    let output = dopri5::integrate(&input, &CONFIG).unwrap();
+    let yf = Vehicle::read(&layout, output.y.as_slice());
+
+    // Rocket equation.
+    let mass = DRY_MASS + PROPELLANT_MASS - MASS_FLOW * 10.0;
+    let delta_v = EXHAUST_VELOCITY * f64::ln((DRY_MASS + PROPELLANT_MASS) / mass);
+
+    assert_that!(yf.body.position[0]).is_close_to(10.0, 1e-8);
+    assert_that!(yf.body.velocity[2]).is_close_to(delta_v, 1e-6);
+    assert_that!(yf.tank.mass).is_close_to(PROPELLANT_MASS - MASS_FLOW * 10.0, 1e-8);
+    for (angle, command) in yf.actuators.angles.iter().zip(commands) {
+        assert_that!(*angle).is_close_to(command * (1.0 - f64::exp(-10.0 / TIME_CONSTANT)), 1e-8);
+    }
+}
+
+// Nested states are written in place in declaration order, matching the layout.
+#[test]
+fn test_nested_write() {
+    let state = Vehicle {
+        body: RigidBody {
+            position: [1.0, 2.0, 3.0],
+            velocity: [4.0, 5.0, 6.0],
+        },
+        tank: Tank { mass: 7.0 },
+        actuators: Actuators {
+            angles: vec![8.0, 9.0],
+        },
+    };
+    let layout = state.layout();
+    assert_that!(state.len()).is_equal_to(layout.len());
+
+    let data = state.to_vector();
+    assert_that!(data.as_slice().to_vec())
+        .is_equal_to(vec![1.0, 2.0, 3.0, 4.0, 5.0, 6.0, 7.0, 8.0, 9.0]);
+    let read = Vehicle::read(&layout, data.as_slice());
+    assert_that!(read.actuators.angles).is_equal_to(vec![8.0, 9.0]);
+    assert_that!(read.body.velocity).is_equal_to([4.0, 5.0, 6.0]);
+}
+
+#[test]
+fn test_metadata() {
+    let y0 = Vehicle {
+        body: RigidBody {
+            position: [0.0; 3],
+            velocity: [0.0; 3],
+        },
+        tank: Tank { mass: 1.0 },
+        actuators: Actuators {
+            angles: vec![0.0; 2],
+        },
+    };
+    let layout = y0.layout().with_name("vehicle");
+
+    let names: Vec<_> = layout.elements().iter().map(ToString::to_string).collect();
+    assert_that!(names).is_equal_to(
+        [
+            "vehicle.body.position.x [m]",
+            "vehicle.body.position.y [m]",
+            "vehicle.body.position.z [m]",
+            "vehicle.body.velocity.x [m/s]",
+            "vehicle.body.velocity.y [m/s]",
+            "vehicle.body.velocity.z [m/s]",
+            "vehicle.tank.mass [kg]",
+            "vehicle.actuators.angles[0]",
+            "vehicle.actuators.angles[1]",
+        ]
+        .map(String::from)
+        .to_vec(),
+    );
+    assert_that!(layout.element(4).description).is_equal_to(Some("Inertial velocity"));
+    assert_that!(layout.element(6).description).is_equal_to(Some("Propellant mass"));
+
+    // Only the z velocity has a nonzero local error, so it limits every step.
+    let f = |t: f64, _: &DVector<f64>| {
+        let mut dydt = DVector::zeros(layout.len());
+        dydt[5] = f64::sin(10.0 * t);
+        dydt
+    };
+    let input = dopri5::Input {
+        t_span: [0.0, 1.0],
+        y0: &y0.to_vector(),
+        h0: 0.1,
+        f: &f,
+    };
+    let loose = dopri5::integrate(
+        &input,
+        &dopri5::Config {
+            rel_tol: 1e-6,
+            abs_tol: 1e-8,
+        },
+    )
+    .unwrap();
+    let limiting = loose.limiting_elements(Some(&layout));
+    assert_that!(limiting[0].0.as_str()).is_equal_to("vehicle.body.velocity.z [m/s]");
+    assert_that!(limiting[0].1).is_equal_to(loose.num_steps + loose.num_rejected);
+
+    // Linear growth is integrated exactly, so no element limits any step.
+    let linear = |_, _: &DVector<f64>| DVector::from_element(layout.len(), 1.0);
+    let exact = dopri5::integrate(
+        &dopri5::Input {
+            f: &linear,
+            ..input
+        },
+        &dopri5::Config {
+            rel_tol: 1e-6,
+            abs_tol: 1e-8,
+        },
+    )
+    .unwrap();
+    assert_that!(exact.limiting_elements(None)).is_empty();
+
+    let error = dopri5::integrate(
+        &input,
+        &dopri5::Config {
+            rel_tol: 0.0,
+            abs_tol: 1e-300,
+        },
+    )
+    .unwrap_err();
+    assert_that!(matches!(error, Error::Convergence { element: 5, .. })).is_true();
+    assert_that!(error.describe(Some(&layout))).ends_with("vehicle.body.velocity.z [m/s]");
+    assert_that!(error.to_string()).ends_with("element 5");
+}
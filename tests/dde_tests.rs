@@ -0,0 +1,127 @@
+use nalgebra::{dvector, DVector};
+use speculoos::prelude::*;
+
+use finfoot::ode::dde::{self, History};
+use finfoot::ode::dopri5::Config;
+
+const CONFIG: Config = Config {
+    rel_tol: 1e-9,
+    abs_tol: 1e-11,
+};
+
+// y'(t) = -y(t - 1) with y = 1 for t <= 0, solved piecewise by the method of steps.
+fn method_of_steps(t: f64) -> f64 {
+    let mut y = 1.0;
+    let mut factorial = 1.0;
+    for k in 1..=4 {
+        let shifted = t - f64::from(k - 1);
+        if shifted <= 0.0 {
+            break;
+        }
+        factorial *= f64::from(k);
+        y += f64::from(if k % 2 == 0 { 1 } else { -1 }) * shifted.powi(k) / factorial;
+    }
+    y
+}
+
+#[test]
+fn test_constant_delay() {
+    let f = |t: f64, _: &DVector<f64>, history: &History<'_>| -history.at(t - 1.0);
+    let history = |_| dvector![1.0];
+    let input = dde::Input {
+        t_span: [0.0, 4.0],
+        history: &history,
+        h0: 0.1,
+        f: &f,
+        delays: &[1.0],
+    };
+
+    let mut steps = Vec::new();
+    let output = dde::integrate_with_observer(&input, &CONFIG, &mut |t, _| steps.push(t)).unwrap();
+
+    // The derivative jumps at t = 0 and the jump propagates to every multiple of the delay.
+    assert_that!(output.breakpoints).is_equal_to(vec![1.0, 2.0, 3.0]);
+    for breakpoint in &output.breakpoints {
+        assert_that!(steps.contains(breakpoint)).is_true();
+    }
+
+    assert_that!(output.y[0]).is_close_to(method_of_steps(4.0), 1e-8);
+    for i in 0..=40 {
+        let t = f64::from(i) * 0.1;
+        let y = output.dense.evaluate(t)[0];
+        assert_that!(y)
+            .named(&format!("t = {t}"))
+            .is_close_to(method_of_steps(t), 1e-8);
+    }
+}
+
+// y'(t) = -y(t - tau(y)) + g(t) with tau = 0.5 + 0.25 y(t)^2, where g is chosen so y = cos(t).
+#[test]
+fn test_state_dependent_delay() {
+    let tau = |y: f64| 0.5 + 0.25 * y * y;
+    let f = |t: f64, y: &DVector<f64>, history: &History<'_>| {
+        let g = -t.sin() + (t - tau(t.cos())).cos();
+        -history.at(t - tau(y[0])) + dvector![g]
+    };
+    let history = |t: f64| dvector![t.cos()];
+    let input = dde::Input {
+        t_span: [0.0, 10.0],
+        history: &history,
+        h0: 0.1,
+        f: &f,
+        delays: &[],
+    };
+
+    let output = dde::integrate(&input, &CONFIG).unwrap();
+    assert_that!(output.breakpoints).is_empty();
+    assert_that!(output.y[0]).is_close_to(10.0f64.cos(), 1e-7);
+    for i in 0..=100 {
+        let t = f64::from(i) * 0.1;
+        let y = output.dense.evaluate(t)[0];
+        assert_that!(y)
+            .named(&format!("t = {t}"))
+            .is_close_to(t.cos(), 1e-7);
+    }
+}
+
+// Two incommensurate delays produce breakpoints at all their sums.
+#[test]
+fn test_multiple_delays() {
+    let f = |t: f64, y: &DVector<f64>, history: &History<'_>| {
+        -0.5 * y - 0.3 * history.at(t - 1.0) + 0.2 * history.at(t - 1.5)
+    };
+    let history = |t: f64| dvector![1.0 + t];
+    let input = dde::Input {
+        t_span: [0.0, 3.2],
+        history: &history,
+        h0: 0.1,
+        f: &f,
+        delays: &[1.0, 1.5],
+    };
+    let output = dde::integrate(&input, &CONFIG).unwrap();
+    assert_that!(output.breakpoints).is_equal_to(vec![1.0, 1.5, 2.0, 2.5, 3.0]);
+
+    let coarse = dde::integrate(
+        &input,
+        &Config {
+            rel_tol: 1e-6,
+            abs_tol: 1e-8,
+        },
+    )
+    .unwrap();
+    assert_that!(coarse.y[0]).is_close_to(output.y[0], 1e-5);
+}
+
+#[test]
+fn test_invalid_delay() {
+    let f = |_, y: &DVector<f64>, _: &History<'_>| y.clone();
+    let history = |_| dvector![1.0];
+    let input = dde::Input {
+        t_span: [0.0, 1.0],
+        history: &history,
+        h0: 0.1,
+        f: &f,
+        delays: &[0.0],
+    };
+    assert_that!(dde::integrate(&input, &CONFIG)).is_err();
+}
@@ -0,0 +1,121 @@
+use nalgebra::{dvector, DVector};
+use speculoos::prelude::*;
+
+use finfoot::ode::dopri5;
+use finfoot::ode::ensemble::{self, Detector, Ensemble, Member};
+use finfoot::ode::hybrid::Direction;
+use finfoot::ode::rng::Rng;
+
+const SOLVER: dopri5::Config = dopri5::Config {
+    rel_tol: 1e-8,
+    abs_tol: 1e-10,
+};
+
+// Exponential decay with dispersed initial state and rate: y(1) = y0 * exp(-k).
+fn run(num_threads: usize) -> Ensemble {
+    ensemble::run(
+        |_, rng: &mut Rng| {
+            let k = 1.0 + 0.1 * rng.normal();
+            Member {
+                t_span: [0.0, 1.0],
+                y0: DVector::from_vec(vec![1.0 + 0.1 * rng.normal(), 2.0]),
+                h0: 0.01,
+                f: Box::new(move |_, y: &DVector<f64>| -k * y),
+                detectors: Vec::new(),
+            }
+        },
+        &SOLVER,
+        &ensemble::Config {
+            num_members: 2000,
+            seed: 42,
+            num_threads,
+        },
+    )
+}
+
+#[test]
+fn test_reproducible_across_thread_counts() {
+    let serial = run(1);
+    let parallel = run(4);
+
+    assert_that!(serial.num_failed()).is_equal_to(0);
+    assert_that!(serial.final_states()).is_equal_to(parallel.final_states());
+    assert_that!(serial.mean()).is_equal_to(parallel.mean());
+    assert_that!(serial.covariance()).is_equal_to(parallel.covariance());
+}
+
+#[test]
+fn test_statistics() {
+    let ensemble = run(0);
+
+    // E[y0 exp(-k)] = E[y0] exp(-1 + 0.1^2 / 2) for independent normal y0 and k.
+    let expected_mean = (-1.0f64 + 0.005).exp();
+    let mean = ensemble.mean().unwrap();
+    assert_that!(mean[0]).is_close_to(expected_mean, 0.01);
+    assert_that!(mean[1]).is_close_to(2.0 * expected_mean, 0.02);
+
+    // Both elements share k, so they are strongly correlated.
+    let covariance = ensemble.covariance().unwrap();
+    let correlation = covariance[(0, 1)] / (covariance[(0, 0)] * covariance[(1, 1)]).sqrt();
+    assert_that!(correlation).is_close_to(0.7, 0.1);
+
+    let low = ensemble.percentile(5.0).unwrap();
+    let median = ensemble.percentile(50.0).unwrap();
+    let high = ensemble.percentile(95.0).unwrap();
+    assert_that!(low[0]).is_less_than(median[0]);
+    assert_that!(median[0]).is_less_than(high[0]);
+    assert_that!(median[1]).is_close_to(2.0 * (-1.0f64).exp(), 0.02);
+
+    let min = ensemble.percentile(0.0).unwrap();
+    let min_state = ensemble
+        .final_states()
+        .iter()
+        .map(|y| y[0])
+        .fold(f64::INFINITY, f64::min);
+    assert_that!(min[0]).is_equal_to(min_state);
+}
+
+// Projectiles launched upwards with dispersed speed v: the apex is at t = v / g, at height
+// v^2 / 2 g, and the landing at twice that time.
+#[test]
+fn test_events() {
+    const G: f64 = 9.81;
+    let ensemble = ensemble::run(
+        |_, rng: &mut Rng| Member {
+            t_span: [0.0, 5.0],
+            y0: dvector![0.0, 10.0 + rng.normal()],
+            h0: 0.01,
+            f: Box::new(|_, y: &DVector<f64>| dvector![y[1], -G]),
+            detectors: vec![
+                Detector {
+                    guard: Box::new(|_, y: &DVector<f64>| y[1]),
+                    direction: Direction::Falling,
+                },
+                Detector {
+                    guard: Box::new(|_, y: &DVector<f64>| y[0]),
+                    direction: Direction::Falling,
+                },
+            ],
+        },
+        &SOLVER,
+        &ensemble::Config {
+            num_members: 100,
+            seed: 7,
+            num_threads: 0,
+        },
+    );
+
+    assert_that!(ensemble.events.len()).is_equal_to(100);
+    for (result, events) in ensemble.results.iter().zip(&ensemble.events) {
+        assert_that!(result.is_ok()).is_true();
+        assert_that!(events.len()).is_equal_to(2);
+        let v = G * events[0].t;
+        assert_that!(events[0].detector).is_equal_to(0);
+        assert_that!(events[0].y[1]).is_close_to(0.0, 1e-10);
+        assert_that!(events[0].y[0]).is_close_to(v * v / (2.0 * G), 1e-8);
+        assert_that!(events[1].detector).is_equal_to(1);
+        assert_that!(events[1].t).is_close_to(2.0 * events[0].t, 1e-8);
+        assert_that!(events[1].y[0]).is_close_to(0.0, 1e-8);
+    }
+    assert_that!(ensemble.first_event_times(0).len()).is_equal_to(100);
+}
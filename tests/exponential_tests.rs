@@ -0,0 +1,158 @@
+use nalgebra::{dvector, DMatrix, DVector};
+use speculoos::prelude::*;
+
+use finfoot::ode::dopri5::{self, Config};
+use finfoot::ode::exponential::{self, Action};
+use finfoot::ode::jacobian::Jacobian;
+
+const NUM_POINTS: usize = 29;
+
+// Second difference on (0, 1) with zero Dirichlet boundaries. The largest eigenvalue is about
+// -3600, so explicit methods are stability limited to h ~ 1e-3.
+#[allow(clippy::cast_precision_loss)]
+fn laplacian() -> DMatrix<f64> {
+    let dx = 1.0 / (NUM_POINTS as f64 + 1.0);
+    let scale = 1.0 / (dx * dx);
+    DMatrix::from_fn(NUM_POINTS, NUM_POINTS, |i, j| match i.abs_diff(j) {
+        0 => -2.0 * scale,
+        1 => scale,
+        _ => 0.0,
+    })
+}
+
+#[allow(clippy::cast_precision_loss)]
+fn initial_profile() -> DVector<f64> {
+    let dx = 1.0 / (NUM_POINTS as f64 + 1.0);
+    DVector::from_fn(NUM_POINTS, |i, _| {
+        (std::f64::consts::PI * (i as f64 + 1.0) * dx).sin()
+    })
+}
+
+// Fisher-KPP reaction.
+fn reaction(_: f64, y: &DVector<f64>) -> DVector<f64> {
+    y.map(|u| 5.0 * u * (1.0 - u))
+}
+
+fn reaction_jacobian(_: f64, y: &DVector<f64>) -> DMatrix<f64> {
+    DMatrix::from_diagonal(&y.map(|u| 5.0 * (1.0 - 2.0 * u)))
+}
+
+fn fisher_input<'a>(
+    linear: &'a DMatrix<f64>,
+    y0: &'a DVector<f64>,
+    h: f64,
+    action: Action,
+) -> exponential::Input<'a> {
+    exponential::Input {
+        t_span: [0.0, 1.0],
+        y0,
+        h,
+        linear,
+        nonlinear: &reaction,
+        jacobian: Jacobian::Analytic(&reaction_jacobian),
+        action,
+    }
+}
+
+// With a constant nonlinear part, y = exp(t A) y0 + t phi_1(t A) b is reproduced for any step.
+#[test]
+fn test_linear_exact() {
+    let lambda = dvector![-1.0, -100.0, -1e4];
+    let linear = DMatrix::from_diagonal(&lambda);
+    let y0 = dvector![1.0, 2.0, 3.0];
+    let b = dvector![1.0, -1.0, 0.5];
+    let nonlinear = |_, _: &DVector<f64>| b.clone();
+    let zero = |_, y: &DVector<f64>| DMatrix::zeros(y.len(), y.len());
+    let t: f64 = 2.0;
+    let expected = DVector::from_fn(3, |i, _| {
+        let decay = (lambda[i] * t).exp();
+        decay * y0[i] + (decay - 1.0) / lambda[i] * b[i]
+    });
+
+    for action in [Action::Pade, Action::Krylov { dimension: 3 }] {
+        let input = exponential::Input {
+            t_span: [0.0, t],
+            y0: &y0,
+            h: 0.5,
+            linear: &linear,
+            nonlinear: &nonlinear,
+            jacobian: Jacobian::Analytic(&zero),
+            action,
+        };
+        let output = exponential::etdrk4(&input).unwrap();
+        assert_that!((&output.y - &expected).amax()).is_less_than(1e-12);
+        assert_that!(output.num_steps).is_equal_to(4);
+
+        let config = Config {
+            rel_tol: 1e-10,
+            abs_tol: 1e-10,
+        };
+        let output = exponential::exprb43(&input, &config).unwrap();
+        assert_that!((&output.y - &expected).amax()).is_less_than(1e-12);
+        assert_that!(output.num_rejected).is_equal_to(0);
+    }
+}
+
+#[test]
+fn test_etdrk4_order() {
+    let linear = laplacian();
+    let y0 = initial_profile();
+    let reference = exponential::etdrk4(&fisher_input(&linear, &y0, 5e-3, Action::Pade))
+        .unwrap()
+        .y;
+    let errors: Vec<f64> = [0.05, 0.025]
+        .iter()
+        .map(|&h| {
+            let output = exponential::etdrk4(&fisher_input(&linear, &y0, h, Action::Pade)).unwrap();
+            (output.y - &reference).amax()
+        })
+        .collect();
+    let order = (errors[0] / errors[1]).log2();
+    assert_that!(order).is_close_to(4.0, 0.3);
+}
+
+// With |h A| about 36, a subspace of a third of the system's dimension is enough.
+#[test]
+fn test_krylov() {
+    let linear = laplacian();
+    let y0 = initial_profile();
+    let pade = exponential::etdrk4(&fisher_input(&linear, &y0, 0.01, Action::Pade)).unwrap();
+    let krylov = exponential::etdrk4(&fisher_input(
+        &linear,
+        &y0,
+        0.01,
+        Action::Krylov { dimension: 10 },
+    ))
+    .unwrap();
+    assert_that!((&krylov.y - &pade.y).amax()).is_less_than(1e-8);
+}
+
+// Step size is set by accuracy alone, where dopri5 is held at its stability limit.
+#[test]
+fn test_exprb43_stiff() {
+    let linear = laplacian();
+    let y0 = initial_profile();
+    let config = Config {
+        rel_tol: 1e-6,
+        abs_tol: 1e-6,
+    };
+    let input = fisher_input(&linear, &y0, 1e-3, Action::Pade);
+    let output = exponential::exprb43(&input, &config).unwrap();
+
+    let f = |t, y: &DVector<f64>| &linear * y + reaction(t, y);
+    let reference = dopri5::integrate(
+        &dopri5::Input {
+            t_span: [0.0, 1.0],
+            y0: &y0,
+            h0: 1e-3,
+            f: &f,
+        },
+        &Config {
+            rel_tol: 1e-10,
+            abs_tol: 1e-10,
+        },
+    )
+    .unwrap();
+    assert_that!((&output.y - &reference.y).amax()).is_less_than(1e-7);
+    assert_that!(output.num_steps).is_less_than(reference.num_steps / 10);
+}
@@ -0,0 +1,135 @@
+use nalgebra::{dmatrix, dvector, DVector};
+use speculoos::prelude::*;
+
+use finfoot::ode::dopri5::{self, Config};
+use finfoot::ode::gbs;
+use finfoot::ode::jacobian::{Difference, Jacobian};
+use finfoot::ode::rodas3::{self, MassMatrix};
+use finfoot::ode::seulex;
+use test_util::all_problems;
+
+const TIGHT: Config = Config {
+    rel_tol: 1e-12,
+    abs_tol: 1e-12,
+};
+
+#[test]
+fn test_gbs_analytic() {
+    let problems = all_problems();
+    for name in ["exponential", "harmonic_oscillator"] {
+        let problem = &problems[name];
+        let input = gbs::Input {
+            t_span: problem.t_span,
+            y0: &problem.y0,
+            h0: 1e-2,
+            f: &problem.f,
+        };
+        let output = gbs::integrate(&input, &TIGHT).unwrap();
+        assert_that!((&output.y - &problem.yf).amax())
+            .named(name)
+            .is_less_than(1e-11);
+        assert_that!(output.order).named(name).is_greater_than(8);
+    }
+}
+
+// The reference solutions of `ode_solutions.py`, to far higher accuracy and at a fraction of the
+// cost of dopri5.
+#[test]
+fn test_gbs_reference_solutions() {
+    let problems = all_problems();
+    for name in ["van_der_pol_oscillator", "lorentz_attractor"] {
+        let problem = &problems[name];
+        let input = gbs::Input {
+            t_span: problem.t_span,
+            y0: &problem.y0,
+            h0: 1e-2,
+            f: &problem.f,
+        };
+        let output = gbs::integrate(&input, &TIGHT).unwrap();
+        for i in 0..problem.y0.len() {
+            assert_that!(output.y[i]).is_close_to(problem.yf[i], problem.tolerance[i]);
+        }
+
+        let reference = dopri5::integrate(
+            &dopri5::Input {
+                t_span: problem.t_span,
+                y0: &problem.y0,
+                h0: 1e-2,
+                f: &problem.f,
+            },
+            &TIGHT,
+        )
+        .unwrap();
+        let difference = (&output.y - &reference.y).amax();
+        assert_that!(difference).named(name).is_less_than(1e-9);
+        assert_that!(output.num_calls)
+            .named(name)
+            .is_less_than(reference.num_calls / 2);
+    }
+}
+
+#[test]
+fn test_seulex_robertson() {
+    let problem = &all_problems()["robertson_equations"];
+    let input = seulex::Input {
+        t_span: problem.t_span,
+        y0: &problem.y0,
+        h0: 1e-6,
+        f: &problem.f,
+        jacobian: Jacobian::FiniteDifference {
+            difference: Difference::Forward,
+            sparsity: None,
+        },
+    };
+    let config = Config {
+        rel_tol: 1e-6,
+        abs_tol: 1e-10,
+    };
+    let output = seulex::integrate(&input, &config).unwrap();
+    for i in 0..3 {
+        assert_that!(output.y[i]).is_close_to(problem.yf[i], problem.tolerance[i]);
+    }
+}
+
+// Against the reference at t = 40 from Hairer & Wanner, with far fewer steps than rodas3.
+#[test]
+fn test_seulex_tight_tolerance() {
+    let problem = &all_problems()["robertson_equations"];
+    let jacobian = |_, y: &DVector<f64>| {
+        let (y2, y3) = (y[1], y[2]);
+        dmatrix![
+            -0.04, 1e4 * y3, 1e4 * y2;
+            0.04, -1e4 * y3 - 6e7 * y2, -1e4 * y2;
+            0.0, 6e7 * y2, 0.0;
+        ]
+    };
+    let config = Config {
+        rel_tol: 1e-10,
+        abs_tol: 1e-14,
+    };
+    let input = seulex::Input {
+        t_span: [0.0, 40.0],
+        y0: &problem.y0,
+        h0: 1e-6,
+        f: &problem.f,
+        jacobian: Jacobian::Analytic(&jacobian),
+    };
+    let output = seulex::integrate(&input, &config).unwrap();
+    let expected = dvector![0.715_827_068_7, 9.185_534_764e-6, 0.284_163_745_7];
+    let relative_error = (&output.y - &expected).component_div(&expected).amax();
+    assert_that!(relative_error).is_less_than(1e-9);
+
+    let reference = rodas3::integrate(
+        &rodas3::Input {
+            t_span: [0.0, 40.0],
+            y0: &problem.y0,
+            h0: 1e-6,
+            f: &problem.f,
+            mass: MassMatrix::Identity,
+            jacobian: Jacobian::Analytic(&jacobian),
+        },
+        &config,
+    )
+    .unwrap();
+    assert_that!(output.num_steps).is_less_than(reference.num_steps / 10);
+}
@@ -0,0 +1,103 @@
+use nalgebra::DVector;
+use speculoos::prelude::*;
+
+use finfoot::ode::dopri5::{self, Config};
+use finfoot::ode::gbs;
+use finfoot::ode::global_error::{self, Method};
+use test_util::{all_problems, OdeProblem};
+
+fn exact(problem: &OdeProblem) -> DVector<f64> {
+    let input = gbs::Input {
+        t_span: problem.t_span,
+        y0: &problem.y0,
+        h0: 1e-2,
+        f: &problem.f,
+    };
+    let config = Config {
+        rel_tol: 1e-13,
+        abs_tol: 1e-13,
+    };
+    gbs::integrate(&input, &config).unwrap().y
+}
+
+#[test]
+fn test_estimates() {
+    let problems = all_problems();
+    for name in ["van_der_pol_oscillator", "lorentz_attractor"] {
+        let problem = &problems[name];
+        let exact = exact(problem);
+        let input = dopri5::Input {
+            t_span: problem.t_span,
+            y0: &problem.y0,
+            h0: 1e-2,
+            f: &problem.f,
+        };
+        for tol in [1e-6, 1e-8] {
+            let config = Config {
+                rel_tol: tol,
+                abs_tol: tol,
+            };
+            for method in [Method::Tolerances, Method::Richardson] {
+                let output = global_error::integrate(&input, &config, method).unwrap();
+                let actual = &output.y - &exact;
+                let mismatch = (&output.error - &actual).amax();
+                assert_that!(mismatch)
+                    .named(&format!("{name} {tol} {method:?}"))
+                    .is_less_than(0.1 * actual.amax());
+            }
+        }
+    }
+}
+
+#[test]
+fn test_solution_unchanged() {
+    let problem = &all_problems()["van_der_pol_oscillator"];
+    let input = dopri5::Input {
+        t_span: problem.t_span,
+        y0: &problem.y0,
+        h0: 1e-2,
+        f: &problem.f,
+    };
+    let config = Config {
+        rel_tol: 1e-6,
+        abs_tol: 1e-6,
+    };
+    let plain = dopri5::integrate(&input, &config).unwrap();
+
+    for method in [Method::Tolerances, Method::Richardson] {
+        let mut num_observed = 0;
+        let output = global_error::integrate_with_observer(&input, &config, method, &mut |_, _| {
+            num_observed += 1;
+        })
+        .unwrap();
+        assert_that!(output.y).is_equal_to(&plain.y);
+        assert_that!(output.num_steps).is_equal_to(plain.num_steps);
+        assert_that!(num_observed).is_equal_to(plain.num_steps + 1);
+        assert_that!(output.num_calls).is_greater_than(plain.num_calls);
+    }
+}
+
+// Local error control keeps the exponential well within tolerance, while the chaotic Lorenz system
+// amplifies local errors far beyond it.
+#[test]
+fn test_error_ratio() {
+    let problems = all_problems();
+    let config = Config {
+        rel_tol: 1e-6,
+        abs_tol: 1e-6,
+    };
+    let ratio = |name: &str| {
+        let problem = &problems[name];
+        let input = dopri5::Input {
+            t_span: problem.t_span,
+            y0: &problem.y0,
+            h0: 1e-2,
+            f: &problem.f,
+        };
+        global_error::integrate(&input, &config, Method::Richardson)
+            .unwrap()
+            .error_ratio(&config)
+    };
+    assert_that!(ratio("exponential")).is_less_than(1.0);
+    assert_that!(ratio("lorentz_attractor")).is_greater_than(10.0);
+}
@@ -0,0 +1,254 @@
+use nalgebra::{dvector, DVector};
+use speculoos::prelude::*;
+
+use finfoot::ode::dopri5::Config;
+use finfoot::ode::hybrid::{self, Direction, Mode, Transition};
+use finfoot::ode::{Error, InputError};
+
+const CONFIG: Config = Config {
+    rel_tol: 1e-10,
+    abs_tol: 1e-12,
+};
+
+const G: f64 = 9.81;
+const RESTITUTION: f64 = 0.8;
+
+fn falling(_: f64, y: &DVector<f64>) -> DVector<f64> {
+    dvector![y[1], -G]
+}
+
+fn height(_: f64, y: &DVector<f64>) -> f64 {
+    y[0]
+}
+
+fn bounce(_: f64, y: &DVector<f64>) -> DVector<f64> {
+    dvector![y[0], -RESTITUTION * y[1]]
+}
+
+// Bouncing ball: impacts accumulate at a finite time, so the event limit has to stop it.
+#[test]
+fn test_bouncing_ball() {
+    let modes = [Mode {
+        f: &falling,
+        transitions: vec![Transition {
+            guard: &height,
+            direction: Direction::Falling,
+            reset: &bounce,
+            target: 0,
+        }],
+    }];
+    let input = hybrid::Input {
+        t_span: [0.0, 10.0],
+        y0: &dvector![1.0, 0.0],
+        mode0: 0,
+        h0: 0.01,
+        modes: &modes,
+        max_events: 30,
+    };
+    let output = hybrid::integrate(&input, &CONFIG).unwrap();
+
+    // First impact after falling 1 m, then flights of 2 v / g with v shrinking each bounce.
+    let t_first = (2.0 / G).sqrt();
+    let mut v = G * t_first;
+    let mut t_expected = t_first;
+    for (k, event) in output.events.iter().enumerate() {
+        assert_that!(event.t)
+            .named(&format!("impact {k}"))
+            .is_close_to(t_expected, 1e-8);
+        assert_that!(event.y_before[0]).is_close_to(0.0, 1e-9);
+        assert_that!(event.y_after[1]).is_close_to(-RESTITUTION * event.y_before[1], 1e-12);
+        v *= RESTITUTION;
+        t_expected += 2.0 * v / G;
+    }
+
+    let t_zeno = t_first + 2.0 * G * t_first * RESTITUTION / (G * (1.0 - RESTITUTION));
+    assert_that!(output.event_limit_reached).is_true();
+    assert_that!(output.events.len()).is_equal_to(30);
+    assert_that!(output.t).is_less_than(t_zeno);
+    assert_that!(output.t).is_greater_than(t_zeno - 0.01);
+}
+
+// Switching the derivative function must not reuse the last derivative of the old mode.
+#[test]
+fn test_mode_switch() {
+    let rising = |_, _: &DVector<f64>| dvector![1.0];
+    let descending = |_, _: &DVector<f64>| dvector![-2.0];
+    let threshold = |_, y: &DVector<f64>| y[0] - 1.0;
+    let identity = |_, y: &DVector<f64>| y.clone();
+    let modes = [
+        Mode {
+            f: &rising,
+            transitions: vec![Transition {
+                guard: &threshold,
+                direction: Direction::Rising,
+                reset: &identity,
+                target: 1,
+            }],
+        },
+        Mode {
+            f: &descending,
+            transitions: vec![],
+        },
+    ];
+    let input = hybrid::Input {
+        t_span: [0.0, 2.0],
+        y0: &dvector![0.0],
+        mode0: 0,
+        h0: 0.3,
+        modes: &modes,
+        max_events: 10,
+    };
+    let output = hybrid::integrate(&input, &CONFIG).unwrap();
+
+    assert_that!(output.mode).is_equal_to(1);
+    assert_that!(output.events.len()).is_equal_to(1);
+    assert_that!(output.events[0].t).is_close_to(1.0, 1e-12);
+    assert_that!(output.t).is_equal_to(2.0);
+    assert_that!(output.y[0]).is_close_to(-1.0, 1e-10);
+
+    // With no events allowed, integration stops at the first one without switching.
+    let output = hybrid::integrate(
+        &hybrid::Input {
+            max_events: 0,
+            ..input
+        },
+        &CONFIG,
+    )
+    .unwrap();
+    assert_that!(output.event_limit_reached).is_true();
+    assert_that!(output.events.len()).is_equal_to(0);
+    assert_that!(output.mode).is_equal_to(0);
+    assert_that!(output.t).is_close_to(1.0, 1e-12);
+    assert_that!(output.y[0]).is_close_to(1.0, 1e-10);
+}
+
+#[test]
+fn test_invalid_mode() {
+    let f = |_, _: &DVector<f64>| dvector![1.0];
+    let guard = |_, y: &DVector<f64>| y[0] - 1.0;
+    let identity = |_, y: &DVector<f64>| y.clone();
+    let modes = [Mode {
+        f: &f,
+        transitions: vec![Transition {
+            guard: &guard,
+            direction: Direction::Rising,
+            reset: &identity,
+            target: 1,
+        }],
+    }];
+    let y0 = dvector![0.0];
+    let input = |mode0, modes| hybrid::Input {
+        t_span: [0.0, 2.0],
+        y0: &y0,
+        mode0,
+        h0: 0.3,
+        modes,
+        max_events: 10,
+    };
+    let valid = [Mode {
+        f: &f,
+        transitions: vec![],
+    }];
+    let is_mode_error = |result| matches!(result, Err(Error::Input(InputError::Mode)));
+    assert_that!(is_mode_error(hybrid::integrate(&input(0, &modes), &CONFIG))).is_true();
+    assert_that!(is_mode_error(hybrid::integrate(&input(1, &valid), &CONFIG))).is_true();
+    assert_that!(hybrid::integrate(&input(0, &valid), &CONFIG).is_ok()).is_true();
+}
+
+// Stage separation: a time guard drops mass, changing the acceleration under constant thrust.
+#[test]
+fn test_staging() {
+    const THRUST: f64 = 100.0;
+    let thrusting = |_, y: &DVector<f64>| dvector![y[1], THRUST / y[2], 0.0];
+    let separation_time = |t: f64, _: &DVector<f64>| t - 2.0;
+    let separate = |_, y: &DVector<f64>| dvector![y[0], y[1], 0.5 * y[2]];
+    let modes = [
+        Mode {
+            f: &thrusting,
+            transitions: vec![Transition {
+                guard: &separation_time,
+                direction: Direction::Either,
+                reset: &separate,
+                target: 1,
+            }],
+        },
+        Mode {
+            f: &thrusting,
+            transitions: vec![],
+        },
+    ];
+    let mut observed = Vec::new();
+    let output = hybrid::integrate_with_observer(
+        &hybrid::Input {
+            t_span: [0.0, 3.0],
+            y0: &dvector![0.0, 0.0, 10.0],
+            mode0: 0,
+            h0: 0.7,
+            modes: &modes,
+            max_events: 10,
+        },
+        &CONFIG,
+        &mut |t, y| observed.push((t, y[2])),
+    )
+    .unwrap();
+
+    // a = 10 for 2 s, then 20 for 1 s.
+    assert_that!(output.y[1]).is_close_to(40.0, 1e-9);
+    assert_that!(output.y[0]).is_close_to(0.5 * 10.0 * 4.0 + 20.0 + 0.5 * 20.0, 1e-9);
+    assert_that!(output.event_limit_reached).is_false();
+
+    // The observer sees the reset state at the event.
+    let event = observed
+        .iter()
+        .position(|&(t, m)| (t - 2.0).abs() < 1e-12 && (m - 5.0).abs() < 1e-12);
+    assert_that!(event).is_some();
+}
+
+// Two transitions a few ulps apart: the second event is located right at the start of the step
+// after the first, and restarting with only the part of that step used would leave a step size of
+// a few ulps to grow back from.
+#[test]
+fn test_event_at_step_start() {
+    let rising = |_, _: &DVector<f64>| dvector![1.0];
+    let first = |_, y: &DVector<f64>| y[0] - 1.0;
+    let second = |_, y: &DVector<f64>| y[0] - (1.0 + 64.0 * f64::EPSILON);
+    let identity = |_, y: &DVector<f64>| y.clone();
+    let modes = [
+        Mode {
+            f: &rising,
+            transitions: vec![Transition {
+                guard: &first,
+                direction: Direction::Rising,
+                reset: &identity,
+                target: 1,
+            }],
+        },
+        Mode {
+            f: &rising,
+            transitions: vec![Transition {
+                guard: &second,
+                direction: Direction::Rising,
+                reset: &identity,
+                target: 2,
+            }],
+        },
+        Mode {
+            f: &rising,
+            transitions: vec![],
+        },
+    ];
+    let input = hybrid::Input {
+        t_span: [0.0, 2.0],
+        y0: &dvector![0.0],
+        mode0: 0,
+        h0: 0.3,
+        modes: &modes,
+        max_events: 10,
+    };
+    let output = hybrid::integrate(&input, &CONFIG).unwrap();
+
+    assert_that!(output.mode).is_equal_to(2);
+    assert_that!(output.events.len()).is_equal_to(2);
+    assert_that!(output.y[0]).is_close_to(2.0, 1e-12);
+    assert_that!(output.num_steps).is_less_than(10);
+}
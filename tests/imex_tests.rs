@@ -0,0 +1,186 @@
+use nalgebra::{dmatrix, dvector, DVector};
+use speculoos::prelude::*;
+
+use finfoot::ode::dopri5::{self, Config};
+use finfoot::ode::imex;
+use finfoot::ode::jacobian::{Difference, Jacobian};
+use finfoot::ode::seulex;
+use test_util::all_problems;
+
+const FINITE_DIFFERENCE: Jacobian<'static> = Jacobian::FiniteDifference {
+    difference: Difference::Forward,
+    sparsity: None,
+};
+
+fn zero(_: f64, y: &DVector<f64>) -> DVector<f64> {
+    DVector::zeros(y.len())
+}
+
+// Prothero-Robinson: y' = lambda (y - sin t) + cos t has the solution sin t, which the stiff
+// relaxation makes hard for explicit methods.
+#[test]
+fn test_prothero_robinson() {
+    let lambda = -1e6;
+    let explicit = |t: f64, _: &DVector<f64>| dvector![t.cos()];
+    let implicit = |t: f64, y: &DVector<f64>| dvector![lambda * (y[0] - t.sin())];
+    let jacobian = |_, _: &DVector<f64>| dmatrix![lambda];
+    let input = imex::Input {
+        t_span: [0.0, 10.0],
+        y0: &dvector![0.0],
+        h0: 1e-3,
+        explicit: &explicit,
+        implicit: &implicit,
+        jacobian: Jacobian::Analytic(&jacobian),
+    };
+    let config = Config {
+        rel_tol: 1e-6,
+        abs_tol: 1e-6,
+    };
+
+    let mut max_error: f64 = 0.0;
+    let output = imex::integrate_with_observer(&input, &config, &mut |t, y| {
+        max_error = max_error.max((y[0] - t.sin()).abs());
+    })
+    .unwrap();
+    assert_that!(max_error).is_less_than(1e-6);
+    // An explicit method would be held to h ~ 3e-6 by stability.
+    assert_that!(output.num_steps).is_less_than(2000);
+}
+
+// Either part alone reduces to an explicit or a stiffly accurate implicit method.
+#[test]
+fn test_single_part() {
+    let problems = all_problems();
+    let config = Config {
+        rel_tol: 1e-6,
+        abs_tol: 1e-10,
+    };
+
+    let problem = &problems["van_der_pol_oscillator"];
+    let input = imex::Input {
+        t_span: problem.t_span,
+        y0: &problem.y0,
+        h0: 1e-3,
+        explicit: &problem.f,
+        implicit: &zero,
+        jacobian: FINITE_DIFFERENCE,
+    };
+    let output = imex::integrate(&input, &config).unwrap();
+    for i in 0..2 {
+        assert_that!(output.y[i]).is_close_to(problem.yf[i], problem.tolerance[i]);
+    }
+    assert_that!(output.num_newton_failures).is_equal_to(0);
+
+    let problem = &problems["robertson_equations"];
+    let input = imex::Input {
+        t_span: problem.t_span,
+        y0: &problem.y0,
+        h0: 1e-4,
+        explicit: &zero,
+        implicit: &problem.f,
+        jacobian: FINITE_DIFFERENCE,
+    };
+    let output = imex::integrate(&input, &config).unwrap();
+    for i in 0..3 {
+        assert_that!(output.y[i]).is_close_to(problem.yf[i], problem.tolerance[i]);
+    }
+}
+
+const DRAG: f64 = 1e4;
+
+// Circular orbit velocity at r about a unit mass.
+fn circular_velocity(r: &DVector<f64>) -> DVector<f64> {
+    let distance = r.norm();
+    dvector![-r[1], r[0]] / distance.powf(1.5)
+}
+
+fn gravity(_: f64, y: &DVector<f64>) -> DVector<f64> {
+    let r = y.rows(0, 2).into_owned();
+    let acceleration = -&r / r.norm().powi(3);
+    dvector![y[2], y[3], acceleration[0], acceleration[1]]
+}
+
+// Stiff drag towards an atmosphere co-rotating at circular orbit velocity.
+fn drag(_: f64, y: &DVector<f64>) -> DVector<f64> {
+    let r = y.rows(0, 2).into_owned();
+    let relative = y.rows(2, 2) - circular_velocity(&r);
+    dvector![0.0, 0.0, -DRAG * relative[0], -DRAG * relative[1]]
+}
+
+// Only the drag is linearized, and the step is no longer held to the explicit stability limit
+// of about 3 / DRAG.
+#[test]
+fn test_orbit_with_drag() {
+    let y0 = dvector![1.0, 0.0, 0.0, 1.3];
+    let t_span = [0.0, 10.0];
+    let config = Config {
+        rel_tol: 1e-6,
+        abs_tol: 1e-8,
+    };
+    let input = imex::Input {
+        t_span,
+        y0: &y0,
+        h0: 1e-4,
+        explicit: &gravity,
+        implicit: &drag,
+        jacobian: FINITE_DIFFERENCE,
+    };
+    let output = imex::integrate(&input, &config).unwrap();
+
+    let f = |t, y: &DVector<f64>| gravity(t, y) + drag(t, y);
+    let reference = seulex::integrate(
+        &seulex::Input {
+            t_span,
+            y0: &y0,
+            h0: 1e-4,
+            f: &f,
+            jacobian: FINITE_DIFFERENCE,
+        },
+        &Config {
+            rel_tol: 1e-10,
+            abs_tol: 1e-12,
+        },
+    )
+    .unwrap();
+    let explicit = dopri5::integrate(
+        &dopri5::Input {
+            t_span,
+            y0: &y0,
+            h0: 1e-4,
+            f: &f,
+        },
+        &config,
+    )
+    .unwrap();
+    assert_that!((&output.y - &reference.y).amax()).is_less_than(1e-6);
+    assert_that!(output.num_steps).is_less_than(explicit.num_steps / 10);
+}
+
+// Harmonic oscillator with the position and velocity updates in different parts, so only the
+// coupling conditions of the pair give fourth order: steps grow as tol^(-1/4).
+#[test]
+fn test_coupled_order() {
+    let explicit = |_, y: &DVector<f64>| dvector![y[1], 0.0];
+    let implicit = |_, y: &DVector<f64>| dvector![0.0, -4.0 * y[0]];
+    let num_steps = |tol: f64| {
+        let input = imex::Input {
+            t_span: [0.0, 10.0],
+            y0: &dvector![1.0, 0.0],
+            h0: 1e-3,
+            explicit: &explicit,
+            implicit: &implicit,
+            jacobian: FINITE_DIFFERENCE,
+        };
+        let config = Config {
+            rel_tol: tol,
+            abs_tol: tol,
+        };
+        let output = imex::integrate(&input, &config).unwrap();
+        assert_that!((output.y[0] - 20.0_f64.cos()).abs()).is_less_than(1e3 * tol);
+        #[allow(clippy::cast_precision_loss)]
+        let num_steps = output.num_steps as f64;
+        num_steps
+    };
+    let order = 4.0 * 10.0_f64.ln() / (num_steps(1e-10) / num_steps(1e-6)).ln();
+    assert_that!(order).is_close_to(4.0, 0.3);
+}
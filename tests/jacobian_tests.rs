@@ -0,0 +1,185 @@
+use std::cell::Cell;
+
+use nalgebra::{DMatrix, DVector};
+use speculoos::prelude::*;
+
+use finfoot::ode::jacobian::{self, Difference, Dual, Jacobian, Real, Sparsity};
+use test_util::all_problems;
+
+fn assert_matrix_close(a: &DMatrix<f64>, b: &DMatrix<f64>, tolerance: f64, name: &str) {
+    assert_that!(a.shape()).is_equal_to(b.shape());
+    for (i, (a, b)) in a.iter().zip(b.iter()).enumerate() {
+        assert_that!(*a)
+            .named(&format!("{name} element {i}"))
+            .is_close_to(*b, tolerance);
+    }
+}
+
+const N: usize = 100; // Must match the test problem.
+
+// Same system as the `coupled_oscillators` test problem, generic over the scalar type.
+fn coupled_oscillators<T: Real>(_: T, y: &DVector<T>) -> DVector<T> {
+    DVector::from_fn(2 * N, |i, _| {
+        if i < N {
+            y[N + i]
+        } else {
+            let i = i - N;
+            let mut sum = T::from(0.0);
+            for j in 0..N {
+                sum += y[i] - y[j];
+            }
+            -sum
+        }
+    })
+}
+
+#[test]
+fn test_coupled_oscillators() {
+    let problem = &all_problems()["coupled_oscillators"];
+
+    let mut expected = DMatrix::zeros(2 * N, 2 * N);
+    for i in 0..N {
+        expected[(i, N + i)] = 1.0;
+        for j in 0..N {
+            expected[(N + i, j)] = if i == j { -99.0 } else { 1.0 };
+        }
+    }
+
+    let analytic = |_, _: &DVector<f64>| expected.clone();
+    let methods = [
+        ("analytic", Jacobian::Analytic(&analytic), 0.0),
+        (
+            "forward",
+            Jacobian::FiniteDifference {
+                difference: Difference::Forward,
+                sparsity: None,
+            },
+            1e-5,
+        ),
+        (
+            "central",
+            Jacobian::FiniteDifference {
+                difference: Difference::Central,
+                sparsity: None,
+            },
+            1e-8,
+        ),
+        (
+            "colored",
+            Jacobian::FiniteDifference {
+                difference: Difference::Central,
+                sparsity: Some(Sparsity::dense(2 * N)),
+            },
+            1e-8,
+        ),
+        ("dual", Jacobian::Dual(&coupled_oscillators::<Dual>), 0.0),
+    ];
+
+    assert_that!(coupled_oscillators(0.0, &problem.y0)).is_equal_to((problem.f)(0.0, &problem.y0));
+    for (name, method, tolerance) in methods {
+        let jacobian = method.evaluate(&problem.f, 0.0, &problem.y0);
+        assert_matrix_close(&jacobian, &expected, tolerance, name);
+    }
+}
+
+// Nonlinear reaction-diffusion chain with a tridiagonal Jacobian.
+fn chain<T: Real>(_: T, y: &DVector<T>) -> DVector<T> {
+    let n = y.len();
+    DVector::from_fn(n, |i, _| {
+        let left = if i > 0 { y[i - 1] } else { T::from(0.0) };
+        let right = if i + 1 < n { y[i + 1] } else { T::from(0.0) };
+        left - y[i] * 2.0 + right - y[i].powi(3) + y[i].sin()
+    })
+}
+
+fn chain_jacobian(y: &DVector<f64>) -> DMatrix<f64> {
+    let n = y.len();
+    DMatrix::from_fn(n, n, |i, j| {
+        if i == j {
+            -2.0 - 3.0 * y[i].powi(2) + y[i].cos()
+        } else if i.abs_diff(j) == 1 {
+            1.0
+        } else {
+            0.0
+        }
+    })
+}
+
+#[test]
+fn test_banded() {
+    const SIZE: usize = 50;
+    let y = DVector::from_fn(SIZE, |i, _| {
+        (0.3 * f64::from(u32::try_from(i).unwrap())).sin()
+    });
+    let expected = chain_jacobian(&y);
+
+    let sparsity = Sparsity::banded(SIZE, 1, 1);
+    assert_that!(sparsity.shape()).is_equal_to((SIZE, SIZE));
+    assert_that!(sparsity.num_colors()).is_equal_to(3);
+
+    // Column coloring needs one evaluation per color rather than per column.
+    let num_calls = Cell::new(0);
+    let f = |t: f64, y: &DVector<f64>| {
+        num_calls.set(num_calls.get() + 1);
+        chain(t, y)
+    };
+    let f_value = f(0.0, &y);
+    num_calls.set(0);
+    let colored = jacobian::finite_difference(
+        |y| f(0.0, y),
+        &y,
+        &f_value,
+        Difference::Forward,
+        Some(&sparsity),
+    );
+    assert_that!(num_calls.get()).is_equal_to(3);
+    assert_matrix_close(&colored, &expected, 1e-6, "forward colored");
+
+    num_calls.set(0);
+    let central = jacobian::finite_difference(
+        |y| f(0.0, y),
+        &y,
+        &f_value,
+        Difference::Central,
+        Some(&sparsity),
+    );
+    assert_that!(num_calls.get()).is_equal_to(6);
+    assert_matrix_close(&central, &expected, 1e-9, "central colored");
+
+    let dual = jacobian::dual(&chain::<Dual>, 0.0, &y);
+    assert_matrix_close(&dual, &expected, 1e-14, "dual");
+}
+
+#[test]
+fn test_coloring() {
+    // Arrowhead: the dense first row forces every column into its own color.
+    let entries: Vec<_> = (0..5).flat_map(|j| [(0, j), (j, j)]).collect();
+    let sparsity = Sparsity::new(5, 5, &entries);
+    assert_that!(sparsity.num_colors()).is_equal_to(5);
+
+    // Diagonal: one color.
+    let entries: Vec<_> = (0..5).map(|i| (i, i)).collect();
+    let sparsity = Sparsity::new(5, 5, &entries);
+    assert_that!(sparsity.num_colors()).is_equal_to(1);
+
+    let sparsity = Sparsity::banded(10, 2, 1);
+    assert_that!(sparsity.num_colors()).is_equal_to(4);
+    assert_that!(sparsity.colors()[..4].to_vec()).is_equal_to(vec![0, 1, 2, 3]);
+}
+
+#[test]
+fn test_dual_arithmetic() {
+    let x = Dual { re: 0.7, eps: 1.0 };
+    let y = (x.exp().sin() / x + x.powf(1.5) - x.sqrt() * 2.0).ln();
+
+    let value = |x: f64| (x.exp().sin() / x + x.powf(1.5) - x.sqrt() * 2.0).ln();
+    let step = 1e-6;
+    let derivative = (value(0.7 + step) - value(0.7 - step)) / (2.0 * step);
+
+    assert_that!(y.re).is_close_to(value(0.7), 1e-15);
+    assert_that!(y.eps).is_close_to(derivative, 1e-8);
+
+    // A sub-gradient at the kink of abs.
+    assert_that!(Dual { re: 0.0, eps: 1.0 }.abs().eps).is_equal_to(0.0);
+    assert_that!(Dual { re: -0.5, eps: 1.0 }.abs().eps).is_equal_to(-1.0);
+}
@@ -0,0 +1,154 @@
+use nalgebra::{dvector, DVector, Matrix3, UnitQuaternion, Vector3};
+use speculoos::prelude::*;
+
+use finfoot::ode::dopri5::{self, Config};
+use finfoot::ode::lie::{self, State, Tangent};
+
+const CONFIG: Config = Config {
+    rel_tol: 1e-10,
+    abs_tol: 1e-12,
+};
+
+fn inertia() -> Matrix3<f64> {
+    Matrix3::from_diagonal(&Vector3::new(1.0, 2.0, 3.0))
+}
+
+// Torque-free rigid body: Euler's equations for the body rate in x, attitude in q.
+fn rigid_body(_: f64, y: &State) -> Tangent {
+    let inertia = inertia();
+    let omega = Vector3::new(y.x[0], y.x[1], y.x[2]);
+    let omega_dot = inertia.try_inverse().unwrap() * -omega.cross(&(inertia * omega));
+    Tangent {
+        x: DVector::from_column_slice(omega_dot.as_slice()),
+        omega: vec![omega],
+    }
+}
+
+fn initial_state() -> State {
+    State {
+        x: dvector![0.1, 2.0, 0.2],
+        q: vec![UnitQuaternion::from_euler_angles(0.3, -0.2, 1.0)],
+    }
+}
+
+fn angular_momentum(y: &State) -> Vector3<f64> {
+    y.q[0] * (inertia() * Vector3::new(y.x[0], y.x[1], y.x[2]))
+}
+
+#[test]
+fn test_constant_rate() {
+    let omega = Vector3::new(0.3, -1.2, 0.7);
+    let f = |_, _: &State| Tangent {
+        x: DVector::zeros(0),
+        omega: vec![omega],
+    };
+    let y0 = State {
+        x: DVector::zeros(0),
+        q: vec![UnitQuaternion::from_euler_angles(0.1, 0.2, 0.3)],
+    };
+    let input = lie::Input {
+        t_span: [0.0, 10.0],
+        y0: &y0,
+        h: 0.1,
+        f: &f,
+    };
+    let expected = y0.q[0] * UnitQuaternion::from_scaled_axis(omega * 10.0);
+
+    let output = lie::integrate(&input, &CONFIG).unwrap();
+    assert_that!(output.y.q[0].angle_to(&expected)).is_less_than(1e-12);
+    let output = lie::crouch_grossman(&input).unwrap();
+    assert_that!(output.y.q[0].angle_to(&expected)).is_less_than(1e-12);
+}
+
+// The spin about the intermediate axis is unstable, so the attitude tumbles.
+#[test]
+fn test_rigid_body() {
+    let y0 = initial_state();
+    let input = lie::Input {
+        t_span: [0.0, 100.0],
+        y0: &y0,
+        h: 0.01,
+        f: &rigid_body,
+    };
+    let momentum = angular_momentum(&y0);
+
+    let mut max_drift: f64 = 0.0;
+    let mut max_norm_error: f64 = 0.0;
+    let output = lie::integrate_with_observer(&input, &CONFIG, &mut |_, y| {
+        max_drift = max_drift.max((angular_momentum(y) - momentum).norm());
+        max_norm_error = max_norm_error.max((y.q[0].as_ref().norm() - 1.0).abs());
+    })
+    .unwrap();
+    assert_that!(max_drift / momentum.norm()).is_less_than(1e-7);
+    assert_that!(max_norm_error).is_less_than(1e-15);
+    assert_that!(output.num_steps).is_greater_than(100);
+
+    // The same system with the quaternion as four Euclidean elements drifts off unit norm.
+    let f = |t, y: &DVector<f64>| {
+        let q = UnitQuaternion::new_unchecked(nalgebra::Quaternion::new(y[3], y[4], y[5], y[6]));
+        let state = State {
+            x: y.rows(0, 3).into_owned(),
+            q: vec![q],
+        };
+        let derivative = rigid_body(t, &state);
+        let omega = derivative.omega[0];
+        let q_dot = q.into_inner() * nalgebra::Quaternion::from_imag(omega / 2.0);
+        let mut y_dot = DVector::zeros(7);
+        y_dot.rows_mut(0, 3).copy_from(&derivative.x);
+        y_dot[3] = q_dot.w;
+        y_dot.rows_mut(4, 3).copy_from(&q_dot.imag());
+        y_dot
+    };
+    let q0 = y0.q[0].into_inner();
+    let euclidean = dopri5::integrate(
+        &dopri5::Input {
+            t_span: [0.0, 100.0],
+            y0: &dvector![y0.x[0], y0.x[1], y0.x[2], q0.w, q0.i, q0.j, q0.k],
+            h0: 0.01,
+            f: &f,
+        },
+        &Config {
+            rel_tol: 1e-6,
+            abs_tol: 1e-8,
+        },
+    )
+    .unwrap();
+    assert_that!((euclidean.y.rows(3, 4).norm() - 1.0).abs()).is_greater_than(1e-8);
+}
+
+#[test]
+fn test_crouch_grossman_order() {
+    let y0 = initial_state();
+    let t_span = [0.0, 2.0];
+    let reference = lie::integrate(
+        &lie::Input {
+            t_span,
+            y0: &y0,
+            h: 0.01,
+            f: &rigid_body,
+        },
+        &Config {
+            rel_tol: 1e-13,
+            abs_tol: 1e-13,
+        },
+    )
+    .unwrap();
+
+    let errors: Vec<f64> = [0.02, 0.01, 0.005]
+        .iter()
+        .map(|&h| {
+            let output = lie::crouch_grossman(&lie::Input {
+                t_span,
+                y0: &y0,
+                h,
+                f: &rigid_body,
+            })
+            .unwrap();
+            output.y.q[0].angle_to(&reference.y.q[0]) + (&output.y.x - &reference.y.x).norm()
+        })
+        .collect();
+    for pair in errors.windows(2) {
+        let order = (pair[0] / pair[1]).log2();
+        assert_that!(order).is_close_to(3.0, 0.2);
+    }
+}
@@ -2,7 +2,7 @@ use nalgebra::DVector;
 use paste::paste;
 use speculoos::prelude::*;
 
-use finfoot::ode::dopri5;
+use finfoot::ode::{dopri5, Error};
 use test_util::{all_problems, OdeProblem};
 
 fn assert_dvector_close(a: &DVector<f64>, b: &DVector<f64>, tolerance: &DVector<f64>, name: &str) {
@@ -58,3 +58,83 @@ generate_tests! {
     robertson_equations,
     coupled_oscillators,
 }
+
+#[test]
+fn test_dense_output() {
+    const CONFIG: dopri5::Config = dopri5::Config {
+        rel_tol: 1e-8,
+        abs_tol: 1e-10,
+    };
+
+    let problem = &all_problems()["harmonic_oscillator"];
+    let input = dopri5::Input {
+        t_span: problem.t_span,
+        y0: &problem.y0,
+        h0: 0.01,
+        f: &problem.f,
+    };
+
+    let mut steps = Vec::new();
+    dopri5::integrate_with_observer(&input, &CONFIG, &mut |t, y| steps.push((t, y.clone())))
+        .unwrap();
+    let (output, dense) = dopri5::integrate_dense(&input, &CONFIG).unwrap();
+    assert_that!(dense.segments.len()).is_equal_to(output.num_steps);
+
+    // Dense output interpolates the accepted steps exactly.
+    for (t, y) in &steps {
+        assert_dvector_close(
+            &dense.evaluate(*t),
+            y,
+            &DVector::from_element(2, 1e-12),
+            "step",
+        );
+    }
+
+    // And is fourth order accurate between them.
+    let omega = 2.0 * std::f64::consts::PI;
+    for i in 0..=100 {
+        let t = f64::from(i) * 0.0099;
+        let expected = DVector::from_vec(vec![(omega * t).cos(), -omega * (omega * t).sin()]);
+        assert_dvector_close(
+            &dense.evaluate(t),
+            &expected,
+            &DVector::from_element(2, 1e-6),
+            &format!("t = {t}"),
+        );
+    }
+}
+
+// y' = y^2 blows up at t = 1, where the step size underflows rather than the solution
+// overflowing.
+#[test]
+fn test_step_underflow() {
+    let input = dopri5::Input {
+        t_span: [0.0, 2.0],
+        y0: &DVector::from_element(1, 1.0),
+        h0: 0.01,
+        f: &|_, y| y.map(|y| y * y),
+    };
+    let config = dopri5::Config {
+        rel_tol: 1e-6,
+        abs_tol: 1e-6,
+    };
+    let result = dopri5::integrate(&input, &config);
+    assert!(matches!(result, Err(Error::Convergence { t, .. }) if (t - 1.0).abs() < 1e-6));
+}
+
+// A step producing NaN has to be rejected rather than accepted on a NaN error ratio.
+#[test]
+fn test_non_finite_step() {
+    let input = dopri5::Input {
+        t_span: [0.0, 2.0],
+        y0: &DVector::from_element(1, 1.0),
+        h0: 0.01,
+        f: &|t, y| if t < 1.0 { -y } else { y.map(|_| f64::NAN) },
+    };
+    let config = dopri5::Config {
+        rel_tol: 1e-6,
+        abs_tol: 1e-6,
+    };
+    let result = dopri5::integrate(&input, &config);
+    assert!(matches!(result, Err(Error::Convergence { t, .. }) if t <= 1.0));
+}
@@ -0,0 +1,339 @@
+use nalgebra::DVector;
+use speculoos::prelude::*;
+use uom::si::length::meter;
+use uom::si::velocity::meter_per_second;
+
+use finfoot::contig::StateVector;
+use finfoot::ode::dopri5;
+use finfoot::output::csv::CsvWriter;
+use finfoot::output::jsonl::JsonLinesWriter;
+use finfoot::output::{self, Stream, Trajectory};
+use test_util::all_problems;
+
+const CONFIG: dopri5::Config = dopri5::Config {
+    rel_tol: 1e-6,
+    abs_tol: 1e-8,
+};
+
+#[derive(StateVector)]
+struct Oscillator {
+    #[state(unit = meter)]
+    position: f64,
+    #[state(unit = meter_per_second, description = "Rate of position")]
+    velocity: f64,
+}
+
+fn oscillator_input<'a>(
+    y0: &'a DVector<f64>,
+    f: &'a dyn Fn(f64, &DVector<f64>) -> DVector<f64>,
+) -> dopri5::Input<'a> {
+    dopri5::Input {
+        t_span: [0.0, 1.0],
+        y0,
+        h0: 0.01,
+        f,
+    }
+}
+
+#[test]
+fn test_csv_stream() {
+    let problem = &all_problems()["harmonic_oscillator"];
+    let y0 = Oscillator {
+        position: problem.y0[0],
+        velocity: problem.y0[1],
+    };
+    let layout = y0.layout().with_name("oscillator");
+    let elements = output::elements(Some(&layout), layout.len());
+
+    let mut stream = Stream::new(CsvWriter::new(Vec::new(), &elements).unwrap());
+    let y0 = y0.to_vector();
+    let result = dopri5::integrate_with_observer(
+        &oscillator_input(&y0, &problem.f),
+        &CONFIG,
+        &mut |t, y| stream.observe(t, y),
+    )
+    .unwrap();
+    let csv = String::from_utf8(stream.finish().unwrap().into_inner()).unwrap();
+
+    let lines: Vec<_> = csv.lines().collect();
+    assert_that!(lines[0]).is_equal_to("t [s],oscillator.position [m],oscillator.velocity [m/s]");
+    assert_that!(lines[1]).is_equal_to("0,1,0");
+    assert_that!(lines.len()).is_equal_to(result.num_steps + 2);
+
+    let last: Vec<f64> = lines[lines.len() - 1]
+        .split(',')
+        .map(|x| x.parse().unwrap())
+        .collect();
+    assert_that!(last[0]).is_equal_to(1.0);
+    assert_that!(last[1]).is_equal_to(result.y[0]);
+    assert_that!(last[2]).is_equal_to(result.y[1]);
+}
+
+#[test]
+fn test_json_lines() {
+    let problem = &all_problems()["exponential"];
+    let mut trajectory = Trajectory::new();
+    let input = dopri5::Input {
+        t_span: problem.t_span,
+        y0: &problem.y0,
+        h0: 0.1,
+        f: &problem.f,
+    };
+    dopri5::integrate_with_observer(&input, &CONFIG, &mut |t, y| trajectory.observe(t, y)).unwrap();
+
+    let elements = output::elements(None, problem.y0.len());
+    let mut writer = JsonLinesWriter::new(Vec::new(), &elements);
+    trajectory.write(&mut writer).unwrap();
+    let jsonl = String::from_utf8(writer.into_inner()).unwrap();
+
+    let lines: Vec<_> = jsonl.lines().collect();
+    assert_that!(lines.len()).is_equal_to(trajectory.t.len());
+    assert_that!(lines[0]).is_equal_to("{\"t [s]\":0.0,\"y[0]\":1.0}");
+    assert_that!(lines[lines.len() - 1]).starts_with("{\"t [s]\":1.0,\"y[0]\":0.36");
+}
+
+// Names with separators, quotes or line breaks are quoted.
+#[test]
+fn test_csv_escape() {
+    let y0 = Oscillator {
+        position: 0.0,
+        velocity: 0.0,
+    };
+    for (name, header) in [
+        ("a,b", r#""a,b.position [m]""#),
+        ("a\"b", r#""a""b.position [m]""#),
+        ("a\rb", "\"a\rb.position [m]\""),
+    ] {
+        let layout = y0.layout().with_name(name);
+        let elements = output::elements(Some(&layout), layout.len());
+        let csv = CsvWriter::new(Vec::new(), &elements).unwrap().into_inner();
+        let csv = String::from_utf8(csv).unwrap();
+        assert_that!(csv.starts_with(&format!("t [s],{header},"))).is_true();
+    }
+}
+
+// Control characters in names are escaped, keeping each record on one line.
+#[test]
+fn test_json_escape() {
+    let y0 = Oscillator {
+        position: 0.0,
+        velocity: 0.0,
+    };
+    for (name, key) in [
+        ("a\"b\\c", r#""a\"b\\c.position [m]""#),
+        ("a\nb", r#""a\nb.position [m]""#),
+        ("a\tb", r#""a\tb.position [m]""#),
+        ("a\u{1}b", r#""a\u0001b.position [m]""#),
+    ] {
+        let layout = y0.layout().with_name(name);
+        let elements = output::elements(Some(&layout), layout.len());
+        let mut writer = JsonLinesWriter::new(Vec::new(), &elements);
+        output::Writer::write(&mut writer, 0.0, &y0.to_vector()).unwrap();
+        let jsonl = String::from_utf8(writer.into_inner()).unwrap();
+        assert_that!(jsonl.lines().count()).is_equal_to(1);
+        assert_that!(jsonl.contains(&format!(",{key}:0.0"))).is_true();
+    }
+}
+
+#[cfg(feature = "arrow")]
+#[test]
+fn test_arrow_and_parquet() {
+    use arrow_array::cast::AsArray;
+    use arrow_array::types::Float64Type;
+    use finfoot::output::arrow::{IpcWriter, ParquetWriter};
+    use finfoot::output::Writer;
+
+    let problem = &all_problems()["harmonic_oscillator"];
+    let y0 = Oscillator {
+        position: problem.y0[0],
+        velocity: problem.y0[1],
+    };
+    let layout = y0.layout();
+    let elements = output::elements(Some(&layout), layout.len());
+
+    let mut trajectory = Trajectory::new();
+    let y0 = y0.to_vector();
+    dopri5::integrate_with_observer(&oscillator_input(&y0, &problem.f), &CONFIG, &mut |t, y| {
+        trajectory.observe(t, y);
+    })
+    .unwrap();
+
+    let check = |batches: Vec<arrow_array::RecordBatch>| {
+        let schema = batches[0].schema();
+        assert_that!(schema.field(2).name().as_str()).is_equal_to("velocity");
+        assert_that!(schema.field(2).metadata()["unit"].as_str()).is_equal_to("m/s");
+        assert_that!(schema.field(2).metadata()["description"].as_str())
+            .is_equal_to("Rate of position");
+
+        let velocity: Vec<f64> = batches
+            .iter()
+            .flat_map(|batch| {
+                batch
+                    .column(2)
+                    .as_primitive::<Float64Type>()
+                    .values()
+                    .to_vec()
+            })
+            .collect();
+        let expected: Vec<f64> = trajectory.y.iter().map(|y| y[1]).collect();
+        assert_that!(velocity).is_equal_to(expected);
+    };
+
+    // Small batches force several record batches to be streamed, and the row count leaves a
+    // partial batch pending at the end.
+    assert_that!(trajectory.t.len() % 16).is_not_equal_to(0);
+    let read_ipc = |ipc: Vec<u8>| {
+        let reader =
+            arrow_ipc::reader::FileReader::try_new(std::io::Cursor::new(ipc), None).unwrap();
+        assert_that!(reader.num_batches()).is_greater_than(1);
+        check(reader.map(Result::unwrap).collect());
+    };
+
+    // `into_inner` after `finish`, and on its own.
+    let mut writer = IpcWriter::new(Vec::new(), &elements, 16).unwrap();
+    trajectory.write(&mut writer).unwrap();
+    read_ipc(writer.into_inner().unwrap());
+    let mut writer = IpcWriter::new(Vec::new(), &elements, 16).unwrap();
+    for (t, y) in trajectory.t.iter().zip(&trajectory.y) {
+        writer.write(*t, y).unwrap();
+    }
+    read_ipc(writer.into_inner().unwrap());
+
+    let path = std::env::temp_dir().join(format!("finfoot_output_{}.parquet", std::process::id()));
+    let mut writer =
+        ParquetWriter::new(std::fs::File::create(&path).unwrap(), &elements, 16).unwrap();
+    trajectory.write(&mut writer).unwrap();
+    writer.into_inner().unwrap().sync_all().unwrap();
+    let reader = parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder::try_new(
+        std::fs::File::open(&path).unwrap(),
+    )
+    .unwrap()
+    .build()
+    .unwrap();
+    check(reader.map(Result::unwrap).collect());
+    std::fs::remove_file(path).unwrap();
+}
+
+#[cfg(feature = "mcap")]
+#[test]
+fn test_mcap() {
+    use finfoot::output::mcap::{McapLogger, STATE_TOPIC};
+
+    let problem = &all_problems()["harmonic_oscillator"];
+    let y0 = Oscillator {
+        position: problem.y0[0],
+        velocity: problem.y0[1],
+    };
+    let layout = y0.layout().with_name("oscillator");
+    let elements = output::elements(Some(&layout), layout.len());
+
+    let logger = McapLogger::new(std::io::Cursor::new(Vec::new()), &elements, 0.0).unwrap();
+    let mut stream = Stream::new(logger);
+    let energy = stream
+        .get_mut()
+        .add_channel(
+            "/energy",
+            "Energy",
+            r#"{"type":"object","properties":{"energy":{"type":"number"}}}"#,
+        )
+        .unwrap();
+
+    let y0 = y0.to_vector();
+    let result = dopri5::integrate_with_observer(
+        &oscillator_input(&y0, &problem.f),
+        &CONFIG,
+        &mut |t, y| {
+            stream.observe(t, y);
+            let energy_json = format!("{{\"energy\":{}}}", y[0].powi(2) + y[1].powi(2));
+            stream.get_mut().log(energy, t, &energy_json).unwrap();
+        },
+    )
+    .unwrap();
+    let mcap = stream.finish().unwrap().into_inner().into_inner();
+
+    let messages: Vec<_> = mcap::MessageStream::new(&mcap)
+        .unwrap()
+        .map(Result::unwrap)
+        .collect();
+    let states: Vec<_> = messages
+        .iter()
+        .filter(|m| m.channel.topic == STATE_TOPIC)
+        .collect();
+    assert_that!(states.len()).is_equal_to(result.num_steps + 1);
+    assert_that!(messages.len()).is_equal_to(2 * states.len());
+
+    let schema = states[0].channel.schema.as_ref().unwrap();
+    assert_that!(schema.encoding.as_str()).is_equal_to("jsonschema");
+    assert_that!(String::from_utf8(schema.data.to_vec()).unwrap())
+        .contains(r#""velocity":{"type":"number","description":"Rate of position [m/s]"}"#);
+
+    assert_that!(std::str::from_utf8(&states[0].data).unwrap())
+        .is_equal_to(r#"{"t":0.0,"y":{"oscillator":{"position":1.0,"velocity":0.0}}}"#);
+    let last = states.last().unwrap();
+    assert_that!(last.log_time).is_equal_to(1_000_000_000);
+    assert_that!(last.sequence).is_equal_to(u32::try_from(result.num_steps).unwrap());
+}
+
+// A leaf named like the prefix of another element, and an array without its first index, do not
+// nest into JSON, so every element keeps its full name as a key.
+#[cfg(feature = "mcap")]
+#[test]
+fn test_mcap_flat_names() {
+    use finfoot::contig::Element;
+    use finfoot::output::mcap::McapLogger;
+    use finfoot::output::Writer;
+
+    let elements: Vec<_> = ["a.b", "a", "v[1]"]
+        .into_iter()
+        .map(|name| Element {
+            name: name.to_string(),
+            unit: None,
+            description: None,
+        })
+        .collect();
+    let mut logger = McapLogger::new(std::io::Cursor::new(Vec::new()), &elements, 0.0).unwrap();
+    logger
+        .write(0.0, &DVector::from_vec(vec![1.0, 2.0, 3.0]))
+        .unwrap();
+    logger.finish().unwrap();
+    let mcap = logger.into_inner().into_inner();
+
+    let message = mcap::MessageStream::new(&mcap)
+        .unwrap()
+        .next()
+        .unwrap()
+        .unwrap();
+    assert_that!(std::str::from_utf8(&message.data).unwrap())
+        .is_equal_to(r#"{"t":0.0,"y":{"a.b":1.0,"a":2.0,"v[1]":3.0}}"#);
+    let schema = message.channel.schema.as_ref().unwrap();
+    assert_that!(String::from_utf8(schema.data.to_vec()).unwrap()).contains(
+        r#""y":{"type":"object","properties":{"a.b":{"type":"number","description":""},"a":"#,
+    );
+}
+
+// Timestamps count from the start time, so runs starting at negative times keep distinct stamps.
+#[cfg(feature = "mcap")]
+#[test]
+fn test_mcap_start_time() {
+    use finfoot::output::mcap::McapLogger;
+    use finfoot::output::Writer;
+
+    let elements = output::elements(None, 1);
+    let mut logger = McapLogger::new(std::io::Cursor::new(Vec::new()), &elements, -2.0).unwrap();
+    for t in [-2.0, -1.5, 0.0] {
+        logger.write(t, &DVector::from_element(1, t)).unwrap();
+    }
+    assert_that!(matches!(
+        logger.write(-3.0, &DVector::zeros(1)),
+        Err(output::Error::BeforeStart { .. })
+    ))
+    .is_true();
+    logger.finish().unwrap();
+    let mcap = logger.into_inner().into_inner();
+
+    let times: Vec<_> = mcap::MessageStream::new(&mcap)
+        .unwrap()
+        .map(|message| message.unwrap().log_time)
+        .collect();
+    assert_that!(times).is_equal_to(vec![0, 500_000_000, 2_000_000_000]);
+}
@@ -0,0 +1,149 @@
+use nalgebra::{dvector, DMatrix, DVector};
+use speculoos::prelude::*;
+
+use finfoot::ode::dopri5::Config;
+use finfoot::ode::jacobian::{Difference, Jacobian};
+use finfoot::ode::projection::{self, Invariant};
+use test_util::all_problems;
+
+const CONFIG: Config = Config {
+    rel_tol: 1e-6,
+    abs_tol: 1e-8,
+};
+
+#[test]
+fn test_robertson_mass() {
+    let problems = all_problems();
+    let problem = &problems["robertson_equations"];
+    let mass = |_, y: &DVector<f64>| dvector![y.sum() - 1.0];
+    let gradient = |_, y: &DVector<f64>| DMatrix::from_element(1, y.len(), 1.0);
+
+    let output = projection::integrate(
+        &projection::Input {
+            t_span: problem.t_span,
+            y0: &problem.y0,
+            h0: 1e-4,
+            f: &problem.f,
+            invariants: &[Invariant {
+                g: &mass,
+                jacobian: Jacobian::Analytic(&gradient),
+                project: true,
+            }],
+            tolerance: 1e-14,
+        },
+        &CONFIG,
+    )
+    .unwrap();
+
+    assert_that!(output.num_unconverged).is_equal_to(0);
+    assert_that!(output.drift[0].max).is_less_than_or_equal_to(1e-14);
+    assert_that!(output.drift[0].last).is_less_than_or_equal_to(1e-14);
+    for i in 0..3 {
+        assert_that!(output.y[i]).is_close_to(problem.yf[i], problem.tolerance[i]);
+    }
+}
+
+// Kepler problem with eccentricity 0.6 over 20 orbits. Energy is projected, angular momentum only
+// monitored.
+#[test]
+fn test_kepler_energy() {
+    let e: f64 = 0.6;
+    let y0 = dvector![1.0 - e, 0.0, 0.0, ((1.0 + e) / (1.0 - e)).sqrt()];
+    let f = |_, y: &DVector<f64>| {
+        let r3 = (y[0] * y[0] + y[1] * y[1]).powf(1.5);
+        dvector![y[2], y[3], -y[0] / r3, -y[1] / r3]
+    };
+    let hamiltonian = |y: &DVector<f64>| {
+        0.5 * (y[2] * y[2] + y[3] * y[3]) - 1.0 / (y[0] * y[0] + y[1] * y[1]).sqrt()
+    };
+    let energy0 = hamiltonian(&y0);
+    let energy = |_, y: &DVector<f64>| dvector![hamiltonian(y) - energy0];
+    let momentum0 = y0[0] * y0[3] - y0[1] * y0[2];
+    let momentum = |_, y: &DVector<f64>| dvector![y[0] * y[3] - y[1] * y[2] - momentum0];
+
+    let run = |project| {
+        projection::integrate(
+            &projection::Input {
+                t_span: [0.0, 40.0 * std::f64::consts::PI],
+                y0: &y0,
+                h0: 0.01,
+                f: &f,
+                invariants: &[
+                    Invariant {
+                        g: &energy,
+                        jacobian: Jacobian::FiniteDifference {
+                            difference: Difference::Central,
+                            sparsity: None,
+                        },
+                        project,
+                    },
+                    Invariant {
+                        g: &momentum,
+                        jacobian: Jacobian::FiniteDifference {
+                            difference: Difference::Forward,
+                            sparsity: None,
+                        },
+                        project: false,
+                    },
+                ],
+                tolerance: 1e-12,
+            },
+            &CONFIG,
+        )
+        .unwrap()
+    };
+
+    let free = run(false);
+    let projected = run(true);
+
+    assert_that!(free.drift[0].max).is_greater_than(1e-6);
+    assert_that!(free.drift[0].max_step).is_equal_to(free.drift[0].max);
+    assert_that!(projected.num_unconverged).is_equal_to(0);
+    assert_that!(projected.drift[0].max).is_less_than_or_equal_to(1e-12);
+    assert_that!(projected.drift[0].max_step).is_greater_than(projected.drift[0].max);
+    assert_that!(projected.drift[1].max).is_less_than(free.drift[1].max);
+
+    // After 20 orbits the body is back at perihelion.
+    assert_that!((&projected.y - &y0).norm()).is_less_than((&free.y - &y0).norm());
+}
+
+// Attitude kinematics q' = q (0, omega / 2) with the quaternion as four Euclidean elements.
+#[test]
+fn test_quaternion_norm() {
+    let f = |t: f64, q: &DVector<f64>| {
+        let omega = [t.sin(), 0.5, (2.0 * t).cos()];
+        let (w, x, y, z) = (q[0], q[1], q[2], q[3]);
+        0.5 * dvector![
+            -x * omega[0] - y * omega[1] - z * omega[2],
+            w * omega[0] + y * omega[2] - z * omega[1],
+            w * omega[1] + z * omega[0] - x * omega[2],
+            w * omega[2] + x * omega[1] - y * omega[0]
+        ]
+    };
+    let norm = |_, q: &DVector<f64>| dvector![q.norm_squared() - 1.0];
+    let gradient = |_, q: &DVector<f64>| DMatrix::from_row_slice(1, 4, (2.0 * q).as_slice());
+    let y0 = dvector![1.0, 0.0, 0.0, 0.0];
+
+    let mut max_observed: f64 = 0.0;
+    let output = projection::integrate_with_observer(
+        &projection::Input {
+            t_span: [0.0, 100.0],
+            y0: &y0,
+            h0: 0.01,
+            f: &f,
+            invariants: &[Invariant {
+                g: &norm,
+                jacobian: Jacobian::Analytic(&gradient),
+                project: true,
+            }],
+            tolerance: 1e-14,
+        },
+        &CONFIG,
+        &mut |_, q| max_observed = max_observed.max((q.norm_squared() - 1.0).abs()),
+    )
+    .unwrap();
+
+    assert_that!(output.drift[0].max_step).is_greater_than(1e-10);
+    assert_that!(output.drift[0].max).is_less_than_or_equal_to(1e-14);
+    assert_that!(max_observed).is_less_than_or_equal_to(1e-14);
+}
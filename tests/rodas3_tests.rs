@@ -0,0 +1,262 @@
+use nalgebra::{dmatrix, dvector, DMatrix, DVector};
+use speculoos::prelude::*;
+
+use finfoot::ode::dopri5::Config;
+use finfoot::ode::jacobian::{Difference, Jacobian};
+use finfoot::ode::rodas3::{self, MassMatrix};
+use finfoot::ode::Error;
+use test_util::all_problems;
+
+const FINITE_DIFFERENCE: Jacobian<'static> = Jacobian::FiniteDifference {
+    difference: Difference::Forward,
+    sparsity: None,
+};
+
+#[test]
+fn test_robertson_ode() {
+    let problem = &all_problems()["robertson_equations"];
+    let input = rodas3::Input {
+        t_span: problem.t_span,
+        y0: &problem.y0,
+        h0: 1e-4,
+        f: &problem.f,
+        mass: MassMatrix::Identity,
+        jacobian: FINITE_DIFFERENCE,
+    };
+    let config = Config {
+        rel_tol: 1e-6,
+        abs_tol: 1e-10,
+    };
+    let output = rodas3::integrate(&input, &config).unwrap();
+    for i in 0..3 {
+        assert_that!(output.y[i]).is_close_to(problem.yf[i], problem.tolerance[i]);
+    }
+}
+
+// Robertson with conservation of mass as the third equation.
+#[test]
+fn test_robertson_dae() {
+    let problem = &all_problems()["robertson_equations"];
+    let f = |_, y: &DVector<f64>| {
+        let (y1, y2, y3) = (y[0], y[1], y[2]);
+        dvector![
+            -0.04 * y1 + 1e4 * y2 * y3,
+            0.04 * y1 - 1e4 * y2 * y3 - 3e7 * y2.powi(2),
+            y1 + y2 + y3 - 1.0,
+        ]
+    };
+    let jacobian = |_, y: &DVector<f64>| {
+        let (y2, y3) = (y[1], y[2]);
+        dmatrix![
+            -0.04, 1e4 * y3, 1e4 * y2;
+            0.04, -1e4 * y3 - 6e7 * y2, -1e4 * y2;
+            1.0, 1.0, 1.0;
+        ]
+    };
+
+    // Start from an inconsistent y3.
+    let y0 = dvector![1.0, 0.0, 0.5];
+    let mut input = rodas3::Input {
+        t_span: problem.t_span,
+        y0: &y0,
+        h0: 1e-4,
+        f: &f,
+        mass: MassMatrix::Constant(DMatrix::from_diagonal(&dvector![1.0, 1.0, 0.0])),
+        jacobian: Jacobian::Analytic(&jacobian),
+    };
+    let y0 = rodas3::consistent_initial_conditions(&input, 1e-12).unwrap();
+    assert_that!(y0[0]).is_equal_to(1.0);
+    assert_that!(y0[1]).is_equal_to(0.0);
+    assert_that!(y0[2]).is_close_to(0.0, 1e-12);
+
+    input.y0 = &y0;
+    let config = Config {
+        rel_tol: 1e-6,
+        abs_tol: 1e-10,
+    };
+    let mut max_violation: f64 = 0.0;
+    let output = rodas3::integrate_with_observer(&input, &config, &mut |_, y| {
+        max_violation = max_violation.max((y.sum() - 1.0).abs());
+    })
+    .unwrap();
+
+    // The algebraic equation holds at every step since the method is stiffly accurate.
+    assert_that!(max_violation).is_less_than(1e-12);
+    for i in 0..3 {
+        assert_that!(output.y[i]).is_close_to(problem.yf[i], problem.tolerance[i]);
+    }
+}
+
+const G: f64 = 9.81;
+
+// Pendulum of unit length in index-1 form: the tension lambda follows from the second derivative
+// of the length constraint.
+#[allow(clippy::many_single_char_names)]
+fn pendulum(_: f64, y: &DVector<f64>) -> DVector<f64> {
+    let (x, z, u, v, lambda) = (y[0], y[1], y[2], y[3], y[4]);
+    dvector![
+        u,
+        v,
+        -lambda * x,
+        -lambda * z - G,
+        u * u + v * v - lambda * (x * x + z * z) - G * z,
+    ]
+}
+
+#[test]
+fn test_pendulum() {
+    // Released horizontally at rest, with a wrong initial tension.
+    let y0 = dvector![1.0, 0.0, 0.0, 0.0, 3.0];
+    let mut input = rodas3::Input {
+        t_span: [0.0, 2.0],
+        y0: &y0,
+        h0: 1e-3,
+        f: &pendulum,
+        mass: MassMatrix::Constant(DMatrix::from_diagonal(&dvector![1.0, 1.0, 1.0, 1.0, 0.0])),
+        jacobian: FINITE_DIFFERENCE,
+    };
+    let y0 = rodas3::consistent_initial_conditions(&input, 1e-12).unwrap();
+    assert_that!(y0[4]).is_close_to(0.0, 1e-12);
+
+    input.y0 = &y0;
+    let config = Config {
+        rel_tol: 1e-8,
+        abs_tol: 1e-10,
+    };
+    let energy = |y: &DVector<f64>| 0.5 * (y[2] * y[2] + y[3] * y[3]) + G * y[1];
+    let mut max_drift: f64 = 0.0;
+    let mut max_energy_error: f64 = 0.0;
+    let output = rodas3::integrate_with_observer(&input, &config, &mut |_, y| {
+        max_drift = max_drift.max((y[0] * y[0] + y[1] * y[1] - 1.0).abs());
+        max_energy_error = max_energy_error.max(energy(y).abs());
+    })
+    .unwrap();
+
+    assert_that!(max_drift).is_less_than(1e-5);
+    assert_that!(max_energy_error).is_less_than(1e-4);
+
+    // Tension matches the centripetal plus gravity load.
+    let y = &output.y;
+    let expected_tension = y[2] * y[2] + y[3] * y[3] - G * y[1];
+    assert_that!(y[4]).is_close_to(expected_tension, 1e-6);
+}
+
+// (1 + y^2) y' = 1 + y^2 has the solution y = t for any state dependent factor.
+#[test]
+fn test_state_dependent_mass() {
+    let f = |_, y: &DVector<f64>| dvector![1.0 + y[0] * y[0]];
+    let mass = |_, y: &DVector<f64>| dmatrix![1.0 + y[0] * y[0]];
+    let input = rodas3::Input {
+        t_span: [0.0, 3.0],
+        y0: &dvector![0.0],
+        h0: 0.01,
+        f: &f,
+        mass: MassMatrix::StateDependent(&mass),
+        jacobian: FINITE_DIFFERENCE,
+    };
+    let output = rodas3::integrate(
+        &input,
+        &Config {
+            rel_tol: 1e-8,
+            abs_tol: 1e-10,
+        },
+    )
+    .unwrap();
+    assert_that!(output.y[0]).is_close_to(3.0, 1e-6);
+
+    // A mass matrix that is singular at the start leaves y' undetermined.
+    let singular = |_, y: &DVector<f64>| dmatrix![y[0] * y[0]];
+    let result = rodas3::integrate(
+        &rodas3::Input {
+            mass: MassMatrix::StateDependent(&singular),
+            ..input
+        },
+        &Config {
+            rel_tol: 1e-8,
+            abs_tol: 1e-10,
+        },
+    );
+    assert_that!(matches!(result, Err(Error::Singular { t, .. }) if t == 0.0)).is_true();
+}
+
+#[test]
+fn test_order() {
+    // y' = -y + sin(t); the global error follows the tolerance.
+    let f = |t: f64, y: &DVector<f64>| dvector![-y[0] + t.sin()];
+    let exact = |t: f64| 1.5 * (-t).exp() + 0.5 * (t.sin() - t.cos());
+    let error = |rel_tol: f64| {
+        let input = rodas3::Input {
+            t_span: [0.0, 2.0],
+            y0: &dvector![1.0],
+            h0: 1e-3,
+            f: &f,
+            mass: MassMatrix::Identity,
+            jacobian: FINITE_DIFFERENCE,
+        };
+        let output = rodas3::integrate(
+            &input,
+            &Config {
+                rel_tol,
+                abs_tol: rel_tol,
+            },
+        )
+        .unwrap();
+        (output.y[0] - exact(2.0)).abs()
+    };
+    assert_that!(error(1e-6)).is_less_than(1e-5);
+    assert_that!(error(1e-9)).is_less_than(1e-8);
+}
+
+// The algebraic equation does not involve the algebraic variable, so no step size makes the
+// iteration matrix invertible.
+#[test]
+fn test_singular_iteration_matrix() {
+    let f = |_, y: &DVector<f64>| dvector![-y[0], y[0] - 1.0];
+    let y0 = dvector![1.0, 0.0];
+    let input = rodas3::Input {
+        t_span: [0.0, 1.0],
+        y0: &y0,
+        h0: 1e-2,
+        f: &f,
+        mass: MassMatrix::Constant(DMatrix::from_diagonal(&dvector![1.0, 0.0])),
+        jacobian: FINITE_DIFFERENCE,
+    };
+    let config = Config {
+        rel_tol: 1e-6,
+        abs_tol: 1e-10,
+    };
+    let result = rodas3::integrate(&input, &config);
+    assert_that!(matches!(result, Err(Error::Singular { t, .. }) if t == 0.0)).is_true();
+}
+
+// A non-finite derivative in an element other than the first counts as the largest error, so the
+// step is cut back rather than the step size becoming NaN.
+#[test]
+fn test_non_finite_step() {
+    let f = |t: f64, y: &DVector<f64>| dvector![-y[0], if t < 0.5 { -y[1] } else { f64::NAN }];
+    let jacobian = |_, _: &DVector<f64>| -DMatrix::identity(2, 2);
+    let y0 = dvector![1.0, 1.0];
+    let input = rodas3::Input {
+        t_span: [0.0, 1.0],
+        y0: &y0,
+        h0: 1e-2,
+        f: &f,
+        mass: MassMatrix::Identity,
+        jacobian: Jacobian::Analytic(&jacobian),
+    };
+    let config = Config {
+        rel_tol: 1e-6,
+        abs_tol: 1e-10,
+    };
+    let mut all_finite = true;
+    let result = rodas3::integrate_with_observer(&input, &config, &mut |_, y| {
+        all_finite &= y.iter().all(|x| x.is_finite());
+    });
+    assert_that!(all_finite).is_true();
+    // The step shrinks onto the point where f stops being finite.
+    let Err(Error::Convergence { t, .. }) = result else {
+        panic!("expected a convergence error, got {result:?}");
+    };
+    assert_that!(t).is_less_than(0.5);
+    assert_that!(t).is_close_to(0.5, 1e-6);
+}
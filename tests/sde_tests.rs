@@ -0,0 +1,188 @@
+use nalgebra::{dvector, DVector};
+use speculoos::prelude::*;
+
+use finfoot::ode::dopri5::Config;
+use finfoot::ode::rng::Rng;
+use finfoot::ode::sde::{self, Output};
+use finfoot::ode::Error;
+
+// Geometric Brownian motion dX = lambda X dt + sigma X dW, with
+// X(t) = X0 exp((lambda - sigma^2 / 2) t + sigma W(t)).
+const LAMBDA: f64 = 2.0;
+const SIGMA: f64 = 1.0;
+
+fn drift(_: f64, y: &DVector<f64>) -> DVector<f64> {
+    LAMBDA * y
+}
+
+fn diffusion(_: f64, y: &DVector<f64>) -> DVector<f64> {
+    SIGMA * y
+}
+
+fn exact(w: f64) -> f64 {
+    ((LAMBDA - 0.5 * SIGMA * SIGMA) + SIGMA * w).exp()
+}
+
+type Method = fn(&sde::Input<'_>, &mut Rng) -> Result<Output, Error>;
+
+fn run(method: Method, h: f64, rng: &mut Rng) -> Output {
+    let input = sde::Input {
+        t_span: [0.0, 1.0],
+        y0: &dvector![1.0],
+        h,
+        drift: &drift,
+        diffusion: &diffusion,
+    };
+    method(&input, rng).unwrap()
+}
+
+// Least squares slope of log(error) against log(h).
+fn order(steps: &[f64], errors: &[f64]) -> f64 {
+    let x: Vec<f64> = steps.iter().map(|h| h.ln()).collect();
+    let y: Vec<f64> = errors.iter().map(|e| e.ln()).collect();
+    let n = f64::from(u32::try_from(x.len()).unwrap());
+    let (mean_x, mean_y) = (x.iter().sum::<f64>() / n, y.iter().sum::<f64>() / n);
+    let covariance: f64 = x
+        .iter()
+        .zip(&y)
+        .map(|(x, y)| (x - mean_x) * (y - mean_y))
+        .sum();
+    let variance: f64 = x.iter().map(|x| (x - mean_x).powi(2)).sum();
+    covariance / variance
+}
+
+fn strong_order(method: Method) -> f64 {
+    const NUM_PATHS: u32 = 1000;
+    let steps: Vec<f64> = (3..=7).map(|k| 0.5f64.powi(k)).collect();
+    let errors: Vec<f64> = steps
+        .iter()
+        .map(|&h| {
+            let mut rng = Rng::new(1);
+            let total: f64 = (0..NUM_PATHS)
+                .map(|_| {
+                    let output = run(method, h, &mut rng);
+                    (output.y[0] - exact(output.w[0])).abs()
+                })
+                .sum();
+            total / f64::from(NUM_PATHS)
+        })
+        .collect();
+    order(&steps, &errors)
+}
+
+// E[exact(W)] = exp(lambda) is known, so the exact solution on the same path serves as a control
+// variate for E[X] - exp(lambda).
+fn weak_order(method: Method) -> f64 {
+    const NUM_PATHS: u32 = 2000;
+    let steps: Vec<f64> = (4..=7).map(|k| 0.5f64.powi(k)).collect();
+    let errors: Vec<f64> = steps
+        .iter()
+        .map(|&h| {
+            let mut rng = Rng::new(2);
+            let total: f64 = (0..NUM_PATHS)
+                .map(|_| {
+                    let output = run(method, h, &mut rng);
+                    output.y[0] - exact(output.w[0])
+                })
+                .sum();
+            (total / f64::from(NUM_PATHS)).abs()
+        })
+        .collect();
+    order(&steps, &errors)
+}
+
+#[test]
+fn test_euler_maruyama_convergence() {
+    assert_that!(strong_order(sde::euler_maruyama)).is_close_to(0.5, 0.15);
+    assert_that!(weak_order(sde::euler_maruyama)).is_close_to(1.0, 0.25);
+}
+
+#[test]
+fn test_milstein_convergence() {
+    assert_that!(strong_order(sde::milstein)).is_close_to(1.0, 0.15);
+    assert_that!(weak_order(sde::milstein)).is_close_to(1.0, 0.25);
+}
+
+fn run_adaptive(rel_tol: f64, rng: &mut Rng) -> Output {
+    let input = sde::Input {
+        t_span: [0.0, 1.0],
+        y0: &dvector![1.0],
+        h: 0.1,
+        drift: &drift,
+        diffusion: &diffusion,
+    };
+    let config = Config {
+        rel_tol,
+        abs_tol: rel_tol,
+    };
+    sde::milstein_adaptive(&input, &config, rng).unwrap()
+}
+
+#[test]
+fn test_adaptive() {
+    const NUM_PATHS: u32 = 300;
+    let strong_error = |rel_tol: f64| {
+        let mut rng = Rng::new(3);
+        let total: f64 = (0..NUM_PATHS)
+            .map(|_| {
+                let output = run_adaptive(rel_tol, &mut rng);
+                (output.y[0] - exact(output.w[0])).abs()
+            })
+            .sum();
+        total / f64::from(NUM_PATHS)
+    };
+
+    // The O(h) error estimate makes the step, and with it the strong error, scale with the
+    // tolerance.
+    let coarse = strong_error(1e-2);
+    let fine = strong_error(1e-3);
+    assert_that!(fine).is_less_than(coarse / 3.0);
+
+    let output = run_adaptive(1e-3, &mut Rng::new(4));
+    assert_that!(output.num_rejected).is_greater_than(0);
+}
+
+// Step rejections must not bias the sampled Wiener process: W(1) ~ N(0, 1).
+#[test]
+fn test_adaptive_wiener_process() {
+    const NUM_PATHS: u32 = 2000;
+    let mut rng = Rng::new(7);
+    let samples: Vec<f64> = (0..NUM_PATHS)
+        .map(|_| run_adaptive(1e-2, &mut rng).w[0])
+        .collect();
+    let mean = samples.iter().sum::<f64>() / f64::from(NUM_PATHS);
+    let variance = samples.iter().map(|w| w * w).sum::<f64>() / f64::from(NUM_PATHS);
+    assert_that!(mean).is_close_to(0.0, 0.075);
+    assert_that!(variance).is_close_to(1.0, 0.1);
+}
+
+#[test]
+fn test_reproducible() {
+    let a = run(sde::milstein, 0.01, &mut Rng::new(5));
+    let b = run(sde::milstein, 0.01, &mut Rng::new(5));
+    assert_that!(a.y).is_equal_to(b.y);
+
+    let a = run_adaptive(1e-3, &mut Rng::new(6));
+    let b = run_adaptive(1e-3, &mut Rng::new(6));
+    assert_that!(a.y).is_equal_to(b.y);
+    assert_that!(a.num_steps).is_equal_to(b.num_steps);
+}
+
+// A drift that turns NaN fails the step instead of being accepted.
+#[test]
+fn test_adaptive_non_finite() {
+    let drift = |t: f64, y: &DVector<f64>| if t < 0.5 { -y } else { y.map(|_| f64::NAN) };
+    let input = sde::Input {
+        t_span: [0.0, 1.0],
+        y0: &dvector![1.0],
+        h: 0.1,
+        drift: &drift,
+        diffusion: &diffusion,
+    };
+    let config = Config {
+        rel_tol: 1e-3,
+        abs_tol: 1e-3,
+    };
+    let result = sde::milstein_adaptive(&input, &config, &mut Rng::new(8));
+    assert_that!(matches!(result, Err(Error::Convergence { t, .. }) if t < 0.5)).is_true();
+}
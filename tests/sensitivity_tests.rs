@@ -0,0 +1,243 @@
+use nalgebra::{dmatrix, dvector, DMatrix, DVector};
+use paste::paste;
+use speculoos::prelude::*;
+
+use finfoot::ode::jacobian::{Difference, Dual, Jacobian, Real};
+use finfoot::ode::sensitivity;
+use finfoot::ode::{dopri5, DerivativeFunc};
+use test_util::all_problems;
+
+const CONFIG: dopri5::Config = dopri5::Config {
+    rel_tol: 1e-10,
+    abs_tol: 1e-12,
+};
+
+const FINITE_DIFFERENCE: Jacobian<'static> = Jacobian::FiniteDifference {
+    difference: Difference::Forward,
+    sparsity: None,
+};
+
+fn final_state(t_span: [f64; 2], y0: &DVector<f64>, f: &DerivativeFunc<'_>) -> DVector<f64> {
+    let input = dopri5::Input {
+        t_span,
+        y0,
+        h0: (t_span[1] - t_span[0]) / 100.0,
+        f,
+    };
+    dopri5::integrate(&input, &CONFIG).unwrap().y
+}
+
+// Central differences of the final state through the whole integration.
+fn integrated_difference(
+    x: &DVector<f64>,
+    final_state: impl Fn(&DVector<f64>) -> DVector<f64>,
+) -> DMatrix<f64> {
+    const DELTA: f64 = 1e-5;
+    let columns: Vec<_> = (0..x.len())
+        .map(|j| {
+            let mut plus = x.clone();
+            let mut minus = x.clone();
+            plus[j] += DELTA;
+            minus[j] -= DELTA;
+            (final_state(&plus) - final_state(&minus)) / (2.0 * DELTA)
+        })
+        .collect();
+    DMatrix::from_columns(&columns)
+}
+
+fn assert_matrix_close(a: &DMatrix<f64>, b: &DMatrix<f64>, rel_tol: f64, name: &str) {
+    assert_that!(a.shape()).is_equal_to(b.shape());
+    let scale = b.amax().max(1.0);
+    for (i, (a, b)) in a.iter().zip(b.iter()).enumerate() {
+        assert_that!(*a)
+            .named(&format!("{name} element {i}"))
+            .is_close_to(*b, rel_tol * scale);
+    }
+}
+
+fn test_problem(name: &str, error_control: bool) {
+    let problem = &all_problems()[name];
+    let f = |t, y: &DVector<f64>, _: &DVector<f64>| (problem.f)(t, y);
+    let input = sensitivity::Input {
+        t_span: problem.t_span,
+        y0: &problem.y0,
+        p: &DVector::zeros(0),
+        h0: (problem.t_span[1] - problem.t_span[0]) / 100.0,
+        f: &f,
+        jacobian_y: FINITE_DIFFERENCE,
+        jacobian_p: FINITE_DIFFERENCE,
+        error_control,
+    };
+    let output = sensitivity::integrate(&input, &CONFIG).unwrap();
+
+    let expected = integrated_difference(&problem.y0, |y0| {
+        final_state(problem.t_span, y0, &problem.f)
+    });
+    let y = final_state(problem.t_span, &problem.y0, &problem.f);
+    if error_control {
+        for i in 0..y.len() {
+            assert_that!(output.y[i])
+                .named(name)
+                .is_close_to(y[i], 1e-6);
+        }
+    } else {
+        // Sensitivities do not change the steps taken.
+        assert_that!(output.y).is_equal_to(y);
+    }
+    assert_matrix_close(&output.dy_dy0, &expected, 1e-4, name);
+    assert_that!(output.dy_dp.shape()).is_equal_to((problem.y0.len(), 0));
+}
+
+macro_rules! generate_tests {
+    ($($name:ident,)*) => {
+        $(
+            paste! {
+                #[test]
+                fn [<test_ $name>]() {
+                    test_problem(stringify!($name), false);
+                    test_problem(stringify!($name), true);
+                }
+            }
+        )*
+    };
+}
+
+generate_tests! {
+    exponential,
+    harmonic_oscillator,
+    van_der_pol_oscillator,
+    lorentz_attractor,
+}
+
+fn van_der_pol(_: f64, y: &DVector<f64>, p: &DVector<f64>) -> DVector<f64> {
+    dvector![y[1], p[0] * (1.0 - y[0].powi(2)) * y[1] - y[0]]
+}
+
+#[test]
+fn test_parameter_sensitivity() {
+    let y0 = dvector![1.0, 0.0];
+    let p = dvector![2.0];
+    let t_span = [0.0, 5.0];
+
+    // Analytic Jacobians of a parameterized system take z = [y; p].
+    let jacobian_y = |_, z: &DVector<f64>| {
+        dmatrix![
+            0.0, 1.0;
+            -2.0 * z[2] * z[0] * z[1] - 1.0, z[2] * (1.0 - z[0].powi(2));
+        ]
+    };
+    let jacobian_p = |_, z: &DVector<f64>| {
+        dmatrix![
+            0.0;
+            (1.0 - z[0].powi(2)) * z[1];
+        ]
+    };
+
+    let expected_dy_dp = integrated_difference(&p, |p| {
+        let f = |t, y: &DVector<f64>| van_der_pol(t, y, p);
+        final_state(t_span, &y0, &f)
+    });
+    let expected_dy_dy0 = integrated_difference(&y0, |y0| {
+        let f = |t, y: &DVector<f64>| van_der_pol(t, y, &p);
+        final_state(t_span, y0, &f)
+    });
+
+    let analytic = sensitivity::integrate(
+        &sensitivity::Input {
+            t_span,
+            y0: &y0,
+            p: &p,
+            h0: 0.05,
+            f: &van_der_pol,
+            jacobian_y: Jacobian::Analytic(&jacobian_y),
+            jacobian_p: Jacobian::Analytic(&jacobian_p),
+            error_control: true,
+        },
+        &CONFIG,
+    )
+    .unwrap();
+    assert_matrix_close(&analytic.dy_dp, &expected_dy_dp, 1e-5, "analytic dy/dp");
+    assert_matrix_close(&analytic.dy_dy0, &expected_dy_dy0, 1e-5, "analytic dy/dy0");
+
+    let finite_difference = sensitivity::integrate(
+        &sensitivity::Input {
+            t_span,
+            y0: &y0,
+            p: &p,
+            h0: 0.05,
+            f: &van_der_pol,
+            jacobian_y: FINITE_DIFFERENCE,
+            jacobian_p: FINITE_DIFFERENCE,
+            error_control: false,
+        },
+        &CONFIG,
+    )
+    .unwrap();
+    assert_matrix_close(
+        &finite_difference.dy_dp,
+        &expected_dy_dp,
+        1e-4,
+        "finite difference dy/dp",
+    );
+
+    // Controlling the sensitivity error takes at least as many steps.
+    assert_that!(analytic.num_steps).is_greater_than_or_equal_to(finite_difference.num_steps);
+}
+
+// The Van der Pol system on z = [y; p], for dual Jacobians.
+fn van_der_pol_stacked<T: Real>(_: T, z: &DVector<T>) -> DVector<T> {
+    dvector![z[1], z[2] * (T::from(1.0) - z[0].powi(2)) * z[1] - z[0]]
+}
+
+#[test]
+fn test_dual_sensitivity() {
+    let y0 = dvector![1.0, 0.0];
+    let p = dvector![2.0];
+    let t_span = [0.0, 5.0];
+
+    let expected_dy_dp = integrated_difference(&p, |p| {
+        let f = |t, y: &DVector<f64>| van_der_pol(t, y, p);
+        final_state(t_span, &y0, &f)
+    });
+    let expected_dy_dy0 = integrated_difference(&y0, |y0| {
+        let f = |t, y: &DVector<f64>| van_der_pol(t, y, &p);
+        final_state(t_span, y0, &f)
+    });
+
+    let input = |jacobian_y, jacobian_p| sensitivity::Input {
+        t_span,
+        y0: &y0,
+        p: &p,
+        h0: 0.05,
+        f: &van_der_pol,
+        jacobian_y,
+        jacobian_p,
+        error_control: true,
+    };
+    let dual = sensitivity::integrate(
+        &input(
+            Jacobian::Dual(&van_der_pol_stacked::<Dual>),
+            Jacobian::Dual(&van_der_pol_stacked::<Dual>),
+        ),
+        &CONFIG,
+    )
+    .unwrap();
+    assert_matrix_close(&dual.dy_dp, &expected_dy_dp, 1e-5, "dual dy/dp");
+    assert_matrix_close(&dual.dy_dy0, &expected_dy_dy0, 1e-5, "dual dy/dy0");
+
+    // Dual Jacobians are exact, so they agree with finite differences to the latter's accuracy.
+    let finite_difference =
+        sensitivity::integrate(&input(FINITE_DIFFERENCE, FINITE_DIFFERENCE), &CONFIG).unwrap();
+    assert_matrix_close(
+        &dual.dy_dp,
+        &finite_difference.dy_dp,
+        1e-4,
+        "dual and finite difference dy/dp",
+    );
+    assert_matrix_close(
+        &dual.dy_dy0,
+        &finite_difference.dy_dy0,
+        1e-4,
+        "dual and finite difference dy/dy0",
+    );
+}
@@ -0,0 +1,93 @@
+use speculoos::prelude::*;
+use typenum::{U0, U1, U2, U3, U4};
+use uom::si::acceleration::meter_per_second_squared;
+use uom::si::energy::joule;
+use uom::si::f64::{Acceleration, Energy, Force, Jerk, Length, Momentum, Power, Time, Velocity};
+use uom::si::force::newton;
+use uom::si::length::meter;
+use uom::si::momentum::kilogram_meter_per_second;
+use uom::si::power::watt;
+use uom::si::time::second;
+use uom::si::velocity::meter_per_second;
+
+use finfoot::ode::dopri5;
+use finfoot::units::{
+    NthTimeDerivative, NthTimeIntegral, StateElement, TimeDerivative, TimeIntegral, UnitState,
+};
+
+const CONFIG: dopri5::Config = dopri5::Config {
+    rel_tol: 1e-6,
+    abs_tol: 1e-9,
+};
+
+#[test]
+fn test_pack_unpack() {
+    let state = (
+        Length::new::<meter>(1.0),
+        [
+            Velocity::new::<meter_per_second>(2.0),
+            Velocity::new::<meter_per_second>(3.0),
+        ],
+    );
+    assert_that!(<(Length, [Velocity; 2])>::LEN).is_equal_to(3);
+
+    let mut values = [0.0; 3];
+    state.pack(&mut values);
+    assert_that!(values).is_equal_to([1.0, 2.0, 3.0]);
+    assert_that!(<(Length, [Velocity; 2])>::unpack(&values)).is_equal_to(state);
+}
+
+#[test]
+fn test_derivative_types() {
+    fn derivative<S: UnitState>(_: &S) -> Option<S::Derivative> {
+        None
+    }
+
+    let _: Option<(Velocity, Acceleration)> = derivative(&(Length::default(), Velocity::default()));
+    let _: Option<[TimeDerivative<Length>; 3]> = derivative(&[Length::default(); 3]);
+}
+
+#[test]
+fn test_falling_body() {
+    let gravity = Acceleration::new::<meter_per_second_squared>(-9.81);
+    let y0 = (
+        Length::new::<meter>(100.0),
+        Velocity::new::<meter_per_second>(5.0),
+    );
+    let f = |_: Time, (_, velocity): &(Length, Velocity)| (*velocity, gravity);
+
+    let input = dopri5::UnitInput {
+        t_span: [Time::new::<second>(0.0), Time::new::<second>(2.0)],
+        y0: &y0,
+        h0: Time::new::<second>(0.1),
+        f: &f,
+    };
+    let (position, velocity) = dopri5::integrate_units(&input, &CONFIG).unwrap().y;
+
+    let t = 2.0;
+    assert_that!(position.get::<meter>()).is_close_to(100.0 + 5.0 * t - 0.5 * 9.81 * t * t, 1e-6);
+    assert_that!(velocity.get::<meter_per_second>()).is_close_to(5.0 - 9.81 * t, 1e-6);
+}
+
+#[test]
+fn test_time_integral_types() {
+    let impulse: TimeIntegral<Force> = Force::new::<newton>(2.0) * Time::new::<second>(3.0);
+    assert_that!(impulse).is_equal_to(Momentum::new::<kilogram_meter_per_second>(6.0));
+
+    let energy: TimeIntegral<Power> = Power::new::<watt>(2.0) * Time::new::<second>(3.0);
+    assert_that!(energy).is_equal_to(Energy::new::<joule>(6.0));
+
+    let velocity: TimeIntegral<TimeDerivative<Velocity>> = Velocity::new::<meter_per_second>(4.0);
+    assert_that!(velocity.get::<meter_per_second>()).is_equal_to(4.0);
+}
+
+#[test]
+fn test_nth_time_derivative_types() {
+    let _: NthTimeDerivative<Length, U0> = Length::default();
+    let _: NthTimeDerivative<Length, U1> = Velocity::default();
+    let _: NthTimeDerivative<Length, U2> = Acceleration::default();
+    let _: NthTimeDerivative<Length, U3> = Jerk::default();
+
+    let _: NthTimeIntegral<Jerk, U3> = Length::default();
+    let _: NthTimeIntegral<NthTimeDerivative<Energy, U4>, U4> = Energy::default();
+}